@@ -0,0 +1,12 @@
+fn main() {
+  #[cfg(feature = "capnp")]
+  {
+    println!("cargo:rerun-if-changed=schema/btree.capnp");
+    capnpc::CompilerCommand::new()
+      .src_prefix("schema")
+      .file("schema/btree.capnp")
+      .default_parent_module(vec!["storage".to_string(), "capnproto".to_string()])
+      .run()
+      .expect("failed to compile schema/btree.capnp");
+  }
+}
@@ -0,0 +1,28 @@
+//! `btree` を `default-features = false` で依存する `no_std` のクレートです。`cargo build` が成功することで、
+//! `std` フィーチャを無効にした `btree` が `core` と `alloc` のみでビルドできることを確認します。
+//!
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use btree::{BTree, BTreeSet};
+
+/// ツリーの主要な操作を `no_std` の環境から呼び出します。
+///
+pub fn exercise() -> Vec<(u32, u32)> {
+  let mut btree = BTree::<u32, u32, 2>::new();
+  for i in 0..100 {
+    btree.put(i, i * 2);
+  }
+  btree.delete(&0);
+  btree.rebuild_bloom_filter();
+  assert_eq!(Some(&4), btree.get(&2));
+
+  let mut set = BTreeSet::<u32, 2>::new();
+  set.insert(1);
+  let flat = btree.as_flat_map();
+  assert_eq!(btree.size(), flat.len());
+
+  btree.iter().collect()
+}
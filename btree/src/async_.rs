@@ -0,0 +1,101 @@
+use crate::BTree;
+use std::borrow::Borrow;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+
+/// Tokio を使用するアプリケーションから `await` で操作できる B-Tree です。`Clone` は同じツリーを共有する
+/// ハンドルを複製します。
+///
+/// `SharedBTree` と同じ理由で、ツリーは `tokio::sync::RwLock` ではなく `tokio::sync::Mutex` で保護されます。
+/// `BTree` の参照操作はノードの借用フラグや参照回数を更新するため、読み取りロックで複数のタスクから同時に参照
+/// することはできません。
+///
+#[derive(Debug)]
+pub struct AsyncBTree<KEY, VALUE, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  tree: Arc<Mutex<BTree<KEY, VALUE, S>>>,
+}
+
+impl<KEY, VALUE, const S: usize> AsyncBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  pub fn new(tree: BTree<KEY, VALUE, S>) -> Self {
+    AsyncBTree {
+      tree: Arc::new(Mutex::new(tree)),
+    }
+  }
+
+  pub async fn get<Q>(&self, key: &Q) -> Option<VALUE>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.tree.lock().await.get_cloned(key)
+  }
+
+  pub async fn put(&self, key: KEY, value: VALUE) -> Option<VALUE> {
+    self.tree.lock().await.put(key, value)
+  }
+
+  pub async fn delete<Q>(&self, key: &Q) -> Option<VALUE>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.tree.lock().await.delete(key)
+  }
+
+  pub async fn size(&self) -> usize {
+    self.tree.lock().await.size()
+  }
+
+  /// `range` に含まれる Key-Value ペアをキーの昇順に `tx` へ送信し、送信した数を返します。受信側が閉じられた
+  /// 場合はその時点で送信を打ち切ります。
+  ///
+  /// 該当するエントリはロックを保持している間に収集され、ロックを解放してから送信されます。したがって受信側の
+  /// 処理が遅い場合でも、送信を待つ間ツリーのロックを保持し続けることはありません。
+  ///
+  pub async fn scan<R>(&self, range: R, tx: mpsc::Sender<(KEY, VALUE)>) -> usize
+  where
+    R: RangeBounds<KEY>,
+  {
+    let entries = {
+      let tree = self.tree.lock().await;
+      tree
+        .iter()
+        .skip_while(|(key, _)| match range.start_bound() {
+          Bound::Included(start) => key < start,
+          Bound::Excluded(start) => key <= start,
+          Bound::Unbounded => false,
+        })
+        .take_while(|(key, _)| range.contains(key))
+        .collect::<Vec<_>>()
+    };
+    let mut count = 0;
+    for entry in entries {
+      if tx.send(entry).await.is_err() {
+        break;
+      }
+      count += 1;
+    }
+    count
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Clone for AsyncBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn clone(&self) -> Self {
+    AsyncBTree {
+      tree: self.tree.clone(),
+    }
+  }
+}
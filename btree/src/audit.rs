@@ -0,0 +1,59 @@
+use crate::{BTree, Op};
+use std::time::Instant;
+
+/// 監査ログに記録された 1 つの更新操作です。
+///
+#[derive(Debug, Clone)]
+pub struct AuditEntry<KEY, VALUE> {
+  /// 操作が行われた時刻です。
+  pub timestamp: Instant,
+  /// `Op::Put` または `Op::Delete` のいずれかです。
+  pub op: Op<KEY, VALUE>,
+  /// 操作の前にそのキーに格納されていた値です。
+  pub old_value: Option<VALUE>,
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// このツリーに対して行われたすべての `put` と `delete` を古い順に参照します。`retain` や `split_off` など
+  /// それ以外の操作による変更は記録されません。`delete` はキーを借用した型で指定できるため、存在しないキーの
+  /// 削除のようにツリーを変更しなかった操作は記録されません。
+  ///
+  pub fn audit_log(&self) -> &[AuditEntry<KEY, VALUE>] {
+    &self.audit
+  }
+
+  /// 監査ログを空にします。
+  ///
+  pub fn clear_audit_log(&mut self) {
+    self.audit.clear();
+  }
+
+  /// 監査ログに記録されている操作を古い順に `target` に適用し、適用した操作の数を返します。
+  ///
+  pub fn replay_audit_to(&self, target: &mut BTree<KEY, VALUE, S>) -> usize {
+    for entry in self.audit.iter() {
+      match &entry.op {
+        Op::Put(key, value) => {
+          target.put(key.clone(), value.clone());
+        }
+        Op::Delete(key) => {
+          target.delete(key);
+        }
+        Op::Get(_) => (),
+      }
+    }
+    self.audit.len()
+  }
+
+  pub(crate) fn record_audit(&mut self, op: Op<KEY, VALUE>, old_value: Option<VALUE>) {
+    self.audit.push(AuditEntry {
+      timestamp: Instant::now(),
+      op,
+      old_value,
+    });
+  }
+}
@@ -0,0 +1,81 @@
+use crate::BTree;
+
+/// 挿入ごとに単調増加する `u64` のキーを自動的に割り当てる B-Tree です。組み込みデータベースにおける
+/// `SERIAL` や `AUTO_INCREMENT` の主キーのように使用します。
+///
+/// 割り当てたキーは削除されても再利用されません。`insert_at` で明示的に指定したキーが次に割り当てる予定の
+/// キー以上である場合は、以後そのキーの次から割り当てます。
+///
+#[derive(Debug, Clone, Default)]
+pub struct AutoIncrBTree<VALUE, const S: usize>
+where
+  VALUE: Clone,
+{
+  tree: BTree<u64, VALUE, S>,
+  next_key: u64,
+}
+
+impl<VALUE, const S: usize> AutoIncrBTree<VALUE, S>
+where
+  VALUE: Clone,
+{
+  pub fn new() -> Self {
+    AutoIncrBTree {
+      tree: BTree::new(),
+      next_key: 0,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.tree.size()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// 次に割り当てるキーを値に割り当てて挿入し、そのキーを返します。
+  ///
+  /// 割り当てるキーが `u64::MAX` を超える場合は panic します。
+  ///
+  pub fn insert(&mut self, value: VALUE) -> u64 {
+    let key = self.next_key;
+    self.next_key = key.checked_add(1).expect("auto-increment keys exhausted");
+    self.tree.put(key, value);
+    key
+  }
+
+  /// 指定されたキーに値を挿入し、既に値が存在していた場合はその値を返します。`key` が次に割り当てる予定の
+  /// キー以上である場合、次に割り当てるキーを `key + 1` に進めます。
+  ///
+  pub fn insert_at(&mut self, key: u64, value: VALUE) -> Option<VALUE> {
+    if key >= self.next_key {
+      self.next_key = key.saturating_add(1);
+    }
+    self.tree.put(key, value)
+  }
+
+  pub fn get(&self, key: u64) -> Option<&VALUE> {
+    self.tree.get(&key)
+  }
+
+  pub fn delete(&mut self, key: u64) -> Option<VALUE> {
+    self.tree.delete(&key)
+  }
+
+  /// 次の `insert` で割り当てられるキーを参照します。
+  ///
+  pub fn peek_next_key(&self) -> u64 {
+    self.next_key
+  }
+
+  /// すべての Key-Value をキーの昇順、すなわち割り当てられた順に列挙します。
+  ///
+  pub fn iter(&self) -> impl Iterator<Item = (u64, VALUE)> + '_ {
+    self.tree.iter()
+  }
+
+  pub fn into_btree(self) -> BTree<u64, VALUE, S> {
+    self.tree
+  }
+}
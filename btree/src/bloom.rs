@@ -0,0 +1,154 @@
+use crate::{BTree, Fnv1a};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+
+/// キー 1 つあたりのビット数です。
+///
+const BITS_PER_KEY: usize = 8;
+
+/// 1 つのキーに対して立てるビットの数です。
+///
+const HASH_FUNCTIONS: u64 = 3;
+
+/// `BTree::bloom_stats` で参照する Bloom フィルタの状態です。
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomStats {
+  /// フィルタのビットのうち立っているものの割合です。
+  pub fill_rate: f64,
+  /// 存在しないキーをフィルタが「存在する可能性がある」と判定する確率の推定値で、`fill_rate` の
+  /// ハッシュ関数の数の累乗です。
+  pub estimated_false_positive_rate: f64,
+}
+
+/// ツリーに挿入されたキーを記録する Bloom フィルタです。キーのハッシュ関数は `rebuild_bloom_filter` で
+/// フィルタを構築したときに `KEY: Hash` の実装から取得して保持するため、`put` などの操作は `KEY: Hash` を
+/// 必要としません。`get_filtered` では借用した形式のキーを同じ FNV-1a でハッシュして判定します。
+///
+#[derive(Debug, Clone)]
+pub(crate) struct BloomFilter<KEY> {
+  bits: Vec<u64>,
+  hash: fn(&KEY) -> u64,
+}
+
+impl<KEY> BloomFilter<KEY> {
+  fn new(capacity: usize, hash: fn(&KEY) -> u64) -> Self {
+    let words = (capacity.max(1) * BITS_PER_KEY).div_ceil(64);
+    BloomFilter {
+      bits: vec![0; words],
+      hash,
+    }
+  }
+
+  pub(crate) fn insert(&mut self, key: &KEY) {
+    for i in self.positions((self.hash)(key)) {
+      self.bits[i / 64] |= 1 << (i % 64);
+    }
+  }
+
+  /// キーがフィルタに記録されている可能性がある場合に true を返します。借用した形式のキーは `Borrow` の規約
+  /// により元のキーと同じハッシュ値を持つため、`KEY` と同じく FNV-1a でハッシュして判定します。
+  ///
+  pub(crate) fn may_contain<Q: Hash + ?Sized>(&self, key: &Q) -> bool {
+    let hash = hash_key(key);
+    self
+      .positions(hash)
+      .all(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+  }
+
+  /// キーのハッシュ値から二重ハッシュ法で `HASH_FUNCTIONS` 個のビットの位置を求めます。
+  ///
+  fn positions(&self, hash: u64) -> impl Iterator<Item = usize> {
+    let hash = mix(hash);
+    let (h1, h2) = (hash & 0xFFFF_FFFF, (hash >> 32) | 1);
+    let len = self.bits.len() as u64 * 64;
+    (0..HASH_FUNCTIONS).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+  }
+}
+
+/// FNV-1a のハッシュ値の上位ビットにも下位ビットの変化が行き渡るように攪拌します (SplitMix64 の最終処理)。
+///
+fn mix(mut x: u64) -> u64 {
+  x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+  x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+  x ^ (x >> 31)
+}
+
+fn hash_key<KEY: Hash + ?Sized>(key: &KEY) -> u64 {
+  let mut hasher = Fnv1a::new();
+  key.hash(&mut hasher);
+  hasher.finish()
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// 現在のすべてのキーから Bloom フィルタを構築し直します。フィルタが有効でなかった場合はこの呼び出しで有効
+  /// となり、以後 `put` で挿入したキーがフィルタに記録されます。フィルタは現在のエントリ数に対してキー 1 つ
+  /// あたり 8 ビットの大きさで構築され、3 つのハッシュ関数を使用します。
+  ///
+  /// `delete` ではフィルタからキーを取り除くことができないため、削除やフィルタ構築後の挿入が多くなると誤検出
+  /// の割合が高くなります。`bloom_stats` の推定値などをもとに定期的に構築し直してください。
+  ///
+  pub fn rebuild_bloom_filter(&mut self)
+  where
+    KEY: Hash,
+  {
+    self.bloom = Some(BloomFilter::new(self.size(), hash_key::<KEY>));
+    self.refill_bloom_filter();
+  }
+
+  /// Bloom フィルタの状態を返します。フィルタが有効でない場合は、ビットが 1 つも立っていない状態として
+  /// `fill_rate` と `estimated_false_positive_rate` をいずれも 0 で返します。
+  ///
+  pub fn bloom_stats(&self) -> BloomStats {
+    let Some(bloom) = self.bloom.as_ref() else {
+      return BloomStats {
+        fill_rate: 0.0,
+        estimated_false_positive_rate: 0.0,
+      };
+    };
+    let set = bloom
+      .bits
+      .iter()
+      .map(|w| w.count_ones() as usize)
+      .sum::<usize>();
+    let fill_rate = set as f64 / (bloom.bits.len() * 64) as f64;
+    let estimated_false_positive_rate = (0..HASH_FUNCTIONS).fold(1.0, |p, _| p * fill_rate);
+    BloomStats {
+      fill_rate,
+      estimated_false_positive_rate,
+    }
+  }
+
+  /// 指定されたキーに関連付けられた値への参照を返します。Bloom フィルタが有効で、キーが確実に存在しないと
+  /// 判定された場合はツリーを探索せずに None を返します。フィルタが有効でない場合は `get` と同じです。
+  ///
+  /// フィルタの判定にはキーのハッシュ値が必要です。`bloom` フィーチャの有無で `get` の境界が変わらないよう、
+  /// `Q: Hash` を要求する判定は `get` とは別のこのメソッドで行います。
+  ///
+  pub fn get_filtered<Q>(&self, key: &Q) -> Option<&VALUE>
+  where
+    KEY: core::borrow::Borrow<Q>,
+    Q: Ord + Hash + ?Sized,
+  {
+    match &self.bloom {
+      Some(bloom) if !bloom.may_contain(key) => None,
+      _ => self.get(key),
+    }
+  }
+
+  /// `put` を経由せずにキーが追加された場合に、有効な Bloom フィルタへすべてのキーを記録し直します。
+  ///
+  pub(crate) fn refill_bloom_filter(&mut self) {
+    if let Some(bloom) = &mut self.bloom {
+      self.root.borrow().for_each(&mut |kv| {
+        bloom.insert(&kv.key);
+        true
+      });
+    }
+  }
+}
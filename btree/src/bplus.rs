@@ -0,0 +1,525 @@
+use alloc::rc::{Rc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow;
+use core::cell::RefCell;
+use core::fmt::{self, Debug};
+use core::ops::{Bound, RangeBounds};
+
+/// すべての値を葉にだけ格納する B+ Tree です。
+///
+/// `BTree` の内部ノードが Key-Value ペアを持つのに対して、B+ Tree の内部ノードは経路を選択するための分離キー
+/// (葉にあるキーの複製) と子ノードだけを持ちます。葉の分割では中央のキーを親に移動せず複製するため、すべての
+/// キーは葉に残ります。内部ノードは最大 `2S` 個の分離キーと `2S + 1` 個の子ノードを持ち、ルート以外のノードは
+/// `S` 個以上のキーを持ちます。
+///
+/// 葉は双方向の連結リストで結ばれており、範囲の走査で隣の葉に移る操作は親ノードを辿らずに O(1) で行われます。
+/// このため連続したキーを順に読み出す用途ではデータベースの索引として `BTree` よりも有利です。
+///
+pub struct BPlusTree<KEY, VALUE, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  root: Node<KEY, VALUE, S>,
+  len: usize,
+}
+
+impl<KEY, VALUE, const S: usize> BPlusTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  pub fn new() -> Self {
+    BPlusTree {
+      root: Node::Leaf(Rc::new(RefCell::new(Leaf::new()))),
+      len: 0,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// この B+ Tree の葉までの深さを参照します。この機能は葉を 1 と数えます。
+  ///
+  pub fn level(&self) -> usize {
+    let mut level = 1;
+    let mut node = &self.root;
+    while let Node::Internal { children, .. } = node {
+      level += 1;
+      node = &children[0];
+    }
+    level
+  }
+
+  /// 指定されたキーに関連付けられた値を返します。値が存在しない場合は None を返します。
+  ///
+  pub fn get<Q>(&self, key: &Q) -> Option<VALUE>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let leaf = self.root.find_leaf(Bound::Included(key));
+    let leaf = leaf.borrow();
+    leaf.find_index(key).ok().map(|i| leaf.values[i].clone())
+  }
+
+  pub fn contains_key<Q>(&self, key: &Q) -> bool
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.get(key).is_some()
+  }
+
+  /// ツリーに Key-Value ペアを挿入します。既に同じキーが存在する場合は新しい値で置き換えて古い値を返します。
+  ///
+  pub fn put(&mut self, key: KEY, value: VALUE) -> Option<VALUE> {
+    let (prop, old_value) = self.root.upsert(key, value);
+    if let Some((separator, right)) = prop {
+      let left = core::mem::replace(
+        &mut self.root,
+        Node::Leaf(Rc::new(RefCell::new(Leaf::new()))),
+      );
+      self.root = Node::Internal {
+        keys: vec![separator],
+        children: vec![left, right],
+      };
+    }
+    if old_value.is_none() {
+      self.len += 1;
+    }
+    old_value
+  }
+
+  pub fn delete<Q>(&mut self, key: &Q) -> Option<VALUE>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let old_value = self.root.delete(key);
+    if let Node::Internal { children, .. } = &mut self.root {
+      if children.len() == 1 {
+        self.root = children.pop().unwrap();
+      }
+    }
+    if old_value.is_some() {
+      self.len -= 1;
+    }
+    old_value
+  }
+
+  /// すべての Key-Value ペアをキーの昇順で列挙します。葉の連結リストを辿るため、逆順の列挙も行えます。
+  ///
+  pub fn iter(&self) -> Iter<'_, KEY, VALUE, S> {
+    Iter {
+      front: Some((self.root.edge_leaf(true), 0)),
+      back: Some((self.root.edge_leaf(false), 0)),
+      remaining: self.len,
+      _tree: self,
+    }
+  }
+
+  /// 指定された範囲に含まれるキーを持つ Key-Value ペアをキーの昇順で列挙します。範囲の下限を含む葉までは
+  /// ルートから O(log n) で辿り、以降は葉の連結リストに沿って走査します。
+  ///
+  pub fn range<R: RangeBounds<KEY>>(&self, range: R) -> impl Iterator<Item = (KEY, VALUE)> + '_ {
+    let start = range.start_bound();
+    let leaf = self.root.find_leaf(start);
+    let i = match start {
+      Bound::Included(key) => leaf.borrow().keys.partition_point(|k| k < key),
+      Bound::Excluded(key) => leaf.borrow().keys.partition_point(|k| k <= key),
+      Bound::Unbounded => 0,
+    };
+    let end = range.end_bound().cloned();
+    Iter {
+      front: Some((leaf, i)),
+      back: None,
+      remaining: usize::MAX,
+      _tree: self,
+    }
+    .take_while(move |(key, _)| match &end {
+      Bound::Included(end) => key <= end,
+      Bound::Excluded(end) => key < end,
+      Bound::Unbounded => true,
+    })
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Default for BPlusTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Debug for BPlusTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Debug,
+  VALUE: Clone + Debug,
+{
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_map().entries(self.iter()).finish()
+  }
+}
+
+impl<KEY, VALUE, const S: usize> FromIterator<(KEY, VALUE)> for BPlusTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn from_iter<I: IntoIterator<Item = (KEY, VALUE)>>(iter: I) -> Self {
+    let mut tree = BPlusTree::new();
+    for (key, value) in iter {
+      tree.put(key, value);
+    }
+    tree
+  }
+}
+
+/// `BPlusTree` の Key-Value ペアをキーの順に列挙するイテレータです。前後それぞれの位置を葉とその中のインデックス
+/// で保持し、葉の終端に達すると連結リストで隣の葉に移ります。
+///
+pub struct Iter<'a, KEY, VALUE, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  front: Option<(LeafRef<KEY, VALUE>, usize)>,
+  /// 後方の位置です。インデックスは葉の末尾から数えた、既に列挙したキーの数を表します。
+  back: Option<(LeafRef<KEY, VALUE>, usize)>,
+  remaining: usize,
+  _tree: &'a BPlusTree<KEY, VALUE, S>,
+}
+
+impl<KEY, VALUE, const S: usize> Iterator for Iter<'_, KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  type Item = (KEY, VALUE);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.remaining == 0 {
+      return None;
+    }
+    loop {
+      let (leaf, i) = self.front.as_mut()?;
+      let next = {
+        let node = leaf.borrow();
+        if *i < node.keys.len() {
+          *i += 1;
+          self.remaining -= 1;
+          return Some((node.keys[*i - 1].clone(), node.values[*i - 1].clone()));
+        }
+        node.next.upgrade()
+      };
+      self.front = next.map(|leaf| (leaf, 0));
+    }
+  }
+}
+
+impl<KEY, VALUE, const S: usize> DoubleEndedIterator for Iter<'_, KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn next_back(&mut self) -> Option<Self::Item> {
+    if self.remaining == 0 {
+      return None;
+    }
+    loop {
+      let (leaf, i) = self.back.as_mut()?;
+      let prev = {
+        let node = leaf.borrow();
+        if *i < node.keys.len() {
+          *i += 1;
+          self.remaining -= 1;
+          let j = node.keys.len() - *i;
+          return Some((node.keys[j].clone(), node.values[j].clone()));
+        }
+        node.prev.upgrade()
+      };
+      self.back = prev.map(|leaf| (leaf, 0));
+    }
+  }
+}
+
+/// B+ Tree のノードです。内部ノードは分離キーと子ノードだけを持ち、Key-Value ペアは葉にだけ格納されます。
+/// `children[i]` のキーはすべて `keys[i]` より小さく、`children[i + 1]` のキーはすべて `keys[i]` 以上です。
+///
+enum Node<KEY, VALUE, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  Internal {
+    keys: Vec<KEY>,
+    children: Vec<Node<KEY, VALUE, S>>,
+  },
+  Leaf(LeafRef<KEY, VALUE>),
+}
+
+impl<KEY, VALUE, const S: usize> Node<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn key_count(&self) -> usize {
+    match self {
+      Node::Internal { keys, .. } => keys.len(),
+      Node::Leaf(leaf) => leaf.borrow().keys.len(),
+    }
+  }
+
+  /// 指定された下限を含む可能性のある葉を返します。
+  ///
+  fn find_leaf<Q>(&self, start: Bound<&Q>) -> LeafRef<KEY, VALUE>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    match self {
+      Node::Internal { keys, children } => {
+        let i = match start {
+          Bound::Included(key) | Bound::Excluded(key) => {
+            keys.partition_point(|k| borrow::Borrow::borrow(k) <= key)
+          }
+          Bound::Unbounded => 0,
+        };
+        children[i].find_leaf(start)
+      }
+      Node::Leaf(leaf) => leaf.clone(),
+    }
+  }
+
+  /// 最も左 (`leftmost` が false の場合は最も右) の葉を返します。
+  ///
+  fn edge_leaf(&self, leftmost: bool) -> LeafRef<KEY, VALUE> {
+    match self {
+      Node::Internal { children, .. } => {
+        let i = if leftmost { 0 } else { children.len() - 1 };
+        children[i].edge_leaf(leftmost)
+      }
+      Node::Leaf(leaf) => leaf.clone(),
+    }
+  }
+
+  /// このノードを根とする部分木に Key-Value ペアを挿入します。キー数が `2S` を超えて分割が発生した場合は、
+  /// 親に挿入する分離キーと新しく生成された右側のノードを返します。
+  ///
+  fn upsert(&mut self, key: KEY, value: VALUE) -> (SplitPropagation<KEY, VALUE, S>, Option<VALUE>) {
+    match self {
+      Node::Internal { keys, children } => {
+        let i = keys.partition_point(|k| *k <= key);
+        let (prop, old_value) = children[i].upsert(key, value);
+        if let Some((separator, right)) = prop {
+          keys.insert(i, separator);
+          children.insert(i + 1, right);
+          if keys.len() == 2 * S + 1 {
+            // 内部ノードの分割では中央の分離キーを複製せずに親へ移動する
+            let right_keys = keys.split_off(S + 1);
+            let separator = keys.pop().unwrap();
+            let right_children = children.split_off(S + 1);
+            let right = Node::Internal {
+              keys: right_keys,
+              children: right_children,
+            };
+            return (Some((separator, right)), old_value);
+          }
+        }
+        (None, old_value)
+      }
+      Node::Leaf(leaf) => {
+        let mut node = leaf.borrow_mut();
+        match node.find_index(&key) {
+          Ok(i) => {
+            let old_value = core::mem::replace(&mut node.values[i], value);
+            (None, Some(old_value))
+          }
+          Err(i) => {
+            node.keys.insert(i, key);
+            node.values.insert(i, value);
+            if node.keys.len() < 2 * S + 1 {
+              return (None, None);
+            }
+            // 葉の分割では右側の葉の先頭のキーを複製して親の分離キーとする
+            let right = Rc::new(RefCell::new(Leaf {
+              keys: node.keys.split_off(S),
+              values: node.values.split_off(S),
+              prev: Rc::downgrade(leaf),
+              next: node.next.clone(),
+            }));
+            if let Some(next) = node.next.upgrade() {
+              next.borrow_mut().prev = Rc::downgrade(&right);
+            }
+            node.next = Rc::downgrade(&right);
+            let separator = right.borrow().keys[0].clone();
+            (Some((separator, Node::Leaf(right))), None)
+          }
+        }
+      }
+    }
+  }
+
+  fn delete<Q>(&mut self, key: &Q) -> Option<VALUE>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    match self {
+      Node::Internal { keys, children } => {
+        let i = keys.partition_point(|k| borrow::Borrow::borrow(k) <= key);
+        let old_value = children[i].delete(key);
+        if old_value.is_some() && children[i].key_count() < S {
+          Self::rebalance(keys, children, i);
+        }
+        old_value
+      }
+      Node::Leaf(leaf) => {
+        let mut node = leaf.borrow_mut();
+        let i = node.find_index(key).ok()?;
+        node.keys.remove(i);
+        Some(node.values.remove(i))
+      }
+    }
+  }
+
+  /// キー数が `S` を下回った子ノード `children[i]` に隣接するノードからキーを再配分するか、隣接するノードと
+  /// マージします。
+  ///
+  fn rebalance(keys: &mut Vec<KEY>, children: &mut Vec<Node<KEY, VALUE, S>>, i: usize) {
+    if i + 1 < children.len() && children[i + 1].key_count() > S {
+      // 右ノードのキーを再配分
+      let (left, right) = children.split_at_mut(i + 1);
+      keys[i] = match (&mut left[i], &mut right[0]) {
+        (Node::Leaf(left), Node::Leaf(right)) => {
+          let (mut left, mut right) = (left.borrow_mut(), right.borrow_mut());
+          let key = right.keys.remove(0);
+          let value = right.values.remove(0);
+          left.keys.push(key);
+          left.values.push(value);
+          right.keys[0].clone()
+        }
+        (
+          Node::Internal {
+            keys: lk,
+            children: lc,
+          },
+          Node::Internal {
+            keys: rk,
+            children: rc,
+          },
+        ) => {
+          lk.push(core::mem::replace(&mut keys[i], rk.remove(0)));
+          lc.push(rc.remove(0));
+          return;
+        }
+        _ => unreachable!("siblings must be at the same depth"),
+      };
+    } else if i != 0 && children[i - 1].key_count() > S {
+      // 左ノードのキーを再配分
+      let (left, right) = children.split_at_mut(i);
+      keys[i - 1] = match (&mut left[i - 1], &mut right[0]) {
+        (Node::Leaf(left), Node::Leaf(right)) => {
+          let (mut left, mut right) = (left.borrow_mut(), right.borrow_mut());
+          let key = left.keys.pop().unwrap();
+          let value = left.values.pop().unwrap();
+          right.keys.insert(0, key);
+          right.values.insert(0, value);
+          right.keys[0].clone()
+        }
+        (
+          Node::Internal {
+            keys: lk,
+            children: lc,
+          },
+          Node::Internal {
+            keys: rk,
+            children: rc,
+          },
+        ) => {
+          rk.insert(0, core::mem::replace(&mut keys[i - 1], lk.pop().unwrap()));
+          rc.insert(0, lc.pop().unwrap());
+          return;
+        }
+        _ => unreachable!("siblings must be at the same depth"),
+      };
+    } else {
+      // 右ノード (右端の場合は左ノード) とマージ
+      let i = if i + 1 < children.len() { i } else { i - 1 };
+      let separator = keys.remove(i);
+      let right = children.remove(i + 1);
+      match (&mut children[i], right) {
+        (Node::Leaf(left), Node::Leaf(right)) => {
+          let mut right = right.borrow_mut();
+          let mut node = left.borrow_mut();
+          node.keys.append(&mut right.keys);
+          node.values.append(&mut right.values);
+          node.next = right.next.clone();
+          if let Some(next) = right.next.upgrade() {
+            next.borrow_mut().prev = Rc::downgrade(left);
+          }
+        }
+        (
+          Node::Internal {
+            keys: lk,
+            children: lc,
+          },
+          Node::Internal {
+            keys: mut rk,
+            children: mut rc,
+          },
+        ) => {
+          lk.push(separator);
+          lk.append(&mut rk);
+          lc.append(&mut rc);
+        }
+        _ => unreachable!("siblings must be at the same depth"),
+      }
+    }
+  }
+}
+
+/// B+ Tree の葉です。隣接する葉への参照は所有権を持たない `Weak` で保持し、葉の所有者は親ノードだけとなります。
+///
+struct Leaf<KEY, VALUE> {
+  keys: Vec<KEY>,
+  values: Vec<VALUE>,
+  prev: Weak<RefCell<Leaf<KEY, VALUE>>>,
+  next: Weak<RefCell<Leaf<KEY, VALUE>>>,
+}
+
+impl<KEY: Ord, VALUE> Leaf<KEY, VALUE> {
+  fn new() -> Self {
+    Leaf {
+      keys: Vec::new(),
+      values: Vec::new(),
+      prev: Weak::new(),
+      next: Weak::new(),
+    }
+  }
+
+  #[inline]
+  fn find_index<Q>(&self, key: &Q) -> core::result::Result<usize, usize>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self
+      .keys
+      .binary_search_by(|k| borrow::Borrow::borrow(k).cmp(key))
+  }
+}
+
+type SplitPropagation<KEY, VALUE, const S: usize> = Option<(KEY, Node<KEY, VALUE, S>)>;
+
+type LeafRef<KEY, VALUE> = Rc<RefCell<Leaf<KEY, VALUE>>>;
@@ -0,0 +1,131 @@
+use crate::BTree;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+
+/// `MutationEvent` が表す変更の種類です。
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MutationKind {
+  /// 存在しなかったキーにエントリが挿入されました。
+  Inserted,
+  /// 既存のエントリの値が置き換えられました。
+  Updated,
+  /// エントリが削除されました。
+  Deleted,
+}
+
+/// `CdcBTree` に対する 1 つの変更を表すイベントです。`old_value` は変更前の値で `Inserted` の場合に、
+/// `new_value` は変更後の値で `Deleted` の場合に `None` となります。
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MutationEvent<KEY, VALUE> {
+  pub kind: MutationKind,
+  pub key: KEY,
+  pub old_value: Option<VALUE>,
+  pub new_value: Option<VALUE>,
+}
+
+/// すべての変更を `MutationEvent` として記録する B-Tree です。`BTree::with_cdc` で構築します。
+///
+/// イベントは `drain_events` で取り出されるまで内部のバッファに変更された順に蓄積されます。トリガーやキャッシュ
+/// の無効化、レプリケーションのように、ツリーの変更を他のシステムへ伝える処理の基盤として使用します。存在しない
+/// キーの削除のようにツリーを変更しない操作はイベントを生成しません。
+///
+#[derive(Debug, Clone, Default)]
+pub struct CdcBTree<KEY, VALUE, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  tree: BTree<KEY, VALUE, S>,
+  events: Vec<MutationEvent<KEY, VALUE>>,
+}
+
+impl<KEY, VALUE, const S: usize> CdcBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  pub fn len(&self) -> usize {
+    self.tree.size()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn get<Q>(&self, key: &Q) -> Option<&VALUE>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.tree.get(key)
+  }
+
+  /// Key-Value ペアを挿入し、`Inserted` または `Updated` のイベントを記録します。
+  ///
+  pub fn put(&mut self, key: KEY, value: VALUE) -> Option<VALUE> {
+    let old_value = self.tree.put(key.clone(), value.clone());
+    let kind = if old_value.is_some() {
+      MutationKind::Updated
+    } else {
+      MutationKind::Inserted
+    };
+    self.events.push(MutationEvent {
+      kind,
+      key,
+      old_value: old_value.clone(),
+      new_value: Some(value),
+    });
+    old_value
+  }
+
+  /// 指定されたキーのエントリを削除し、削除した場合は `Deleted` のイベントを記録します。イベントにキーを
+  /// 格納するため、`BTree::delete` と異なり借用した形式のキーは受け付けません。
+  ///
+  pub fn delete(&mut self, key: &KEY) -> Option<VALUE> {
+    let old_value = self.tree.delete(key)?;
+    self.events.push(MutationEvent {
+      kind: MutationKind::Deleted,
+      key: key.clone(),
+      old_value: Some(old_value.clone()),
+      new_value: None,
+    });
+    Some(old_value)
+  }
+
+  /// 取り出されていないイベントの数を参照します。
+  ///
+  pub fn pending_events(&self) -> usize {
+    self.events.len()
+  }
+
+  /// 蓄積されたイベントを記録された順に取り出します。返されたイテレータが破棄された時点で、走査されなかった
+  /// ものを含むすべてのイベントがバッファから取り除かれます。
+  ///
+  pub fn drain_events(&mut self) -> impl Iterator<Item = MutationEvent<KEY, VALUE>> + '_ {
+    self.events.drain(..)
+  }
+
+  /// イベントのバッファを破棄し、内部のツリーを返します。
+  ///
+  pub fn into_btree(self) -> BTree<KEY, VALUE, S> {
+    self.tree
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// このツリーを、以後のすべての変更をイベントとして記録する `CdcBTree` に変換します。既存のエントリに対する
+  /// イベントは生成されません。
+  ///
+  pub fn with_cdc(self) -> CdcBTree<KEY, VALUE, S> {
+    CdcBTree {
+      tree: self,
+      events: Vec::new(),
+    }
+  }
+}
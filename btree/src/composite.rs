@@ -0,0 +1,77 @@
+use crate::BTree;
+use core::ops::{Bound, RangeBounds};
+
+/// 2 つのフィールドからなる複合キーです。`(A, B)` と同様に第 1 フィールド、第 2 フィールドの順に辞書式で
+/// 比較されるため、第 1 フィールドが等しいエントリはツリー上で隣接します。
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct CompositeKey<A, B>(pub A, pub B);
+
+/// 最小値と最大値を持つ型です。`CompositeKey` の第 2 フィールドの範囲の両端として使用します。
+///
+pub trait Bounded {
+  fn min_value() -> Self;
+  fn max_value() -> Self;
+}
+
+macro_rules! impl_bounded {
+  ($($t:ty),*) => {
+    $(
+      impl Bounded for $t {
+        fn min_value() -> Self {
+          <$t>::MIN
+        }
+        fn max_value() -> Self {
+          <$t>::MAX
+        }
+      }
+    )*
+  };
+}
+
+impl_bounded!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, char);
+
+impl Bounded for bool {
+  fn min_value() -> Self {
+    false
+  }
+  fn max_value() -> Self {
+    true
+  }
+}
+
+impl Bounded for () {
+  fn min_value() -> Self {}
+  fn max_value() -> Self {}
+}
+
+impl<A, B, VALUE, const S: usize> BTree<CompositeKey<A, B>, VALUE, S>
+where
+  A: Ord + Clone,
+  B: Ord + Clone + Bounded,
+  VALUE: Clone,
+{
+  /// 第 1 フィールドが `prefix_range` に含まれるすべてのエントリをキーの昇順で参照するイテレータを返します。
+  /// 第 2 フィールドの最小値と最大値で補った `CompositeKey(a_min, B::min_value())` から
+  /// `CompositeKey(a_max, B::max_value())` までのキーの範囲を探索するため、範囲の先頭までの O(log n) の探索の
+  /// 後は該当するエントリのみを走査します。参照回数は更新されません。
+  ///
+  pub fn range_by_first_field<R: RangeBounds<A>>(
+    &self,
+    prefix_range: R,
+  ) -> impl Iterator<Item = (&CompositeKey<A, B>, &VALUE)> {
+    let start = match prefix_range.start_bound() {
+      Bound::Included(a) => Bound::Included(CompositeKey(a.clone(), B::min_value())),
+      Bound::Excluded(a) => Bound::Excluded(CompositeKey(a.clone(), B::max_value())),
+      Bound::Unbounded => Bound::Unbounded,
+    };
+    let end = match prefix_range.end_bound() {
+      Bound::Included(a) => Bound::Included(CompositeKey(a.clone(), B::max_value())),
+      Bound::Excluded(a) => Bound::Excluded(CompositeKey(a.clone(), B::min_value())),
+      Bound::Unbounded => Bound::Unbounded,
+    };
+    self
+      .range_entries(start.as_ref(), end)
+      .map(|kv| (&kv.key, &kv.value))
+  }
+}
@@ -0,0 +1,199 @@
+use crate::BTree;
+use std::borrow::Borrow;
+use std::sync::{Arc, Mutex};
+
+/// キーの範囲で分割した複数のシャードに B-Tree を分散させ、異なるシャードへの操作を競合なく並行に実行できる
+/// ようにしたツリーです。各シャードはそれぞれ独立した `Arc<Mutex<BTree>>` で保護されます。
+///
+/// `BTree` のノードは非アトミックな参照カウントと借用フラグを持つ `Rc<RefCell<_>>` で保持されているため、参照
+/// 操作であっても複数スレッドから同時にアクセスすることはできません。そのためシャードは `RwLock` ではなく
+/// `Mutex` で保護されます。
+///
+/// `Clone` はシャードを共有するハンドルを複製するため、複製したハンドルを各スレッドに渡して使用します。
+///
+#[derive(Debug)]
+pub struct ConcurrentShardedBTree<KEY, VALUE, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// 各シャードの先頭のキーです。シャード `i + 1` は `boundaries[i]` 以上のキーを担当します。
+  boundaries: Arc<Vec<KEY>>,
+  shards: Vec<Arc<Mutex<BTree<KEY, VALUE, S>>>>,
+}
+
+impl<KEY, VALUE, const S: usize> ConcurrentShardedBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// シャードの数を参照します。
+  ///
+  pub fn shard_count(&self) -> usize {
+    self.shards.len()
+  }
+
+  /// 指定されたキーを担当するシャードのインデックスを返します。
+  ///
+  pub fn shard_index<Q>(&self, key: &Q) -> usize
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self
+      .boundaries
+      .partition_point(|boundary| boundary.borrow() <= key)
+  }
+
+  /// すべてのシャードに格納されているキーの総数を参照します。各シャードは順にロックされるため、他のスレッド
+  /// による更新と並行している場合の結果は特定の時点のものとは限りません。
+  ///
+  pub fn size(&self) -> usize {
+    self
+      .shards
+      .iter()
+      .map(|shard| shard.lock().unwrap().size())
+      .sum()
+  }
+
+  pub fn get<Q>(&self, key: &Q) -> Option<VALUE>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.shard(key).lock().unwrap().get_cloned(key)
+  }
+
+  pub fn put(&self, key: KEY, value: VALUE) -> Option<VALUE> {
+    self.shard(&key).lock().unwrap().put(key, value)
+  }
+
+  pub fn delete<Q>(&self, key: &Q) -> Option<VALUE>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.shard(key).lock().unwrap().delete(key)
+  }
+
+  fn shard<Q>(&self, key: &Q) -> &Mutex<BTree<KEY, VALUE, S>>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    &self.shards[self.shard_index(key)]
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Clone for ConcurrentShardedBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn clone(&self) -> Self {
+    ConcurrentShardedBTree {
+      boundaries: self.boundaries.clone(),
+      shards: self.shards.clone(),
+    }
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// このツリーをキーの範囲で `shards` 個のシャードに分割し、並行アクセス可能な `ConcurrentShardedBTree` に
+  /// 変換します。各シャードが担当するキーの範囲は、現在のエントリがほぼ均等に分散するように決定されます。
+  /// エントリ数が `shards` より少ない場合、シャードの数はエントリ数 (空の場合は 1) となります。
+  ///
+  pub fn into_concurrent(mut self, shards: usize) -> ConcurrentShardedBTree<KEY, VALUE, S> {
+    let size = self.size();
+    let count = shards.min(size).max(1);
+    let boundaries = self
+      .iter()
+      .enumerate()
+      .filter(|(i, _)| *i != 0 && i * count % size < count)
+      .map(|(_, (key, _))| key)
+      .collect::<Vec<_>>();
+    debug_assert_eq!(count - 1, boundaries.len());
+
+    let mut parts = Vec::with_capacity(count);
+    for boundary in boundaries.iter().rev() {
+      parts.push(Arc::new(Mutex::new(self.split_off(boundary))));
+    }
+    parts.push(Arc::new(Mutex::new(self)));
+    parts.reverse();
+    ConcurrentShardedBTree {
+      boundaries: Arc::new(boundaries),
+      shards: parts,
+    }
+  }
+}
+
+/// 1 つの B-Tree を複数のスレッドで共有するためのハンドルです。すべての操作は `&self` で呼び出すことができ、
+/// 内部でロックを取得します。
+///
+/// ロックには `RwLock` ではなく `Mutex` を使用し、参照操作と更新操作のいずれも `lock()` で排他的にロックを
+/// 取得します。`BTree` の参照操作はノードの `RefCell` の借用フラグや参照回数を更新するため、読み取りロックで
+/// 複数スレッドから同時に参照することは安全ではありません (`BTree` は `Sync` ではないため `RwLock` に格納
+/// してもスレッド間で共有できません)。
+///
+/// `Clone` は同じツリーを共有する `Arc` を複製するだけの安価な操作です。
+///
+#[derive(Debug)]
+pub struct SharedBTree<KEY, VALUE, const S: usize>(Arc<Mutex<BTree<KEY, VALUE, S>>>)
+where
+  KEY: Ord + Clone,
+  VALUE: Clone;
+
+impl<KEY, VALUE, const S: usize> SharedBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  pub fn new(tree: BTree<KEY, VALUE, S>) -> Self {
+    SharedBTree(Arc::new(Mutex::new(tree)))
+  }
+
+  pub fn get<Q>(&self, key: &Q) -> Option<VALUE>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.0.lock().unwrap().get_cloned(key)
+  }
+
+  pub fn put(&self, key: KEY, value: VALUE) -> Option<VALUE> {
+    self.0.lock().unwrap().put(key, value)
+  }
+
+  pub fn delete<Q>(&self, key: &Q) -> Option<VALUE>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.0.lock().unwrap().delete(key)
+  }
+
+  pub fn size(&self) -> usize {
+    self.0.lock().unwrap().size()
+  }
+
+  /// すべての Key-Value ペアをキーの昇順に並べたベクタを返します。ロックはこのベクタを構築する間だけ保持
+  /// されるため、呼び出し側が結果を走査する間も他のスレッドはツリーを操作できます。
+  ///
+  pub fn snapshot_iter(&self) -> Vec<(KEY, VALUE)> {
+    self.0.lock().unwrap().iter().collect()
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Clone for SharedBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn clone(&self) -> Self {
+    SharedBTree(self.0.clone())
+  }
+}
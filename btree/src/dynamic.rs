@@ -0,0 +1,98 @@
+use crate::BTree;
+use alloc::boxed::Box;
+use core::mem::size_of;
+
+/// 次数 `S` を型から取り除いた B-Tree の操作です。実行時に決定された次数のツリーを同じ型で扱うために使用
+/// します。
+///
+pub trait DynBTree<KEY, VALUE> {
+  /// このツリーの次数 `S` を返します。
+  ///
+  fn order(&self) -> usize;
+
+  fn size(&self) -> usize;
+
+  fn level(&self) -> usize;
+
+  fn get(&self, key: &KEY) -> Option<VALUE>;
+
+  fn put(&mut self, key: KEY, value: VALUE) -> Option<VALUE>;
+
+  fn delete(&mut self, key: &KEY) -> Option<VALUE>;
+
+  /// すべての Key-Value をキーの昇順で列挙します。
+  ///
+  fn iter(&self) -> Box<dyn Iterator<Item = (KEY, VALUE)> + '_>;
+}
+
+impl<KEY, VALUE, const S: usize> DynBTree<KEY, VALUE> for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn order(&self) -> usize {
+    S
+  }
+
+  fn size(&self) -> usize {
+    BTree::size(self)
+  }
+
+  fn level(&self) -> usize {
+    BTree::level(self)
+  }
+
+  fn get(&self, key: &KEY) -> Option<VALUE> {
+    BTree::get_cloned(self, key)
+  }
+
+  fn put(&mut self, key: KEY, value: VALUE) -> Option<VALUE> {
+    BTree::put(self, key, value)
+  }
+
+  fn delete(&mut self, key: &KEY) -> Option<VALUE> {
+    BTree::delete(self, key)
+  }
+
+  fn iter(&self) -> Box<dyn Iterator<Item = (KEY, VALUE)> + '_> {
+    Box::new(BTree::iter(self))
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + 'static,
+  VALUE: Clone + 'static,
+{
+  /// 1 ノードのキーと値の合計サイズが `target_node_size_bytes` (例えば 1 キャッシュライン 64 バイト) に収まる
+  /// 次数 `target_node_size_bytes / (size_of::<KEY>() + size_of::<VALUE>()) / 2` を算出し、その次数で同じ内容
+  /// を格納した新しいツリーを返します。次数は型パラメータであるため、算出された値以下で最も近いサポート済み
+  /// の次数 (1〜8, 12, 16, 24, 32, 48, 64) が使用されます。
+  ///
+  pub fn adaptive_order_migration(
+    &self,
+    target_node_size_bytes: usize,
+  ) -> Box<dyn DynBTree<KEY, VALUE>> {
+    let entry_size = (size_of::<KEY>() + size_of::<VALUE>()).max(1);
+    match target_node_size_bytes / entry_size / 2 {
+      0..=1 => self.migrate::<1>(),
+      2 => self.migrate::<2>(),
+      3 => self.migrate::<3>(),
+      4 => self.migrate::<4>(),
+      5 => self.migrate::<5>(),
+      6 => self.migrate::<6>(),
+      7 => self.migrate::<7>(),
+      8..=11 => self.migrate::<8>(),
+      12..=15 => self.migrate::<12>(),
+      16..=23 => self.migrate::<16>(),
+      24..=31 => self.migrate::<24>(),
+      32..=47 => self.migrate::<32>(),
+      48..=63 => self.migrate::<48>(),
+      _ => self.migrate::<64>(),
+    }
+  }
+
+  fn migrate<const T: usize>(&self) -> Box<dyn DynBTree<KEY, VALUE>> {
+    Box::new(BTree::<KEY, VALUE, T>::from_sorted_iter(self.iter()))
+  }
+}
@@ -0,0 +1,107 @@
+use crate::BTree;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::ops::{Bound, RangeBounds};
+
+/// キーの昇順に整列された `Vec` と二分探索による読み取り専用のマップです。一度構築した後は参照のみを行う
+/// 用途で、`Rc<RefCell<Node>>` によるツリー構造のオーバーヘッドを取り除きます。
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FlatMap<KEY, VALUE>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  entries: Vec<(KEY, VALUE)>,
+}
+
+impl<KEY, VALUE> FlatMap<KEY, VALUE>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// 指定されたキーに関連付けられた値を返します。値が存在しない場合は None を返します。
+  ///
+  pub fn get<Q>(&self, key: &Q) -> Option<&VALUE>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.find_index(key).ok().map(|i| &self.entries[i].1)
+  }
+
+  pub fn contains_key<Q>(&self, key: &Q) -> bool
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.find_index(key).is_ok()
+  }
+
+  /// すべての Key-Value をキーの昇順で列挙します。
+  ///
+  pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&KEY, &VALUE)> + ExactSizeIterator {
+    self.entries.iter().map(|(key, value)| (key, value))
+  }
+
+  /// 指定された範囲に含まれるキーを持つ Key-Value をキーの昇順で列挙します。
+  ///
+  pub fn range<R>(
+    &self,
+    range: R,
+  ) -> impl DoubleEndedIterator<Item = (&KEY, &VALUE)> + ExactSizeIterator
+  where
+    R: RangeBounds<KEY>,
+  {
+    let start = match range.start_bound() {
+      Bound::Included(key) => self.entries.partition_point(|(k, _)| k < key),
+      Bound::Excluded(key) => self.entries.partition_point(|(k, _)| k <= key),
+      Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+      Bound::Included(key) => self.entries.partition_point(|(k, _)| k <= key),
+      Bound::Excluded(key) => self.entries.partition_point(|(k, _)| k < key),
+      Bound::Unbounded => self.entries.len(),
+    };
+    self.entries[start..end.max(start)]
+      .iter()
+      .map(|(key, value)| (key, value))
+  }
+
+  /// この `FlatMap` と同じ内容を持つ次数 `S` の `BTree` を O(n) で構築します。
+  ///
+  pub fn into_btree<const S: usize>(self) -> BTree<KEY, VALUE, S> {
+    BTree::from_sorted_iter(self.entries)
+  }
+
+  #[inline]
+  fn find_index<Q>(&self, key: &Q) -> core::result::Result<usize, usize>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.entries.binary_search_by(|(k, _)| k.borrow().cmp(key))
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// このツリーの内容をキーの昇順に並べた読み取り専用の `FlatMap` に O(n) で変換します。
+  ///
+  pub fn as_flat_map(&self) -> FlatMap<KEY, VALUE> {
+    FlatMap {
+      entries: self.iter().collect(),
+    }
+  }
+}
@@ -0,0 +1,64 @@
+use crate::{BTree, KeyVal};
+use alloc::vec::Vec;
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + AsRef<str>,
+  VALUE: Clone,
+{
+  /// キーとの編集距離 (Levenshtein 距離) が `distance` 以下となるすべてのエントリを、キーの昇順で返します。
+  /// 距離は Unicode のスカラー値を単位として数えます。参照回数は更新されません。
+  ///
+  /// 編集距離は先頭の文字も変更の対象となるため、キーの範囲だけで候補を絞り込むことはできず、すべてのキーを
+  /// 走査します。その代わり、キーの昇順に隣接するキーが共有する接頭辞について動的計画法の行を再利用し、各キー
+  /// では共有していない末尾の文字の分だけを計算します。また、ある接頭辞の時点で行の最小値が `distance` を
+  /// 超えた場合はそのキーの残りの文字を計算しません。最悪の場合の計算量は通常の編集距離と同じく
+  /// O(n * キーの長さ * クエリの長さ) です。
+  ///
+  pub fn fuzzy_get(&self, query: &str, distance: usize) -> Vec<(&KEY, &VALUE)> {
+    let query = query.chars().collect::<Vec<_>>();
+    let mut matches = Vec::new();
+    // rows[i] は直前に計算したキーの先頭 i 文字とクエリとの距離の行
+    let mut rows = Vec::from([(0..=query.len()).collect::<Vec<_>>()]);
+    let mut previous: Vec<char> = Vec::new();
+    self.root.borrow().for_each(&mut |kv| {
+      let key = kv.key.as_ref().chars().collect::<Vec<_>>();
+      let common = key
+        .iter()
+        .zip(previous.iter())
+        .take_while(|(a, b)| a == b)
+        .count()
+        .min(rows.len() - 1);
+      rows.truncate(common + 1);
+      for c in key[common..].iter() {
+        let row = edit_distance_row(rows.last().unwrap(), &query, *c);
+        let pruned = row.iter().min().unwrap() > &distance;
+        rows.push(row);
+        if pruned {
+          break;
+        }
+      }
+      if rows.len() == key.len() + 1 && rows[key.len()][query.len()] <= distance {
+        // SAFETY: ノードが可変に借用されるのは `&mut self` を受け取る操作の中だけであり、返した参照が有効な
+        // 間 (`&self` の借用期間) はツリーが変更されることはない。
+        let kv = unsafe { &*(kv as *const KeyVal<KEY, VALUE>) };
+        matches.push((&kv.key, &kv.value));
+      }
+      previous = key;
+      true
+    });
+    matches
+  }
+}
+
+/// ある文字列とクエリとの編集距離の行 `prev` から、その文字列に `c` を加えた文字列との行を求めます。
+///
+fn edit_distance_row(prev: &[usize], query: &[char], c: char) -> Vec<usize> {
+  let mut row = Vec::with_capacity(prev.len());
+  row.push(prev[0] + 1);
+  for (j, q) in query.iter().enumerate() {
+    let substitution = prev[j] + usize::from(*q != c);
+    row.push(substitution.min(prev[j + 1] + 1).min(row[j] + 1));
+  }
+  row
+}
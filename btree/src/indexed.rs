@@ -0,0 +1,125 @@
+use crate::{BTree, Error, Result};
+use alloc::boxed::Box;
+use core::fmt;
+
+type Extractor<KEY, VALUE, INDEX> = Box<dyn Fn(&KEY, &VALUE) -> INDEX>;
+
+/// 主キーで値を格納する B-Tree と、値から導いた一意の索引キーから主キーを引く B-Tree の 2 つを整合させて
+/// 保持するツリーです。データベースの一意な副索引を構成する最小の部品として使用します。
+///
+/// 索引キーは構築時に与えた抽出関数によって各エントリのキーと値から求めます。1 つの索引キーに対応する
+/// エントリは 1 つだけであり、他のエントリに既に割り当てられている索引キーを持つエントリの `put` は失敗します。
+///
+pub struct IndexedBTree<KEY, VALUE, INDEX, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+  INDEX: Ord + Clone,
+{
+  primary: BTree<KEY, VALUE, S>,
+  secondary: BTree<INDEX, KEY, S>,
+  extractor: Extractor<KEY, VALUE, INDEX>,
+}
+
+impl<KEY, VALUE, INDEX, const S: usize> IndexedBTree<KEY, VALUE, INDEX, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+  INDEX: Ord + Clone,
+{
+  /// エントリの索引キーを `extractor` で求める空のツリーを構築します。
+  ///
+  pub fn new(extractor: impl Fn(&KEY, &VALUE) -> INDEX + 'static) -> Self {
+    IndexedBTree {
+      primary: BTree::new(),
+      secondary: BTree::new(),
+      extractor: Box::new(extractor),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.primary.size()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// 指定された Key-Value を両方のツリーに格納し、既に値が存在していた場合はその値を返します。値の更新に
+  /// よって索引キーが変わった場合、古い索引キーは索引から削除されます。
+  ///
+  /// 索引キーが他のエントリに割り当てられている場合は `Error::DuplicateIndexKey` を、いずれかのツリーへの
+  /// 挿入が失敗した場合はその `Error` を返します。主キーのツリーへの格納が成功した後に索引への格納が失敗した
+  /// 場合は、主キーのツリーを格納する前の状態に戻してからエラーを返します。
+  ///
+  pub fn put(&mut self, key: KEY, value: VALUE) -> Result<Option<VALUE>> {
+    let idx_key = (self.extractor)(&key, &value);
+    let old = self.primary.try_put(key.clone(), value)?;
+    let indexed = match self.secondary.get(&idx_key) {
+      Some(owner) if *owner != key => Err(Error::DuplicateIndexKey),
+      _ => self
+        .secondary
+        .try_put(idx_key.clone(), key.clone())
+        .map(|_| ()),
+    };
+    if let Err(e) = indexed {
+      match old {
+        Some(old) => self.primary.put(key, old),
+        None => self.primary.delete(&key),
+      };
+      return Err(e);
+    }
+    if let Some(old) = &old {
+      let old_idx_key = (self.extractor)(&key, old);
+      if old_idx_key != idx_key {
+        self.secondary.delete(&old_idx_key);
+      }
+    }
+    Ok(old)
+  }
+
+  /// 指定されたキーのエントリを両方のツリーから削除し、その値を返します。
+  ///
+  pub fn delete(&mut self, key: &KEY) -> Option<VALUE> {
+    let value = self.primary.delete(key)?;
+    self.secondary.delete(&(self.extractor)(key, &value));
+    Some(value)
+  }
+
+  pub fn get(&self, key: &KEY) -> Option<&VALUE> {
+    self.primary.get(key)
+  }
+
+  /// 索引キーから主キーを求め、そのエントリのキーと値への参照を返します。
+  ///
+  pub fn get_by_index(&self, idx_key: &INDEX) -> Option<(&KEY, &VALUE)> {
+    let key = self.secondary.get(idx_key)?;
+    self.primary.get(key).map(|value| (key, value))
+  }
+
+  /// 主キーのツリーを参照します。
+  ///
+  pub fn primary(&self) -> &BTree<KEY, VALUE, S> {
+    &self.primary
+  }
+
+  /// 索引キーから主キーを引くツリーを参照します。
+  ///
+  pub fn secondary(&self) -> &BTree<INDEX, KEY, S> {
+    &self.secondary
+  }
+}
+
+impl<KEY, VALUE, INDEX, const S: usize> fmt::Debug for IndexedBTree<KEY, VALUE, INDEX, S>
+where
+  KEY: Ord + Clone + fmt::Debug,
+  VALUE: Clone + fmt::Debug,
+  INDEX: Ord + Clone + fmt::Debug,
+{
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("IndexedBTree")
+      .field("primary", &self.primary)
+      .field("secondary", &self.secondary)
+      .finish_non_exhaustive()
+  }
+}
@@ -0,0 +1,109 @@
+use crate::BTree;
+use alloc::vec::Vec;
+
+/// 語からその語を含む文書の ID の列 (ポスティングリスト) を引く転置索引です。`BTree<TERM, Vec<DOC>, S>` を
+/// 格納領域とし、すべての語を含む文書を探す全文検索の基本的な操作を提供します。
+///
+/// 各ポスティングリストは文書 ID の昇順に整列され、重複を含みません。文書を含まなくなった語は索引から削除
+/// されます。
+///
+#[derive(Debug, Clone, Default)]
+pub struct InvertedIndex<TERM, DOC, const S: usize>
+where
+  TERM: Ord + Clone,
+  DOC: Ord + Copy,
+{
+  postings: BTree<TERM, Vec<DOC>, S>,
+}
+
+impl<TERM, DOC, const S: usize> InvertedIndex<TERM, DOC, S>
+where
+  TERM: Ord + Clone,
+  DOC: Ord + Copy,
+{
+  pub fn new() -> Self {
+    InvertedIndex {
+      postings: BTree::new(),
+    }
+  }
+
+  /// 索引に含まれる語の数を参照します。
+  ///
+  pub fn term_count(&self) -> usize {
+    self.postings.size()
+  }
+
+  /// 文書 `doc_id` をその文書に含まれる各語のポスティングリストに追加します。既に追加されている語は無視します。
+  ///
+  pub fn index_document(&mut self, doc_id: DOC, terms: impl Iterator<Item = TERM>) {
+    for term in terms {
+      let indexed = match self.postings.get_mut(&term) {
+        Some(mut docs) => {
+          if let Err(i) = docs.binary_search(&doc_id) {
+            docs.insert(i, doc_id);
+          }
+          true
+        }
+        None => false,
+      };
+      if !indexed {
+        self.postings.put(term, alloc::vec![doc_id]);
+      }
+    }
+  }
+
+  /// 文書 `doc_id` を指定された各語のポスティングリストから削除します。文書を含まなくなった語は索引から
+  /// 削除されます。
+  ///
+  pub fn remove_document(&mut self, doc_id: DOC, terms: impl Iterator<Item = TERM>) {
+    for term in terms {
+      let now_empty = match self.postings.get_mut(&term) {
+        Some(mut docs) => {
+          if let Ok(i) = docs.binary_search(&doc_id) {
+            docs.remove(i);
+          }
+          docs.is_empty()
+        }
+        None => false,
+      };
+      if now_empty {
+        self.postings.delete(&term);
+      }
+    }
+  }
+
+  /// 指定されたすべての語を含む文書の ID を昇順で返します。ポスティングリストの短い語から順に積集合を求める
+  /// ため、候補は最も短いリストの長さを超えません。`terms` が空の場合は空の `Vec` を返します。
+  ///
+  pub fn search(&self, terms: &[TERM]) -> Vec<DOC> {
+    let mut lists = Vec::with_capacity(terms.len());
+    for term in terms {
+      match self.postings.get(term) {
+        Some(docs) => lists.push(docs),
+        None => return Vec::new(),
+      }
+    }
+    lists.sort_by_key(|docs| docs.len());
+    let Some((shortest, rest)) = lists.split_first() else {
+      return Vec::new();
+    };
+    let mut result = shortest.to_vec();
+    for docs in rest {
+      let mut j = 0;
+      result.retain(|doc| {
+        j += docs[j..].partition_point(|d| d < doc);
+        docs.get(j) == Some(doc)
+      });
+      if result.is_empty() {
+        break;
+      }
+    }
+    result
+  }
+
+  /// 指定された語を含む文書の数を参照します。
+  ///
+  pub fn term_frequency(&self, term: &TERM) -> usize {
+    self.postings.get(term).map_or(0, Vec::len)
+  }
+}
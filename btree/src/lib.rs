@@ -1,91 +1,1860 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::borrow;
+use core::cell::{Cell, RefCell};
+use core::cmp::Ordering;
+use core::fmt::{self, Display, Write as _};
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
+
+#[cfg(feature = "tokio")]
+pub mod async_;
+#[cfg(feature = "audit")]
+mod audit;
+mod autoincr;
+#[cfg(feature = "bloom")]
+mod bloom;
+mod bplus;
+mod cdc;
+mod composite;
+#[cfg(feature = "std")]
+pub mod concurrent;
+mod dynamic;
+mod flat;
+mod fuzzy;
+mod indexed;
+mod inverted;
+mod macros;
+mod multimap;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod persistent;
+mod prefix;
+mod priority;
+#[cfg(feature = "std")]
+mod profile;
+#[cfg(feature = "repair")]
+mod repair;
+#[cfg(feature = "serde")]
+mod serialize;
+mod set;
+pub mod set_ops;
+mod stats;
+#[cfg(feature = "serde")]
 pub mod storage;
+#[cfg(feature = "std")]
+mod throttle;
+mod transaction;
+#[cfg(feature = "std")]
+mod ttl;
+mod validate;
 
-#[cfg(test)]
+#[cfg(feature = "audit")]
+pub use audit::AuditEntry;
+pub use autoincr::AutoIncrBTree;
+#[cfg(feature = "bloom")]
+pub use bloom::BloomStats;
+pub use bplus::BPlusTree;
+pub use cdc::{CdcBTree, MutationEvent, MutationKind};
+pub use composite::{Bounded, CompositeKey};
+pub use dynamic::DynBTree;
+pub use flat::FlatMap;
+pub use indexed::IndexedBTree;
+pub use inverted::InvertedIndex;
+pub use multimap::BTreeMultiMap;
+pub use persistent::PersistentBTree;
+pub use prefix::PrefixTree;
+pub use priority::BTreePriorityQueue;
+#[cfg(feature = "std")]
+pub use profile::{IterOverhead, LookupTrace, Op, OperationProfile};
+#[cfg(feature = "serde")]
+pub use serialize::FORMAT_VERSION;
+pub use set::BTreeSet;
+pub use stats::{RangeSummary, TreeStats};
+#[cfg(feature = "std")]
+pub use throttle::ThrottledIter;
+pub use transaction::Transaction;
+#[cfg(feature = "std")]
+pub use ttl::TtlBTree;
+pub use validate::{Map, ValidationError, ValidationReport};
+
+#[cfg(all(test, feature = "std"))]
 mod test;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
+  #[cfg(feature = "std")]
   #[error("I/O error: {0}")]
   IO(#[from] std::io::Error),
 
+  #[cfg(feature = "serde")]
   #[error("Serialization failed: {0}")]
   Serialize(#[from] bincode::Error),
+
+  #[error("Invalid file header: {0}")]
+  InvalidHeader(String),
+
+  #[error("Incompatible tree order: expected S={expected_s}, but found S={found_s}")]
+  IncompatibleFormat { expected_s: usize, found_s: usize },
+
+  #[error("Page {0} is corrupted")]
+  CorruptedPage(u64),
+
+  #[error("WAL record at offset {0} is corrupted")]
+  WalCorrupted(u64),
+
+  #[error("Tree invariant violated: {0}")]
+  InvariantViolation(String),
+
+  #[error("Index key is already assigned to another entry")]
+  DuplicateIndexKey,
+
+  #[cfg(feature = "capnp")]
+  #[error("Cap'n Proto error: {0}")]
+  Capnp(#[from] capnp::Error),
+
+  #[cfg(feature = "cbor")]
+  #[error("CBOR encoding failed: {0}")]
+  CborEncode(#[from] ciborium::ser::Error<std::io::Error>),
+
+  #[cfg(feature = "cbor")]
+  #[error("CBOR decoding failed: {0}")]
+  CborDecode(#[from] ciborium::de::Error<std::io::Error>),
+
+  #[cfg(feature = "json")]
+  #[error("JSON serialization failed: {0}")]
+  Json(#[from] serde_json::Error),
+
+  #[cfg(feature = "kafka")]
+  #[error("Kafka error: {0}")]
+  Kafka(#[from] rdkafka::error::KafkaError),
+
+  #[cfg(feature = "msgpack")]
+  #[error("MessagePack encoding failed: {0}")]
+  MsgPackEncode(#[from] rmp_serde::encode::Error),
+
+  #[cfg(feature = "msgpack")]
+  #[error("MessagePack decoding failed: {0}")]
+  MsgPackDecode(#[from] rmp_serde::decode::Error),
+
+  #[cfg(feature = "parquet")]
+  #[error("Parquet error: {0}")]
+  Parquet(#[from] parquet::errors::ParquetError),
+
+  #[cfg(feature = "redis")]
+  #[error("Redis error: {0}")]
+  Redis(#[from] redis::RedisError),
+
+  #[cfg(feature = "aws")]
+  #[error("S3 error: {0}")]
+  S3(alloc::boxed::Box<aws_sdk_s3::Error>),
+}
+
+#[cfg(feature = "aws")]
+impl From<aws_sdk_s3::Error> for Error {
+  fn from(e: aws_sdk_s3::Error) -> Self {
+    Error::S3(alloc::boxed::Box::new(e))
+  }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[cfg(feature = "std")]
+thread_local! {
+  /// このスレッドで発生したノードの分割と併合の累計回数です。`profile_operations` が操作の前後の差分から構造の
+  /// 変化を計測するために使用します。
+  static STRUCTURE_CHANGES: Cell<(u64, u64)> = const { Cell::new((0, 0)) };
+}
+
+#[derive(Debug)]
+pub struct BTree<KEY, VALUE, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  root: Rc<RefCell<Node<KEY, VALUE, S>>>,
+  /// `with_capacity` で指定された見積もりのうち、まだ生成されていないノードの数です。`put` による分割で生成
+  /// されるノードはこの数が残っている間、最大のキー数を格納できる容量をあらかじめ確保します。
+  reserved_nodes: usize,
+  #[cfg(feature = "audit")]
+  audit: Vec<audit::AuditEntry<KEY, VALUE>>,
+  #[cfg(feature = "bloom")]
+  bloom: Option<bloom::BloomFilter<KEY>>,
+}
+
+// SAFETY: ツリーを構成する各ノードの `Rc` はその親ノード (ルートはツリー自身) だけが保持しており、ツリーの外
+// に複製されることはない (`Iter` が保持する複製はツリーの借用期間に限られる)。したがってツリー全体を別のスレッド
+// に移動しても、同じ参照カウントや借用フラグが複数のスレッドから操作されることはない。
+unsafe impl<KEY, VALUE, const S: usize> Send for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Send,
+  VALUE: Clone + Send,
+{
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  pub fn new() -> Self {
+    BTree {
+      root: Rc::new(RefCell::new(Node::<KEY, VALUE, S>::new(true))),
+      reserved_nodes: 0,
+      #[cfg(feature = "audit")]
+      audit: Vec::new(),
+      #[cfg(feature = "bloom")]
+      bloom: None,
+    }
+  }
+
+  /// およそ `estimated_entries` 個のエントリを格納する見込みの空のツリーを構築します。
+  ///
+  /// 次数 `S` のツリーが `n` 個のエントリを格納するときのノード数 (葉が `n / S`、その親が `n / S^2`、...) を
+  /// 見積もり、以後の `put` で生成されるその数までのノードに最大のキー数を格納できる容量をあらかじめ確保します。
+  /// これにより大量の挿入でノードのキーが増えるたびに `Vec` が再確保されることを避けられます。
+  ///
+  pub fn with_capacity(estimated_entries: usize) -> Self {
+    let fanout = S.max(2);
+    let mut reserved_nodes = 0;
+    let mut nodes = estimated_entries / fanout;
+    while nodes > 0 {
+      reserved_nodes += nodes;
+      nodes /= fanout;
+    }
+    BTree {
+      root: Rc::new(RefCell::new(Node::with_full_capacity(true))),
+      reserved_nodes,
+      #[cfg(feature = "audit")]
+      audit: Vec::new(),
+      #[cfg(feature = "bloom")]
+      bloom: None,
+    }
+  }
+
+  /// キーの昇順に整列済みで重複のない Key-Value ペアの列からツリーを構築します。各ノードが可能な限りキーで
+  /// 満たされるように葉からボトムアップに構築するため、`put` を繰り返す O(n log n) に対して O(n) で動作し
+  /// ます。
+  ///
+  /// 入力のキーが狭義単調増加でない場合は panic します。
+  ///
+  pub fn from_sorted_iter<I: IntoIterator<Item = (KEY, VALUE)>>(iter: I) -> Self {
+    let iter = iter.into_iter();
+    let mut entries: Vec<KeyVal<KEY, VALUE>> = Vec::with_capacity(iter.size_hint().0);
+    for (key, value) in iter {
+      if let Some(last) = entries.last() {
+        assert!(
+          last.key < key,
+          "from_sorted_iter requires strictly ascending keys, but the key at position {} is not greater than its predecessor",
+          entries.len()
+        );
+      }
+      entries.push(KeyVal::new(key, value));
+    }
+    BTree::bulk_load(entries)
+  }
+
+  /// 葉ノードの内容を左から順に並べたリストからツリーを再構築します。葉レベルのバックアップからツリーを
+  /// 復旧するために使用します。各葉のキーは葉の中でも葉の間でも狭義単調増加である必要があります。
+  ///
+  /// 空の葉がある場合、葉のキーの範囲が重複しているか順序が逆転している場合、または葉が 2 つ以上あるときに
+  /// `S` 以上 `2S` 以下というキー数の制約を満たさない葉がある場合は `Error::InvariantViolation` を返します。
+  ///
+  /// この B-Tree の内部ノードは分岐キーとして自身のエントリを保持するため、葉の内容だけでは元の内部ノードを
+  /// 特定できません。そのため内部ノードはすべてのエントリから `from_sorted_iter` と同様に O(n) で構築し直し、
+  /// 復元したツリーの葉の分割位置は入力と一致するとは限りません。
+  ///
+  pub fn reconstruct_from_leaves(leaves: Vec<Vec<(KEY, VALUE)>>) -> Result<Self> {
+    let checks_size = leaves.len() > 1;
+    let mut entries: Vec<KeyVal<KEY, VALUE>> =
+      Vec::with_capacity(leaves.iter().map(Vec::len).sum());
+    for (i, leaf) in leaves.into_iter().enumerate() {
+      if leaf.is_empty() {
+        return Err(Error::InvariantViolation(format!("leaf {i} is empty")));
+      }
+      if checks_size && !(S..=2 * S).contains(&leaf.len()) {
+        return Err(Error::InvariantViolation(format!(
+          "leaf {i} has {} keys, outside of [{S}, {}]",
+          leaf.len(),
+          2 * S
+        )));
+      }
+      for (j, (key, value)) in leaf.into_iter().enumerate() {
+        if let Some(last) = entries.last() {
+          if last.key >= key {
+            return Err(Error::InvariantViolation(if j == 0 {
+              format!("leaf {i} overlaps the key range of its predecessor")
+            } else {
+              format!("keys in leaf {i} are not strictly ascending at position {j}")
+            }));
+          }
+        }
+        entries.push(KeyVal::new(key, value));
+      }
+    }
+    Ok(BTree::bulk_load(entries))
+  }
+
+  /// `btree!` マクロの展開先です。キーが狭義単調増加であれば `from_sorted_iter` で、そうでなければ `put` を
+  /// 繰り返して構築し、重複したキーがある場合は panic します。
+  ///
+  #[doc(hidden)]
+  pub fn from_literal_entries<const N: usize>(entries: [(KEY, VALUE); N]) -> Self {
+    if entries.windows(2).all(|w| w[0].0 < w[1].0) {
+      return BTree::from_sorted_iter(entries);
+    }
+    let mut btree = BTree::new();
+    for (i, (key, value)) in entries.into_iter().enumerate() {
+      assert!(
+        btree.put(key, value).is_none(),
+        "duplicate key at position {i} in btree! literal"
+      );
+    }
+    btree
+  }
+
+  /// キーの昇順に整列済みで重複のない Key-Value から葉を起点にボトムアップでツリーを構築します。各レベルの
+  /// ノード数を最小にしたうえでキーを均等に配分するため、ルート以外のすべてのノードは `S` 以上 `2S` 以下の
+  /// キーを持ちます。
+  ///
+  fn bulk_load(entries: Vec<KeyVal<KEY, VALUE>>) -> Self {
+    let mut keys = entries;
+    let mut pivots = Vec::new();
+    let mut is_leaf = true;
+    while keys.len() > 2 * S {
+      let count = (keys.len() + 1).div_ceil(2 * S + 1);
+      let per_node = keys.len() - (count - 1);
+      let mut nodes = Vec::with_capacity(count);
+      let mut separators = Vec::with_capacity(count - 1);
+      let mut keys_iter = keys.into_iter();
+      let mut pivots_iter = pivots.into_iter();
+      for i in 0..count {
+        let len = per_node / count + if i < per_node % count { 1 } else { 0 };
+        let mut node = Node::new(is_leaf);
+        node.keys.extend(keys_iter.by_ref().take(len));
+        if !is_leaf {
+          node.pivots.extend(pivots_iter.by_ref().take(len + 1));
+        }
+        node.update_size();
+        nodes.push(Rc::new(RefCell::new(node)));
+        if i + 1 < count {
+          separators.push(keys_iter.next().unwrap());
+        }
+      }
+      keys = separators;
+      pivots = nodes;
+      is_leaf = false;
+    }
+    let mut root = Node::new(is_leaf);
+    root.keys = keys;
+    root.pivots = pivots;
+    root.update_size();
+    BTree {
+      root: Rc::new(RefCell::new(root)),
+      reserved_nodes: 0,
+      #[cfg(feature = "audit")]
+      audit: Vec::new(),
+      #[cfg(feature = "bloom")]
+      bloom: None,
+    }
+  }
+
+  /// この B-Tree に格納されているキーの下図を参照します。ルートが保持する部分木のエントリ数を返すため O(1)
+  /// で動作します。
+  ///
+  pub fn size(&self) -> usize {
+    self.root.borrow().subtree_size
+  }
+
+  // この B-Tree の葉までの深さを参照します。この機能は葉を 1 と数えます。
+  //
+  pub fn level(&self) -> usize {
+    self.root.borrow().level(0)
+  }
+
+  /// すべてのエントリを、そのエントリの深さと共にキーの昇順で参照するイテレータを返します。深さはルートを 0
+  /// とし、葉ノードのエントリはその葉ノードの深さ、内部ノードのエントリは左の部分木の最も右の葉ノード (直前の
+  /// エントリを持つ葉ノード) の深さとなります。正しい B-Tree ではすべての深さが `level() - 1` で等しくなり、
+  /// 移行中や部分的な修復の後のように葉の深さが揃っていないツリーではその不整合が深さの違いとして現れます。
+  /// 参照回数は更新されません。
+  ///
+  pub fn iter_with_depth(&self) -> impl Iterator<Item = (usize, &KEY, &VALUE)> + '_ {
+    let mut entries = Vec::with_capacity(self.size());
+    self.root.borrow().collect_with_depth(0, &mut entries);
+    entries.into_iter().map(|(depth, kv)| {
+      // SAFETY: ノードが可変に借用されるのは `&mut self` を受け取る操作の中だけであり、返した参照が有効な間
+      // (`&self` の借用期間) はツリーが変更されることはない。
+      let kv = unsafe { &*kv };
+      (depth, &kv.key, &kv.value)
+    })
+  }
+
+  /// 深さ `depth` (ルートを 0 とする) にある内部ノードが持つ分岐キーをキーの昇順で返します。指定された深さ
+  /// のノードが葉であるか、ツリーの深さを超えている場合は空の `Vec` を返します。
+  ///
+  pub fn node_pivot_keys(&self, depth: usize) -> Vec<KEY> {
+    let mut keys = Vec::new();
+    self.root.borrow().pivot_keys(depth, &mut keys);
+    keys
+  }
+
+  /// 指定されたキーに関連付けられた値への参照を返します。値が存在しない場合は None を返します。
+  ///
+  pub fn get<Q>(&self, key: &Q) -> Option<&VALUE>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut node = self.root.as_ptr() as *const Node<KEY, VALUE, S>;
+    loop {
+      // SAFETY: ノードが可変に借用されるのは `&mut self` を受け取る操作の中だけであり、返した参照が有効な間
+      // (`&self` の借用期間) はツリーが変更されることはない。
+      let n = unsafe { &*node };
+      match n.find_index(key) {
+        Ok(i) => {
+          n.keys[i].touch();
+          return Some(&n.keys[i].value);
+        }
+        Err(i) if !n.is_leaf => node = n.pivots[i].as_ptr(),
+        Err(_) => return None,
+      }
+    }
+  }
+
+  /// 指定されたキーのエントリがキーの昇順で何番目 (0 始まり) にあるかを返します。キーが存在しない場合は
+  /// None を返します。各ノードが保持する部分木のエントリ数を使用し、探索経路の左側にある部分木を数え上げずに
+  /// O(log n) で求めます。参照回数は更新されません。
+  ///
+  pub fn rank<Q>(&self, key: &Q) -> Option<usize>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut node = self.root.as_ptr() as *const Node<KEY, VALUE, S>;
+    let mut rank = 0;
+    loop {
+      // SAFETY: ノードが可変に借用されるのは `&mut self` を受け取る操作の中だけであり、返した参照が有効な間
+      // (`&self` の借用期間) はツリーが変更されることはない。
+      let n = unsafe { &*node };
+      let (found, i) = match n.find_index(key) {
+        Ok(i) => (true, i),
+        Err(i) => (false, i),
+      };
+      rank += i;
+      if !n.is_leaf {
+        rank += n.pivots[..i]
+          .iter()
+          .map(|child| child.borrow().subtree_size)
+          .sum::<usize>();
+      }
+      match (found, n.is_leaf) {
+        (true, true) => return Some(rank),
+        (true, false) => return Some(rank + n.pivots[i].borrow().subtree_size),
+        (false, false) => node = n.pivots[i].as_ptr(),
+        (false, true) => return None,
+      }
+    }
+  }
+
+  /// キーの昇順で `rank` 番目 (0 始まり) のエントリを返します。`rank` がエントリ数以上の場合は None を返し
+  /// ます。各ノードが保持する部分木のエントリ数を使用して該当する子ノードを選びながら降りるため O(log n) で
+  /// 動作します。参照回数は更新されません。
+  ///
+  pub fn select(&self, rank: usize) -> Option<(&KEY, &VALUE)> {
+    if rank >= self.size() {
+      return None;
+    }
+    let mut node = self.root.as_ptr() as *const Node<KEY, VALUE, S>;
+    let mut rank = rank;
+    loop {
+      // SAFETY: ノードが可変に借用されるのは `&mut self` を受け取る操作の中だけであり、返した参照が有効な間
+      // (`&self` の借用期間) はツリーが変更されることはない。
+      let n = unsafe { &*node };
+      if n.is_leaf {
+        let kv = n.keys.get(rank)?;
+        return Some((&kv.key, &kv.value));
+      }
+      let mut next = None;
+      for (i, child) in n.pivots.iter().enumerate() {
+        let size = child.borrow().subtree_size;
+        if rank < size {
+          next = Some(child.as_ptr());
+          break;
+        }
+        rank -= size;
+        match n.keys.get(i) {
+          Some(kv) if rank == 0 => return Some((&kv.key, &kv.value)),
+          Some(_) => rank -= 1,
+          None => return None,
+        }
+      }
+      node = next? as *const Node<KEY, VALUE, S>;
+    }
+  }
+
+  /// ツリーに存在する `key` のキーの順で左隣 (`SiblingDirection::Left`) または右隣 (`SiblingDirection::Right`)
+  /// のエントリを返します。`key` が存在しない場合や、隣のエントリが存在しない場合は None を返します。参照回数
+  /// は更新されません。
+  ///
+  /// 隣のエントリは多くの場合 `key` と同じ葉ノードにあります。葉ノードの端にある場合の隣は探索経路上の祖先
+  /// ノードのキーとなりますが、ノードは親への参照を持たないため、ルートからの探索の途中で候補となる祖先の
+  /// キーを記録しておきます。いずれの場合も `key` の探索 1 回で求めることができ、前後のキーを改めてルートから
+  /// 探索する必要はありません。
+  ///
+  pub fn sibling_key<Q>(&self, key: &Q, direction: SiblingDirection) -> Option<(&KEY, &VALUE)>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let entry = |kv: &KeyVal<KEY, VALUE>| {
+      // SAFETY: ノードが可変に借用されるのは `&mut self` を受け取る操作の中だけであり、返した参照が有効な間
+      // (`&self` の借用期間) はツリーが変更されることはない。
+      let kv = unsafe { &*(kv as *const KeyVal<KEY, VALUE>) };
+      (&kv.key, &kv.value)
+    };
+    let left = direction == SiblingDirection::Left;
+    let mut ancestor = None;
+    let mut node = self.root.clone();
+    loop {
+      let next = {
+        let n = node.borrow();
+        match n.find_index(key) {
+          Ok(i) if n.is_leaf => {
+            let sibling = if left { i.checked_sub(1) } else { Some(i + 1) };
+            return match sibling.and_then(|j| n.keys.get(j)) {
+              Some(kv) => Some(entry(kv)),
+              None => ancestor,
+            };
+          }
+          Ok(i) => {
+            // 内部ノードのキーの隣は左の部分木の最も右、または右の部分木の最も左のエントリ
+            let mut child = n.pivots[if left { i } else { i + 1 }].clone();
+            loop {
+              let next = {
+                let c = child.borrow();
+                if c.is_leaf {
+                  let kv = if left { c.keys.last() } else { c.keys.first() };
+                  return kv.map(entry);
+                }
+                c.pivots[if left { c.keys.len() } else { 0 }].clone()
+              };
+              child = next;
+            }
+          }
+          Err(_) if n.is_leaf => return None,
+          Err(i) => {
+            let candidate = if left { i.checked_sub(1) } else { Some(i) };
+            if let Some(kv) = candidate.and_then(|j| n.keys.get(j)) {
+              ancestor = Some(entry(kv));
+            }
+            n.pivots[i].clone()
+          }
+        }
+      };
+      node = next;
+    }
+  }
+
+  /// 指定されたキーに関連付けられた値を更新するためのガードを返します。値が存在しない場合は None を返します。
+  /// ガードは値を格納しているノードを可変に借用しており、ガードが破棄されるまでその借用が保持されます。キー
+  /// は変更できないため、値をその場で書き換えてもツリーの順序は損なわれません。
+  ///
+  /// `get_mut` による値の変更は監査ログに記録されません。
+  ///
+  pub fn get_mut<Q>(&mut self, key: &Q) -> Option<ValueGuardMut<'_, VALUE>>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut node = Rc::as_ptr(&self.root);
+    loop {
+      // SAFETY: ノードは `Rc` によってツリーに保持されており、`&mut self` の借用期間 (返したガードが有効な間)
+      // はツリーの構造が変更されてノードが解放されることはない。
+      let cell = unsafe { &*node };
+      let (found, is_leaf) = {
+        let n = cell.borrow();
+        (n.find_index(key), n.is_leaf)
+      };
+      match found {
+        Ok(i) => {
+          let guard = core::cell::RefMut::map(cell.borrow_mut(), |n| {
+            n.keys[i].touch();
+            &mut n.keys[i].value
+          });
+          return Some(ValueGuardMut(guard));
+        }
+        Err(i) if !is_leaf => node = Rc::as_ptr(&cell.borrow().pivots[i]),
+        Err(_) => return None,
+      }
+    }
+  }
+
+  /// 指定されたキーに関連付けられた値の複製を返します。値が存在しない場合は None を返します。
+  ///
+  pub fn get_cloned<Q>(&self, key: &Q) -> Option<VALUE>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.get(key).cloned()
+  }
+
+  /// 指定されたキーがこのツリーに存在する場合に true を返します。参照回数は更新されません。
+  ///
+  pub fn contains_key<Q>(&self, key: &Q) -> bool
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.root.borrow().lookup_entry(key, |_| ()).is_some()
+  }
+
+  /// ツリーに Key-Value ペアを挿入します。既に同じキーが存在する場合は新しい値で置き換えて古い値を返します。
+  ///
+  /// ツリーの構造が壊れていることを検出した場合は panic します。エラーとして扱う場合は `try_put` を使用して
+  /// ください。
+  ///
+  pub fn put(&mut self, key: KEY, value: VALUE) -> Option<VALUE> {
+    self.try_put(key, value).unwrap()
+  }
+
+  /// `put` と同様に Key-Value ペアを挿入します。挿入の過程でノードのキー数や子ノードの数がツリーの不変条件を
+  /// 満たしていないことを検出した場合は `Error::InvariantViolation` を返します。
+  ///
+  pub fn try_put(&mut self, key: KEY, value: VALUE) -> Result<Option<VALUE>> {
+    #[cfg(feature = "audit")]
+    let audited = Op::Put(key.clone(), value.clone());
+    #[cfg(feature = "bloom")]
+    if let Some(bloom) = &mut self.bloom {
+      bloom.insert(&key);
+    }
+    let (prop, result) = self
+      .root
+      .borrow_mut()
+      .upsert(key, value, &mut self.reserved_nodes)?;
+    if let Some((keyval, pivot)) = prop {
+      let mut new_root = Node::reserved(false, &mut self.reserved_nodes);
+      new_root.keys.push(keyval);
+      new_root.pivots.push(self.root.clone());
+      new_root.pivots.push(Rc::new(RefCell::new(pivot)));
+      new_root.update_size();
+      self.root = Rc::new(RefCell::new(new_root));
+    }
+    #[cfg(feature = "audit")]
+    self.record_audit(audited, result.clone());
+    Ok(result)
+  }
+
+  /// 指定されたキーのエントリを削除し、その値を返します。ツリーの構造が壊れていることを検出した場合は panic
+  /// します。エラーとして扱う場合は `try_delete` を使用してください。
+  ///
+  pub fn delete<Q>(&mut self, key: &Q) -> Option<VALUE>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.try_delete(key).unwrap()
+  }
+
+  /// `delete` と同様にエントリを削除します。削除に伴う再配分やマージの過程でツリーの不変条件が満たされて
+  /// いないことを検出した場合は `Error::InvariantViolation` を返します。
+  ///
+  pub fn try_delete<Q>(&mut self, key: &Q) -> Result<Option<VALUE>>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let removed = self.root.borrow_mut().delete(key)?;
+    if !self.root.borrow().is_leaf && self.root.borrow().pivots.len() == 1 {
+      let new_root = self.root.borrow().pivots[0].clone();
+      self.root = new_root;
+    }
+    #[cfg(feature = "audit")]
+    if let Some(kv) = &removed {
+      self.record_audit(Op::Delete(kv.key.clone()), Some(kv.value.clone()));
+    }
+    Ok(removed.map(|kv| kv.value))
+  }
+
+  /// 最小のキーのエントリをツリーから取り除いて返します。ツリーが空の場合は None を返します。
+  ///
+  pub fn pop_first(&mut self) -> Option<(KEY, VALUE)> {
+    let key = self.select(0)?.0.clone();
+    self.delete(&key).map(|value| (key, value))
+  }
+
+  /// 指定された述語が `false` を返すすべての Key-Value ペアをこのツリーから削除します。削除したエントリの
+  /// 数を返します。
+  ///
+  pub fn retain<F>(&mut self, mut f: F) -> usize
+  where
+    F: FnMut(&KEY, &VALUE) -> bool,
+  {
+    let mut removals = Vec::new();
+    self.root.borrow().for_each(&mut |kv| {
+      if !f(&kv.key, &kv.value) {
+        removals.push(kv.key.clone());
+      }
+      true
+    });
+    for key in removals.iter() {
+      self.delete(key);
+    }
+    removals.len()
+  }
+
+  /// 指定されたキー以上のすべてのエントリをこのツリーから取り除き、それらを格納した新しいツリーとして返し
+  /// ます。呼び出し後のこのツリーには `key` 未満のエントリのみが残ります。
+  ///
+  /// ルートから `key` の位置までの経路に沿ってノードを分割し、分割で生じたキー数の不足を境界の枝に沿って
+  /// 修復するため O(log n) で動作します。
+  ///
+  pub fn split_off<Q>(&mut self, key: &Q) -> BTree<KEY, VALUE, S>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let right = self.root.borrow_mut().split_off(key);
+    let mut right = BTree {
+      root: Rc::new(RefCell::new(right)),
+      reserved_nodes: 0,
+      #[cfg(feature = "audit")]
+      audit: Vec::new(),
+      #[cfg(feature = "bloom")]
+      bloom: None,
+    };
+    self.fix_border(false).unwrap();
+    right.fix_border(true).unwrap();
+    right
+  }
+
+  /// `split_off` で分割されたツリーの境界の枝を修復します。キーを持たない内部ノードのルートは唯一の子に
+  /// 置き換えられます。
+  ///
+  fn fix_border(&mut self, leftmost: bool) -> Result<()> {
+    self.collapse_root();
+    if !self.root.borrow().is_leaf {
+      if leftmost {
+        self.root.borrow_mut().fix_left_border()?;
+      } else {
+        self.root.borrow_mut().fix_right_border()?;
+      }
+      self.collapse_root();
+    }
+    Ok(())
+  }
+
+  fn collapse_root(&mut self) {
+    loop {
+      let child = {
+        let root = self.root.borrow();
+        if root.is_leaf || !root.keys.is_empty() {
+          break;
+        }
+        root.pivots[0].clone()
+      };
+      self.root = child;
+    }
+  }
+
+  /// このツリーのすべての Key-Value をキーの昇順で列挙するイテレータを返します。ノードは `RefCell` で保持さ
+  /// れているため、列挙される要素はキーと値の複製です。
+  ///
+  pub fn iter(&self) -> Iter<'_, KEY, VALUE, S> {
+    Iter::new(self)
+  }
+
+  /// 指定された範囲に含まれるキーを持つ Key-Value をキーの昇順で列挙します。範囲の下限の位置まではルートから
+  /// 直接辿るため O(log n) で最初のエントリに到達します。
+  ///
+  pub fn range<R: RangeBounds<KEY>>(&self, range: R) -> impl Iterator<Item = (KEY, VALUE)> + '_ {
+    self
+      .range_entries(range.start_bound(), range.end_bound().cloned())
+      .map(|kv| (kv.key.clone(), kv.value.clone()))
+  }
+
+  /// 指定された範囲に含まれるキーを持つエントリについて、キーの昇順に `f` で値を畳み込みます。範囲内の
+  /// エントリを `Vec` などに集めることなく、合計や最小値、最大値、件数などの集計を 1 回の走査で行うことが
+  /// できます。キーと値は複製されずに参照として `f` に渡されます。参照回数は更新されません。
+  ///
+  pub fn fold_range<R, B, F>(&self, range: R, init: B, mut f: F) -> B
+  where
+    R: RangeBounds<KEY>,
+    F: FnMut(B, (&KEY, &VALUE)) -> B,
+  {
+    self
+      .range_entries(range.start_bound(), range.end_bound().cloned())
+      .fold(init, |acc, kv| f(acc, (&kv.key, &kv.value)))
+  }
+
+  /// 指定された範囲に含まれるキーを持つエントリのキーと値の参照をキーの昇順で `f` に渡します。参照回数は更新
+  /// されません。
+  ///
+  pub fn for_each_range<R, F>(&self, range: R, mut f: F)
+  where
+    R: RangeBounds<KEY>,
+    F: FnMut(&KEY, &VALUE),
+  {
+    self
+      .range_entries(range.start_bound(), range.end_bound().cloned())
+      .for_each(|kv| f(&kv.key, &kv.value))
+  }
+
+  /// `start` から `end` までの範囲に含まれるエントリへの参照をキーの昇順で列挙します。
+  ///
+  fn range_entries(
+    &self,
+    start: Bound<&KEY>,
+    end: Bound<KEY>,
+  ) -> impl Iterator<Item = &KeyVal<KEY, VALUE>> {
+    let mut iter = Iter::seek(self, start);
+    core::iter::from_fn(move || iter.next_entry()).take_while(move |kv| match &end {
+      Bound::Included(end) => kv.key <= *end,
+      Bound::Excluded(end) => kv.key < *end,
+      Bound::Unbounded => true,
+    })
+  }
+
+  /// キーの昇順にエントリのキーと値の参照を列挙し、`pred` が最初に `false` を返した時点で終了するイテレータを
+  /// 返します。終了時に走査中のノードへの参照を破棄するため、残りの部分木は参照されません。キーと値は複製
+  /// されず、参照回数は更新されません。
+  ///
+  pub fn iter_while<'a, P>(&'a self, pred: P) -> impl Iterator<Item = (&'a KEY, &'a VALUE)> + 'a
+  where
+    P: Fn(&KEY, &VALUE) -> bool + 'a,
+  {
+    let mut iter = Iter::new(self);
+    core::iter::from_fn(move || {
+      let kv = iter.next_entry()?;
+      if pred(&kv.key, &kv.value) {
+        Some((&kv.key, &kv.value))
+      } else {
+        iter.stack.clear();
+        None
+      }
+    })
+  }
+
+  /// 各葉が持つキーの範囲 `(最小のキー, 最大のキー)` を左の葉から順に列挙します。ただし最も左の葉の最小値と
+  /// 最も右の葉の最大値は範囲が開いていることを表す `None` となります。データが葉にどのように分割されているか
+  /// を示すため、外部ソートの分割点の選択やノード間のデータの偏りの把握に使用できます。
+  ///
+  pub fn iter_key_ranges(&self) -> impl Iterator<Item = (Option<&KEY>, Option<&KEY>)> + '_ {
+    let mut leaves: Vec<&Node<KEY, VALUE, S>> = Vec::new();
+    let mut stack = vec![self.root.as_ptr() as *const Node<KEY, VALUE, S>];
+    while let Some(node) = stack.pop() {
+      // SAFETY: `Index` と同じく、ノードが可変に借用されるのは `&mut self` を受け取る操作の中だけであり、返した
+      // 参照が有効な間 (`&self` の借用期間) はツリーが変更されることはない。
+      let n = unsafe { &*node };
+      if n.is_leaf {
+        leaves.push(n);
+      } else {
+        stack.extend(
+          n.pivots
+            .iter()
+            .rev()
+            .map(|pivot| pivot.as_ptr() as *const _),
+        );
+      }
+    }
+    let last = leaves.len() - 1;
+    leaves.into_iter().enumerate().map(move |(i, leaf)| {
+      let min = if i == 0 {
+        None
+      } else {
+        leaf.keys.first().map(|kv| &kv.key)
+      };
+      let max = if i == last {
+        None
+      } else {
+        leaf.keys.last().map(|kv| &kv.key)
+      };
+      (min, max)
+    })
+  }
+
+  /// このツリーの階層構造をインデントされた複数行の文字列として返します。内部ノードは葉を 1 とした高さと
+  /// 分岐キーを、葉はそのキーの一覧を 1 行に表示します。
+  ///
+  pub fn display_tree(&self) -> String
+  where
+    KEY: Display,
+  {
+    let mut buffer = String::new();
+    self
+      .root
+      .borrow()
+      .display_tree(0, self.level(), &mut buffer);
+    buffer
+  }
+
+  /// このツリーの構造を Graphviz の DOT 言語で記述した文字列を返します。各ノードはレコード形式のラベルで描画
+  /// され、内部ノードは分岐キーの間に子ノードへのポートを並べた角の四角いレコード (`shape=record`)、葉は
+  /// `key: value` を並べた角の丸いレコード (`shape=Mrecord`) となります。各辺は親のポートから子ノードへ向かい、
+  /// その子ノードに振り分けられるキーの範囲が表示されます。出力を `dot -Tpng` に渡すとツリーの図を生成できます。
+  ///
+  pub fn to_dot(&self) -> String
+  where
+    KEY: Display,
+    VALUE: Display,
+  {
+    let mut buffer = String::from("digraph BTree {\n  node [fontname=\"monospace\"];\n");
+    self.root.borrow().to_dot(&mut 0, &mut buffer);
+    buffer.push_str("}\n");
+    buffer
+  }
+
+  /// キーの昇順に並べたすべての Key-Value ペアから FNV-1a による 64 ビットのフィンガープリントを算出します。
+  /// 同じ内容のツリーは内部構造やプロセスに関係なく常に同じ値となるため、複製間の一貫性の確認や変更の検出
+  /// に使用できます。
+  ///
+  /// 整数や長さは `Fnv1a` がリトルエンディアンの固定幅 (`usize` と `isize` は 64 ビット) に変換してから
+  /// ハッシュするため、ポインタの幅やバイト順の異なるターゲットの間でも同じ値となります。ただし `Hash` の実装
+  /// が `Hasher::write` にネイティブなバイト表現を直接渡す型では、その型の値はターゲットに依存します。
+  ///
+  pub fn fingerprint(&self) -> u64
+  where
+    KEY: Hash,
+    VALUE: Hash,
+  {
+    let mut hasher = Fnv1a::new();
+    self.hash(&mut hasher);
+    hasher.finish()
+  }
+
+  /// 指定されたキーを `shards` 個の分散したツリーのどれに割り当てるべきかを `[0, shards)` のインデックスで
+  /// 返します。キーの FNV-1a ハッシュ値に Jump Consistent Hash を適用するため、キーはシャードに均等に分散し、
+  /// シャードの数を `n` から `n + 1` に増やしたときに割り当てが変わるのは約 `1 / (n + 1)` のキーだけで
+  /// あり、それらはすべて新しいシャードに移動します。結果はプロセスやバージョンをまたいで変わりません。
+  ///
+  /// `shards` が 0 の場合は panic します。
+  ///
+  pub fn consistent_hash_shard(key: &KEY, shards: usize) -> usize
+  where
+    KEY: Hash,
+  {
+    assert!(shards > 0, "shards must be greater than 0");
+    let mut hasher = Fnv1a::new();
+    key.hash(&mut hasher);
+    let mut hash = hasher.finish();
+    let (mut bucket, mut next) = (0u64, 0u64);
+    while next < shards as u64 {
+      bucket = next;
+      hash = hash.wrapping_mul(2_862_933_555_777_941_757).wrapping_add(1);
+      next = ((bucket + 1) as f64 * ((1u64 << 31) as f64 / ((hash >> 33) + 1) as f64)) as u64;
+    }
+    bucket as usize
+  }
+
+  /// 指定されたツリーのすべてのエントリをこのツリーに統合します。同じキーが両方に存在する場合は `other`
+  /// の値が採用されます。
+  ///
+  pub fn merge(&mut self, other: BTree<KEY, VALUE, S>) {
+    self.merge_with(other, |_, _, value| value);
+  }
+
+  /// 指定されたツリーのすべてのエントリをこのツリーに統合します。同じキーが両方に存在する場合は、キーとこの
+  /// ツリーの値、`other` の値を引数に `resolve` を呼び出し、その結果を値とします。
+  ///
+  /// 両方のツリーを同時に昇順で走査して整列済みのエントリ列を作り、ボトムアップに再構築するため、エントリ数
+  /// をそれぞれ n, m として O(n + m) で動作します。
+  ///
+  pub fn merge_with<F>(&mut self, other: BTree<KEY, VALUE, S>, mut resolve: F)
+  where
+    F: FnMut(&KEY, VALUE, VALUE) -> VALUE,
+  {
+    let mut entries = Vec::new();
+    let mut left = self.iter().peekable();
+    let mut right = other.iter().peekable();
+    loop {
+      let order = match (left.peek(), right.peek()) {
+        (Some((k1, _)), Some((k2, _))) => k1.cmp(k2),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => break,
+      };
+      let keyval = match order {
+        Ordering::Less => left.next().map(|(key, value)| KeyVal::new(key, value)),
+        Ordering::Greater => right.next().map(|(key, value)| KeyVal::new(key, value)),
+        Ordering::Equal => {
+          let (key, v1) = left.next().unwrap();
+          let (_, v2) = right.next().unwrap();
+          let value = resolve(&key, v1, v2);
+          Some(KeyVal::new(key, value))
+        }
+      };
+      entries.extend(keyval);
+    }
+    drop(left);
+    self.root = BTree::bulk_load(entries).root;
+    #[cfg(feature = "bloom")]
+    self.refill_bloom_filter();
+  }
+
+  /// 値が `threshold` 未満のすべてのエントリを削除し、削除したエントリの数を返します。キャッシュの追い出し
+  /// や出現頻度による枝刈りに使用できます。
+  ///
+  pub fn threshold_delete(&mut self, threshold: VALUE) -> usize
+  where
+    VALUE: PartialOrd,
+  {
+    self.retain(|_, value| *value >= threshold)
+  }
+
+  /// 指定された Key-Value ペアのそれぞれについて、キーが存在しないか現在の値が新しい値より小さい場合にのみ
+  /// 値を更新し、実際に更新したエントリの数を返します。値をタイムスタンプやバージョン番号とした CRDT の
+  /// Last-Writer-Wins レジスタのように、値が前進する方向にのみ更新される用途を想定しています。
+  ///
+  pub fn batch_put_if_greater(&mut self, pairs: &[(KEY, VALUE)]) -> usize
+  where
+    VALUE: PartialOrd,
+  {
+    let mut updated = 0;
+    for (key, value) in pairs.iter() {
+      if self.get(key).is_none_or(|current| current < value) {
+        self.put(key.clone(), value.clone());
+        updated += 1;
+      }
+    }
+    updated
+  }
+
+  /// `pairs` の各 Key-Value をツリーに挿入します。既に同じキーが存在する場合は既存の値と新しい値を `combine`
+  /// で結合した値に置き換えます。新たに挿入したエントリと、結合によって値が変化したエントリの数を返します。
+  /// CRDT のカウンタ表のように、同じキーへの更新を上書きではなく合成する「マージ挿入」に使用します。
+  ///
+  pub fn batch_upsert<F>(&mut self, pairs: &[(KEY, VALUE)], combine: F) -> usize
+  where
+    VALUE: PartialEq,
+    F: Fn(VALUE, VALUE) -> VALUE,
+  {
+    let mut modified = 0;
+    for (key, value) in pairs.iter() {
+      let merged = match self.get(key) {
+        Some(current) => {
+          let merged = combine(current.clone(), value.clone());
+          if merged == *current {
+            continue;
+          }
+          merged
+        }
+        None => value.clone(),
+      };
+      self.put(key.clone(), merged);
+      modified += 1;
+    }
+    modified
+  }
+
+  /// キーの昇順で値が `target` 以上となる最初のエントリの順位 (0 始まり) を返します。該当するエントリが存在
+  /// しない場合はエントリ数を返します。値がキーに対して単調非減少であれば、これは値による二分探索の挿入位置
+  /// と一致します。
+  ///
+  /// `is_monotone` が true を返すツリーでは、ルートから各ノードの値を `target` と比較して子ノードを選びながら
+  /// 降り、選ばなかった左側の子ノードの部分木のエントリ数を足し合わせることで O(log n) で順位を求めます。値が
+  /// 単調でない場合は先頭から該当エントリまでを走査する O(rank) の方法で「最初に `target` 以上となる
+  /// エントリ」を求めます。いずれの場合も単調性の確認のためにすべての値を 1 回ずつ比較するため、単調である
+  /// ことが分かっている場合は `bisect_by_monotone_value` を使用してください。
+  ///
+  pub fn bisect_by_value(&self, target: VALUE) -> usize
+  where
+    VALUE: PartialOrd,
+  {
+    if self.is_monotone() {
+      return self.bisect_by_monotone_value(&target);
+    }
+    let mut rank = 0;
+    self.root.borrow().for_each(&mut |kv| {
+      if kv.value >= target {
+        false
+      } else {
+        rank += 1;
+        true
+      }
+    });
+    rank
+  }
+
+  /// 値がキーに対して単調非減少であることを前提に、キーの昇順で値が `target` 以上となる最初のエントリの順位を
+  /// O(log n) で返します。値が単調でない場合の結果は意味を持ちません。参照回数は更新されません。
+  ///
+  pub fn bisect_by_monotone_value(&self, target: &VALUE) -> usize
+  where
+    VALUE: PartialOrd,
+  {
+    let mut node = self.root.as_ptr() as *const Node<KEY, VALUE, S>;
+    let mut rank = 0;
+    loop {
+      // SAFETY: ノードが可変に借用されるのは `&mut self` を受け取る操作の中だけであり、このメソッドを抜ける
+      // まで (`&self` の借用期間) はツリーが変更されることはない。
+      let n = unsafe { &*node };
+      let i = n.keys.partition_point(|kv| kv.value < *target);
+      rank += i;
+      if n.is_leaf {
+        return rank;
+      }
+      rank += n.pivots[..i]
+        .iter()
+        .map(|child| child.borrow().subtree_size)
+        .sum::<usize>();
+      node = n.pivots[i].as_ptr();
+    }
+  }
+
+  /// キーの昇順に並べた値が単調非減少であるかを判定します。隣り合うエントリの値を順に比較し、最初に減少
+  /// している箇所、または比較できない値の組が見つかった時点で false を返します。
+  ///
+  pub fn is_monotone(&self) -> bool
+  where
+    VALUE: PartialOrd,
+  {
+    let mut iter = Iter::new(self);
+    let Some(mut previous) = iter.next_entry() else {
+      return true;
+    };
+    while let Some(kv) = iter.next_entry() {
+      if previous
+        .value
+        .partial_cmp(&kv.value)
+        .is_none_or(|o| o == Ordering::Greater)
+      {
+        return false;
+      }
+      previous = kv;
+    }
+    true
+  }
+
+  /// 値を昇順に並べたときに `rank` 番目 (0 始まり) となるエントリのキーと値を返します。`rank` がエントリ数
+  /// 以上の場合は None を返します。等しい値を持つエントリはキーの昇順に並べたものとみなします。
+  ///
+  /// 値による索引を持たないため、すべてのエントリへの参照を集めてクイックセレクト
+  /// (`select_nth_unstable_by`) で選択する平均 O(n) の操作となり、参照の分だけ O(n) の補助領域を使用します。
+  /// 参照回数は更新されません。
+  ///
+  pub fn select_by_value_rank(&self, rank: usize) -> Option<(&KEY, &VALUE)>
+  where
+    VALUE: Ord,
+  {
+    let mut entries = self.entry_refs();
+    if rank >= entries.len() {
+      return None;
+    }
+    let (_, nth, _) = entries.select_nth_unstable_by(rank, |a, b| {
+      a.value.cmp(&b.value).then_with(|| a.key.cmp(&b.key))
+    });
+    Some((&nth.key, &nth.value))
+  }
+
+  /// キーの順に連続するエントリのうち、値が `value_threshold` 未満のものの連なり (ラン) を 1 つの `Vec` に
+  /// まとめて列挙します。値が `value_threshold` 以上のエントリ (NaN のように比較できない値を含む) は区切りと
+  /// して扱われ、どのランにも含まれません。区切りが連続する場合に空のランは生成されません。イベントログの
+  /// ストリーム解析のように、閾値を超える値で区切られた区間ごとに処理する場合に使用します。参照回数は更新
+  /// されません。
+  ///
+  pub fn iter_by_value_chunk(
+    &self,
+    value_threshold: VALUE,
+  ) -> impl Iterator<Item = Vec<(&KEY, &VALUE)>> + '_
+  where
+    VALUE: PartialOrd,
+  {
+    let mut entries = self.entry_refs().into_iter().peekable();
+    core::iter::from_fn(move || {
+      let below =
+        |kv: &&KeyVal<KEY, VALUE>| kv.value.partial_cmp(&value_threshold) == Some(Ordering::Less);
+      while entries.next_if(|kv| !below(kv)).is_some() {}
+      let mut run = Vec::new();
+      while let Some(kv) = entries.next_if(below) {
+        run.push((&kv.key, &kv.value));
+      }
+      (!run.is_empty()).then_some(run)
+    })
+  }
+
+  /// すべてのエントリへの参照をキーの昇順で返します。参照回数は更新されません。
+  ///
+  fn entry_refs(&self) -> Vec<&KeyVal<KEY, VALUE>> {
+    let mut entries = Vec::with_capacity(self.size());
+    self.root.borrow().for_each(&mut |kv| {
+      // SAFETY: ノードが可変に借用されるのは `&mut self` を受け取る操作の中だけであり、返した参照が有効な間
+      // (`&self` の借用期間) はツリーが変更されることはない。
+      entries.push(unsafe { &*(kv as *const KeyVal<KEY, VALUE>) });
+      true
+    });
+    entries
+  }
+
+  /// 値を頻度や重みとみなしたときの分布の不平等さを表すジニ係数を返します。0 はすべての値が等しいことを、1 に
+  /// 近いほど重みが少数のエントリに偏っていることを表します。`n` 個のエントリのうち 1 つだけが正の値を持つ
+  /// 場合の係数は `(n - 1) / n` となります。
+  ///
+  /// 値を昇順に並べた `x_1, ..., x_n` から `2 Σ i x_i / (n Σ x_i) - (n + 1) / n` で算出します。値は非負である
+  /// ことを前提とし、ツリーが空であるか値の合計が 0 の場合は 0 を返します。
+  ///
+  pub fn compute_gini_coefficient(&self) -> f64
+  where
+    VALUE: Into<f64>,
+  {
+    let mut values = self
+      .iter()
+      .map(|(_, value)| value.into())
+      .collect::<Vec<f64>>();
+    values.sort_by(f64::total_cmp);
+    let n = values.len() as f64;
+    let (mut sum, mut weighted) = (0.0, 0.0);
+    for (i, value) in values.iter().enumerate() {
+      sum += value;
+      weighted += (i + 1) as f64 * value;
+    }
+    if sum <= 0.0 {
+      return 0.0;
+    }
+    2.0 * weighted / (n * sum) - (n + 1.0) / n
+  }
+
+  /// キーの順に連続する `window` 個のエントリからなるスライディングウィンドウごとに値の `p` パーセンタイルを
+  /// 求め、`(ウィンドウの最後のキー, パーセンタイル)` の列を返します。直近 100 件のレイテンシの 99 パーセン
+  /// タイルのような SLA の監視に使用します。エントリが `window` 個に満たない場合は空の `Vec` を返します。
+  ///
+  /// パーセンタイルはウィンドウ内の値を昇順に並べたときの位置 `p / 100 * (window - 1)` を前後の値から線形補間
+  /// して算出します。ウィンドウ内の値は整列済みの `Vec` として保持し、ウィンドウを 1 つ進めるごとに二分探索で
+  /// 古い値を取り除いて新しい値を挿入します。
+  ///
+  /// `p` が 0 以上 100 以下でない場合や `window` が 0 の場合は panic します。
+  ///
+  pub fn rolling_percentile(&self, p: f64, window: usize) -> Vec<(KEY, f64)>
+  where
+    VALUE: Into<f64>,
+  {
+    assert!(
+      (0.0..=100.0).contains(&p),
+      "the percentile must be in [0, 100], but {p} was specified"
+    );
+    assert!(window > 0, "the window size must be positive");
+    let entries = self
+      .iter()
+      .map(|(key, value)| (key, value.into()))
+      .collect::<Vec<(KEY, f64)>>();
+    let position = p / 100.0 * (window - 1) as f64;
+    let lower = position as usize;
+    let fraction = position - lower as f64;
+    let mut sorted: Vec<f64> = Vec::with_capacity(window + 1);
+    let mut percentiles = Vec::with_capacity((entries.len() + 1).saturating_sub(window));
+    for (i, (key, value)) in entries.iter().enumerate() {
+      let at = sorted.partition_point(|v| v.total_cmp(value).is_lt());
+      sorted.insert(at, *value);
+      if i >= window {
+        let old = entries[i - window].1;
+        let at = sorted.partition_point(|v| v.total_cmp(&old).is_lt());
+        sorted.remove(at);
+      }
+      if i + 1 >= window {
+        let upper = sorted[(lower + 1).min(window - 1)];
+        let percentile = sorted[lower] + (upper - sorted[lower]) * fraction;
+        percentiles.push((key.clone(), percentile));
+      }
+    }
+    percentiles
+  }
+
+  /// キーの順に連続する `window` 個のエントリからなるスライディングウィンドウごとに値の合計を求め、
+  /// `(ウィンドウの最後のキー, 合計)` の列を返します。結果は `size() - window + 1` 個となり、エントリが
+  /// `window` 個に満たない場合は空の `Vec` を返します。
+  ///
+  /// ウィンドウごとに合計し直す素朴な方法は O(n * window) となります。累計から古い値を引いていく一般的な
+  /// O(n) の方法は減算を必要とするため、ここではウィンドウの古い側の値を後方からの部分和として保持し、新しい
+  /// 側の値の合計と足し合わせることで加算のみで償却 O(n) の計算を行います。値は常に古いものから順に加算され
+  /// ます。
+  ///
+  /// `window` が 0 の場合は panic します。
+  ///
+  pub fn sliding_window_sum(&self, window: usize) -> Vec<(KEY, VALUE)>
+  where
+    VALUE: core::ops::Add<Output = VALUE> + Copy + Default,
+  {
+    assert!(window > 0, "the window size must be positive");
+    let entries = self.iter().collect::<Vec<_>>();
+    let mut sums = Vec::with_capacity((entries.len() + 1).saturating_sub(window));
+    // ウィンドウの古い側 [start, back_start) の後方からの部分和で、末尾が start からの合計となる
+    let mut front: Vec<VALUE> = Vec::with_capacity(window);
+    let mut back_start = 0;
+    let mut back_sum = VALUE::default();
+    for (i, (key, value)) in entries.iter().enumerate() {
+      back_sum = back_sum + *value;
+      if i + 1 < window {
+        continue;
+      }
+      let start = i + 1 - window;
+      if front.is_empty() {
+        debug_assert_eq!(start, back_start);
+        let mut acc = VALUE::default();
+        for (_, v) in entries[start..=i].iter().rev() {
+          acc = *v + acc;
+          front.push(acc);
+        }
+        back_start = i + 1;
+        back_sum = VALUE::default();
+      }
+      sums.push((key.clone(), *front.last().unwrap() + back_sum));
+      front.pop();
+    }
+    sums
+  }
+
+  /// `min` 以上 `max` 以下の整数のキー空間を `bits` 個の等幅の区間に分割し、ツリーにキーが存在する区間の
+  /// ビットを立てたビットマップを返します。区間 `i` はおおよそ `min + i * (max - min) / bits` から始まる範囲
+  /// で、そのビットは `i / 64` 番目のワードの下位から `i % 64` 番目に配置されます。ビットマップは
+  /// `ceil(bits / 64)` ワードからなります。
+  ///
+  /// 区間の幅より細かい情報は失われるため、キーの分布の概観や「おおよそこの範囲にキーは存在するか」という判定
+  /// に使用します。
+  ///
+  pub fn compute_coverage_bitmap(&self, min: KEY, max: KEY, bits: usize) -> Vec<u64>
+  where
+    KEY: Into<i128>,
+  {
+    let mut bitmap = vec![0u64; bits.div_ceil(64)];
+    let (lower, upper) = (min.clone().into(), max.clone().into());
+    if bits == 0 || lower > upper {
+      return bitmap;
+    }
+    let span = upper.abs_diff(lower);
+    for (key, _) in self
+      .iter()
+      .skip_while(|(k, _)| *k < min)
+      .take_while(|(k, _)| *k <= max)
+    {
+      let bit = bucket_index(key.into().abs_diff(lower), span, bits);
+      bitmap[bit / 64] |= 1 << (bit % 64);
+    }
+    bitmap
+  }
+
+  /// 指定されたキーの値が `get` または `Index` によって参照された回数を返します。このメソッド自体は参照回数
+  /// に含まれません。
+  ///
+  pub fn access_count<Q>(&self, key: &Q) -> Option<u64>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.root.borrow().lookup_entry(key, |kv| kv.accesses.get())
+  }
+
+  /// このツリーを、参照回数が `access_threshold` を超えるエントリからなる "hot" なツリーと、それ以外のエントリ
+  /// からなる "cold" なツリーに分割します。各エントリの参照回数は分割後のツリーに引き継がれます。
+  ///
+  pub fn hot_cold_split(
+    self,
+    access_threshold: u64,
+  ) -> (BTree<KEY, VALUE, S>, BTree<KEY, VALUE, S>) {
+    let (mut hot, mut cold) = (Vec::new(), Vec::new());
+    self.root.borrow().for_each(&mut |kv| {
+      if kv.accesses.get() > access_threshold {
+        hot.push(kv.clone());
+      } else {
+        cold.push(kv.clone());
+      }
+      true
+    });
+    (BTree::bulk_load(hot), BTree::bulk_load(cold))
+  }
+
+  /// すべてのノードのキーと子ノードの `Vec` を `shrink_to_fit` し、大量の削除の後などに残った余分な容量を
+  /// 解放します。`with_capacity` による容量の事前確保も打ち切られます。
+  ///
+  pub fn shrink_to_fit(&mut self) {
+    self.reserved_nodes = 0;
+    self.root.borrow_mut().shrink_to_fit();
+  }
+
+  /// `key` の値が `expected` と等しい場合に限りその値を `new` に置き換えて `Ok(expected)` を返します。値が
+  /// 異なる場合はツリーを変更せずに `Err(現在の値)` を返します。楽観的な並行更新の基本操作となる
+  /// compare-and-exchange を 1 回の探索で行います。
+  ///
+  /// 比較する現在の値が存在しないため、キーが存在しない場合は `Index` と同様にパニックします。キーが存在しない
+  /// 可能性がある場合は事前に `contains_key` で確認してください。
+  ///
+  pub fn compare_exchange_value<Q>(
+    &mut self,
+    key: &Q,
+    expected: VALUE,
+    new: VALUE,
+  ) -> core::result::Result<VALUE, VALUE>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+    VALUE: PartialEq,
+  {
+    #[cfg(feature = "audit")]
+    let mut audited_key = None;
+    let result = self.root.borrow_mut().update_entry(key, |kv| {
+      if kv.value == expected {
+        #[cfg(feature = "audit")]
+        {
+          audited_key = Some(kv.key.clone());
+        }
+        Ok(core::mem::replace(&mut kv.value, new.clone()))
+      } else {
+        Err(kv.value.clone())
+      }
+    });
+    let result = result.expect("key not found in BTree");
+    #[cfg(feature = "audit")]
+    if let (Ok(old_value), Some(key)) = (&result, audited_key) {
+      self.record_audit(Op::Put(key, new), Some(old_value.clone()));
+    }
+    result
+  }
+
+  /// このツリーの現時点の状態を、以後の変更の影響を受けない独立したツリーとして取得します。
+  ///
+  pub fn snapshot(&self) -> BTree<KEY, VALUE, S> {
+    self.clone()
+  }
+
+  /// このツリーの状態を `snapshot` で取得したスナップショットの状態に置き換えます。
+  ///
+  pub fn restore(&mut self, snapshot: BTree<KEY, VALUE, S>) {
+    self.root = snapshot.root;
+    #[cfg(feature = "bloom")]
+    self.refill_bloom_filter();
+  }
+
+  /// ノードのキー数に関係なくツリー全体を葉からボトムアップに再構築し、同じレベルのすべてのノードにキーが
+  /// 可能な限り均等かつ最大限に詰まるように再配分します。削除によって `S` 近くまでキーが減ったノードが多い
+  /// ツリーの順次走査の性能を改善するための保守操作です。各エントリの参照回数は維持されます。
+  ///
+  /// 削除時の再配分が下限を下回ったノードとその兄弟ノードだけを対象とするのに対し、この操作はすべてのエントリを
+  /// 移動するため、常に O(n) の時間と一時的な O(n) の領域を必要とします。また解体するノードと構築するノードが
+  /// メモリ上で離れているため、大きなツリーではキャッシュの効率が良くありません。
+  ///
+  pub fn force_rebalance(&mut self) {
+    let root = core::mem::replace(&mut self.root, Rc::new(RefCell::new(Node::new(true))));
+    let root = Rc::try_unwrap(root).map_or_else(|root| root.borrow().clone(), RefCell::into_inner);
+    let mut entries = Vec::with_capacity(root.size());
+    root.drain_into(&mut entries);
+    self.root = BTree::bulk_load(entries).root;
+  }
+
+  /// すべてのキーの符号を反転した、キーの順序が逆転したツリーを O(n) で構築します。元のツリーで `a < b` で
+  /// あったキーはミラーでは `-a > -b` となります。符号の反転で表現できない値 (`i32::MIN` など) をキーに持つ場合は
+  /// オーバーフローとなるため、そのようなキーを含む場合や数値でないキーには `mirror_reverse` を使用します。
+  ///
+  pub fn mirror(&self) -> BTree<KEY, VALUE, S>
+  where
+    KEY: core::ops::Neg<Output = KEY>,
+  {
+    let mut entries = self
+      .iter()
+      .map(|(key, value)| (-key, value))
+      .collect::<Vec<_>>();
+    entries.reverse();
+    BTree::from_sorted_iter(entries)
+  }
+
+  /// すべてのキーを `Reverse` で包んだ、キーの順序が逆転したツリーを O(n) で構築します。
+  ///
+  pub fn mirror_reverse(&self) -> BTree<core::cmp::Reverse<KEY>, VALUE, S> {
+    let mut entries = self
+      .iter()
+      .map(|(key, value)| (core::cmp::Reverse(key), value))
+      .collect::<Vec<_>>();
+    entries.reverse();
+    BTree::from_sorted_iter(entries)
+  }
+
+  /// すべてのエントリのキーを `kf`、値を `vf` で変換した新しいツリーを構築します。キーと値の型の変換と複製を
+  /// 1 回の走査で行います。
+  ///
+  /// 変換後のキーが元の順序を保つ (狭義単調増加となる) 場合はそのまま O(n) で一括構築します。順序が保たれない
+  /// 場合は整列してから構築し、変換後のキーが重複する場合は元のツリーで後ろにあったエントリが残ります。
+  ///
+  pub fn deep_clone_with_transform<K2, V2>(
+    &self,
+    kf: impl Fn(KEY) -> K2,
+    vf: impl Fn(VALUE) -> V2,
+  ) -> BTree<K2, V2, S>
+  where
+    K2: Ord + Clone,
+    V2: Clone,
+  {
+    let mut entries = self
+      .iter()
+      .map(|(key, value)| (kf(key), vf(value)))
+      .collect::<Vec<_>>();
+    if !entries.windows(2).all(|w| w[0].0 < w[1].0) {
+      entries.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+      entries.reverse();
+      entries.dedup_by(|(k1, _), (k2, _)| k1 == k2);
+      entries.reverse();
+    }
+    BTree::from_sorted_iter(entries)
+  }
+
+  /// このツリーと `other` の両方に存在するキーを複製して昇順に並べたベクタを返します。両方のツリーを同時に
+  /// 昇順で走査する O(n + m) のマージ結合で求め、値は参照されません。
+  ///
+  pub fn keys_in_common<V2: Clone>(&self, other: &BTree<KEY, V2, S>) -> Vec<KEY> {
+    let mut left = Iter::new(self);
+    let mut right = Iter::new(other);
+    let (mut x, mut y) = (left.next_entry(), right.next_entry());
+    let mut keys = Vec::new();
+    while let (Some(a), Some(b)) = (x, y) {
+      match a.key.cmp(&b.key) {
+        Ordering::Less => x = left.next_entry(),
+        Ordering::Greater => y = right.next_entry(),
+        Ordering::Equal => {
+          keys.push(a.key.clone());
+          x = left.next_entry();
+          y = right.next_entry();
+        }
+      }
+    }
+    keys
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Default for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn default() -> Self {
+    BTree::new()
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Clone for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// すべてのノードを再帰的に複製した、このツリーと構造を共有しない新しいツリーを返します。
+  ///
+  fn clone(&self) -> Self {
+    BTree {
+      root: Rc::new(RefCell::new(self.root.borrow().clone())),
+      reserved_nodes: self.reserved_nodes,
+      #[cfg(feature = "audit")]
+      audit: self.audit.clone(),
+      #[cfg(feature = "bloom")]
+      bloom: self.bloom.clone(),
+    }
+  }
+}
+
+/// 2 つのツリーは内部のノード構造に関係なく、同じ Key-Value の集合を格納している場合に等しいとみなされます。
+/// 両方のツリーを同時に昇順で走査し、最初に一致しないエントリが見つかった時点で比較を終了します。
+///
+impl<KEY, VALUE, const S: usize> PartialEq for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone + PartialEq,
+{
+  fn eq(&self, other: &Self) -> bool {
+    self.iter().eq(other.iter())
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Eq for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone + Eq,
+{
+}
+
+/// すべてのエントリをキーの昇順に `{key1: value1, key2: value2, ...}` の形式で表示します。
+///
+impl<KEY, VALUE, const S: usize> Display for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Display,
+  VALUE: Clone + Display,
+{
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("{")?;
+    for (i, (key, value)) in self.iter().enumerate() {
+      if i != 0 {
+        f.write_str(", ")?;
+      }
+      write!(f, "{key}: {value}")?;
+    }
+    f.write_str("}")
+  }
+}
+
+/// `btree[&key]` の構文で値を参照します。キーが存在しない場合は panic します。
+///
+impl<KEY, VALUE, Q, const S: usize> core::ops::Index<&Q> for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + borrow::Borrow<Q>,
+  VALUE: Clone,
+  Q: Ord + ?Sized,
+{
+  type Output = VALUE;
+
+  fn index(&self, key: &Q) -> &VALUE {
+    self.get(key).expect("key not found in BTree")
+  }
+}
+
+/// `PartialEq` と一貫するように、キーの昇順に並べた Key-Value ペアとそのエントリ数からハッシュ値を算出しま
+/// す。内部のノード構造はハッシュ値に影響しません。
+///
+impl<KEY, VALUE, const S: usize> Hash for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Hash,
+  VALUE: Clone + Hash,
+{
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    let mut count = 0usize;
+    for entry in self.iter() {
+      entry.hash(state);
+      count += 1;
+    }
+    state.write_usize(count);
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Extend<(KEY, VALUE)> for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// 指定された Key-Value ペアをすべてツリーに挿入します。同じキーが複数回現れた場合は `put` と同様に後の値
+  /// が残ります。ツリーが空の場合は入力を整列してからボトムアップに一括構築するため O(n log n) の `put` の
+  /// 繰り返しより高速です。
+  ///
+  fn extend<I: IntoIterator<Item = (KEY, VALUE)>>(&mut self, iter: I) {
+    if !self.root.borrow().keys.is_empty() {
+      for (key, value) in iter {
+        self.put(key, value);
+      }
+      return;
+    }
+
+    let mut pairs = iter.into_iter().collect::<Vec<_>>();
+    pairs.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+    let mut entries: Vec<KeyVal<KEY, VALUE>> = Vec::with_capacity(pairs.len());
+    for (key, value) in pairs {
+      match entries.last_mut() {
+        Some(last) if last.key == key => last.value = value,
+        _ => entries.push(KeyVal::new(key, value)),
+      }
+    }
+    self.root = BTree::bulk_load(entries).root;
+    #[cfg(feature = "bloom")]
+    self.refill_bloom_filter();
+  }
+}
+
+impl<KEY, VALUE, const S: usize> FromIterator<(KEY, VALUE)> for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn from_iter<I: IntoIterator<Item = (KEY, VALUE)>>(iter: I) -> Self {
+    let mut btree = BTree::new();
+    btree.extend(iter);
+    btree
+  }
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
+/// 標準ライブラリの `BTreeMap` はキーの昇順にエントリを列挙するため、`from_sorted_iter` により O(n) で変換
+/// します。
+///
+impl<KEY, VALUE, const S: usize> From<alloc::collections::BTreeMap<KEY, VALUE>>
+  for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn from(map: alloc::collections::BTreeMap<KEY, VALUE>) -> Self {
+    BTree::from_sorted_iter(map)
+  }
+}
 
-#[derive(Debug)]
-pub struct BTree<KEY, VALUE, const S: usize>
+impl<KEY, VALUE, const S: usize> From<BTree<KEY, VALUE, S>>
+  for alloc::collections::BTreeMap<KEY, VALUE>
 where
   KEY: Ord + Clone,
-  VALUE: Copy,
+  VALUE: Clone,
 {
-  root: Rc<RefCell<Node<KEY, VALUE, S>>>,
+  fn from(btree: BTree<KEY, VALUE, S>) -> Self {
+    btree.iter().collect()
+  }
 }
 
-impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+impl<'a, KEY, VALUE, const S: usize> IntoIterator for &'a BTree<KEY, VALUE, S>
 where
   KEY: Ord + Clone,
-  VALUE: Copy,
+  VALUE: Clone,
 {
-  pub fn new() -> Self {
-    BTree {
-      root: Rc::new(RefCell::new(Node::<KEY, VALUE, S>::new(true))),
-    }
+  type Item = (KEY, VALUE);
+  type IntoIter = Iter<'a, KEY, VALUE, S>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.iter()
   }
+}
 
-  /// この B-Tree に格納されているキーの下図を参照します。
-  ///
-  pub fn size(&self) -> usize {
-    self.root.borrow().size()
+/// `BTree::sibling_key` で参照する隣のエントリの方向です。
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SiblingDirection {
+  /// キーの順で 1 つ前のエントリです。
+  Left,
+  /// キーの順で 1 つ後のエントリです。
+  Right,
+}
+
+/// `BTree::get_mut` が返す、ツリーに格納されている値への可変参照です。値を格納しているノードの借用を保持
+/// し、破棄されたときに借用を解放します。
+///
+pub struct ValueGuardMut<'a, VALUE>(core::cell::RefMut<'a, VALUE>);
+
+impl<VALUE> core::ops::Deref for ValueGuardMut<'_, VALUE> {
+  type Target = VALUE;
+
+  fn deref(&self) -> &VALUE {
+    &self.0
   }
+}
 
-  // この B-Tree の葉までの深さを参照します。この機能は葉を 1 と数えます。
-  //
-  pub fn level(&self) -> usize {
-    self.root.borrow().level(0)
+impl<VALUE> core::ops::DerefMut for ValueGuardMut<'_, VALUE> {
+  fn deref_mut(&mut self) -> &mut VALUE {
+    &mut self.0
+  }
+}
+
+impl<VALUE: fmt::Debug> fmt::Debug for ValueGuardMut<'_, VALUE> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_tuple("ValueGuardMut").field(&*self.0).finish()
+  }
+}
+
+/// B-Tree のエントリをキーの昇順で列挙するイテレータです。根から現在位置までのノードと、各ノードで次に参照
+/// するキーのインデックスをスタックとして保持します。
+///
+pub struct Iter<'a, KEY, VALUE, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  stack: Vec<(NodeRef<KEY, VALUE, S>, usize)>,
+  _tree: PhantomData<&'a BTree<KEY, VALUE, S>>,
+}
+
+impl<'a, KEY, VALUE, const S: usize> Iter<'a, KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn new(btree: &'a BTree<KEY, VALUE, S>) -> Self {
+    let mut iter = Iter {
+      stack: Vec::new(),
+      _tree: PhantomData,
+    };
+    iter.push_leftmost(btree.root.clone());
+    iter
   }
 
-  /// 指定されたキーに関連付けられた値を返します。値が存在しない場合は None を返します。
+  /// `start` 以降の最初のエントリから列挙を開始するイテレータを構築します。
   ///
-  pub fn get(&self, key: &KEY) -> Option<VALUE> {
-    self.root.borrow().lookup(key)
+  fn seek(btree: &'a BTree<KEY, VALUE, S>, start: Bound<&KEY>) -> Self {
+    let mut iter = Iter {
+      stack: Vec::new(),
+      _tree: PhantomData,
+    };
+    let mut node = btree.root.clone();
+    loop {
+      let (i, child) = {
+        let n = node.borrow();
+        let i = match start {
+          Bound::Included(key) => n.keys.partition_point(|kv| kv.key < *key),
+          Bound::Excluded(key) => n.keys.partition_point(|kv| kv.key <= *key),
+          Bound::Unbounded => 0,
+        };
+        (i, (!n.is_leaf).then(|| n.pivots[i].clone()))
+      };
+      iter.stack.push((node, i));
+      match child {
+        Some(child) => node = child,
+        None => break,
+      }
+    }
+    iter
   }
 
-  /// ツリーに Key-Value ペアを挿入します。既に同じキーが存在する場合は新しい値で置き換えて古い値を返します。
+  /// 次のエントリへの参照を返します。参照はツリーの借用期間 `'a` の間有効です。
   ///
-  pub fn put(&mut self, key: KEY, value: VALUE) -> Option<VALUE> {
-    let (prop, result) = self.root.borrow_mut().upsert(key, value);
-    if let Some((keyval, pivot)) = prop {
-      let mut new_root = Node::new(false);
-      new_root.keys.push(keyval);
-      new_root.pivots.push(self.root.clone());
-      new_root.pivots.push(Rc::new(RefCell::new(pivot)));
-      self.root = Rc::new(RefCell::new(new_root));
+  fn next_entry(&mut self) -> Option<&'a KeyVal<KEY, VALUE>> {
+    loop {
+      let (node, i) = self.stack.last_mut()?;
+      let (keyval, child) = {
+        let node = node.borrow();
+        if *i >= node.keys.len() {
+          (None, None)
+        } else {
+          *i += 1;
+          let child = (!node.is_leaf).then(|| node.pivots[*i].clone());
+          // SAFETY: ノードが可変に借用されるのは `&mut self` を受け取る操作の中だけであり、このイテレータが
+          // 有効な間 (ツリーの `&'a` の借用期間) はツリーが変更されることはない。
+          let keyval = unsafe { &*(&node.keys[*i - 1] as *const KeyVal<KEY, VALUE>) };
+          (Some(keyval), child)
+        }
+      };
+      match keyval {
+        Some(keyval) => {
+          if let Some(child) = child {
+            self.push_leftmost(child);
+          }
+          return Some(keyval);
+        }
+        None => {
+          self.stack.pop();
+        }
+      }
     }
-    result
   }
 
-  pub fn delete(&mut self, key: &KEY) -> Option<VALUE> {
-    let old_value = self.root.borrow_mut().delete(key);
-    if !self.root.borrow().is_leaf && self.root.borrow().pivots.len() == 1 {
-      let new_root = self.root.borrow().pivots[0].clone();
-      self.root = new_root;
+  /// 指定されたノードから最も左の葉までのノードをスタックに積みます。
+  ///
+  fn push_leftmost(&mut self, mut node: NodeRef<KEY, VALUE, S>) {
+    loop {
+      let child = if node.borrow().is_leaf {
+        None
+      } else {
+        Some(node.borrow().pivots[0].clone())
+      };
+      self.stack.push((node, 0));
+      match child {
+        Some(child) => node = child,
+        None => break,
+      }
     }
-    old_value
   }
 }
 
-impl<KEY, VALUE, const S: usize> Default for BTree<KEY, VALUE, S>
+impl<KEY, VALUE, const S: usize> Iterator for Iter<'_, KEY, VALUE, S>
 where
   KEY: Ord + Clone,
-  VALUE: Copy,
+  VALUE: Clone,
 {
-  fn default() -> Self {
-    BTree::new()
+  type Item = (KEY, VALUE);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self
+      .next_entry()
+      .map(|keyval| (keyval.key.clone(), keyval.value.clone()))
   }
 }
 
@@ -93,23 +1862,80 @@ where
 struct Node<KEY, VALUE, const S: usize>
 where
   KEY: Ord + Clone,
-  VALUE: Copy,
+  VALUE: Clone,
 {
   is_leaf: bool,
   keys: Vec<KeyVal<KEY, VALUE>>,
   pivots: Vec<Rc<RefCell<Node<KEY, VALUE, S>>>>,
+  /// このノードをルートとする部分木に含まれるエントリの数です。キーや子ノードを変更した操作が `update_size`
+  /// で更新します。
+  subtree_size: usize,
+}
+
+impl<KEY, VALUE, const S: usize> Clone for Node<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// 子ノードを共有せず、部分木全体を再帰的に複製します。
+  ///
+  fn clone(&self) -> Self {
+    Node {
+      is_leaf: self.is_leaf,
+      keys: self.keys.clone(),
+      pivots: self
+        .pivots
+        .iter()
+        .map(|child| Rc::new(RefCell::new(child.borrow().clone())))
+        .collect(),
+      subtree_size: self.subtree_size,
+    }
+  }
 }
 
 impl<KEY, VALUE, const S: usize> Node<KEY, VALUE, S>
 where
   KEY: Ord + Clone,
-  VALUE: Copy,
+  VALUE: Clone,
 {
   fn new(is_leaf: bool) -> Self {
     Node {
       is_leaf,
       keys: Vec::with_capacity(S),
       pivots: Vec::with_capacity(S + 1),
+      subtree_size: 0,
+    }
+  }
+
+  /// 分割される直前の最大のキー数 `2S + 1` とその子ノードを再確保なしに格納できる容量を持つノードを構築
+  /// します。
+  ///
+  fn with_full_capacity(is_leaf: bool) -> Self {
+    Node {
+      is_leaf,
+      keys: Vec::with_capacity(2 * S + 1),
+      pivots: Vec::with_capacity(if is_leaf { 0 } else { 2 * S + 2 }),
+      subtree_size: 0,
+    }
+  }
+
+  /// `reserved` が残っていればそれを 1 つ消費して `with_full_capacity` で、そうでなければ `new` でノードを構築
+  /// します。
+  ///
+  fn reserved(is_leaf: bool, reserved: &mut usize) -> Self {
+    if *reserved > 0 {
+      *reserved -= 1;
+      Node::with_full_capacity(is_leaf)
+    } else {
+      Node::new(is_leaf)
+    }
+  }
+
+  fn shrink_to_fit(&mut self) {
+    self.keys.shrink_to_fit();
+    self.pivots.shrink_to_fit();
+    for pivot in self.pivots.iter() {
+      pivot.borrow_mut().shrink_to_fit();
     }
   }
 
@@ -117,8 +1943,14 @@ where
   /// インデックスを返します。存在しない場合は `Err` と共に `key` が存在すべきインデックスを返します。
   ///
   #[inline]
-  fn find_index(&self, key: &KEY) -> std::result::Result<usize, usize> {
-    self.keys.binary_search_by(|prove| prove.key.cmp(key))
+  fn find_index<Q>(&self, key: &Q) -> core::result::Result<usize, usize>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self
+      .keys
+      .binary_search_by(|prove| borrow::Borrow::borrow(&prove.key).cmp(key))
   }
 
   fn size(&self) -> usize {
@@ -132,6 +1964,17 @@ where
     }
     size
   }
+  /// このノードのキー数と子ノードの `subtree_size` から `subtree_size` を計算し直します。子ノードの
+  /// `subtree_size` は既に正しい値でなければなりません。
+  ///
+  fn update_size(&mut self) {
+    self.subtree_size = self.keys.len()
+      + self
+        .pivots
+        .iter()
+        .map(|child| child.borrow().subtree_size)
+        .sum::<usize>();
+  }
 
   fn level(&self, level: usize) -> usize {
     if self.is_leaf {
@@ -141,85 +1984,283 @@ where
     }
   }
 
-  /// このノードをルートとする部分木から指定されたキーに関連付けられた値を検索します。
+  /// このノードをルートとする部分木のエントリを深さと共にキーの昇順で `entries` に追加し、最も右の葉ノードの
+  /// 深さを返します。
+  ///
+  fn collect_with_depth(
+    &self,
+    depth: usize,
+    entries: &mut Vec<(usize, *const KeyVal<KEY, VALUE>)>,
+  ) -> usize {
+    if self.is_leaf {
+      entries.extend(self.keys.iter().map(|kv| (depth, kv as *const _)));
+      return depth;
+    }
+    for (kv, child) in self.keys.iter().zip(self.pivots.iter()) {
+      let leaf_depth = child.borrow().collect_with_depth(depth + 1, entries);
+      entries.push((leaf_depth, kv));
+    }
+    self.pivots[self.keys.len()]
+      .borrow()
+      .collect_with_depth(depth + 1, entries)
+  }
+
+  fn pivot_keys(&self, depth: usize, keys: &mut Vec<KEY>) {
+    if self.is_leaf {
+      return;
+    }
+    if depth == 0 {
+      keys.extend(self.keys.iter().map(|kv| kv.key.clone()));
+    } else {
+      for child in self.pivots.iter() {
+        child.borrow().pivot_keys(depth - 1, keys);
+      }
+    }
+  }
+
+  fn display_tree(&self, indent: usize, level: usize, buffer: &mut String)
+  where
+    KEY: Display,
+  {
+    let keys = self
+      .keys
+      .iter()
+      .map(|kv| kv.key.to_string())
+      .collect::<Vec<_>>()
+      .join(", ");
+    if self.is_leaf {
+      writeln!(buffer, "{}[{}]", " ".repeat(indent), keys).unwrap();
+    } else {
+      writeln!(buffer, "{}L{}: [{}]", " ".repeat(indent), level, keys).unwrap();
+      for child in self.pivots.iter() {
+        child.borrow().display_tree(indent + 2, level - 1, buffer);
+      }
+    }
+  }
+
+  /// このノードをルートとする部分木を DOT 言語のノードと辺として `buffer` に出力し、このノードに割り当てた
+  /// 番号を返します。ノードには行きがけ順に `next_id` から番号が割り当てられます。
+  ///
+  fn to_dot(&self, next_id: &mut usize, buffer: &mut String) -> usize
+  where
+    KEY: Display,
+    VALUE: Display,
+  {
+    let id = *next_id;
+    *next_id += 1;
+    let (label, shape) = if self.is_leaf {
+      let entries = self
+        .keys
+        .iter()
+        .map(|kv| record_escape(&format!("{}: {}", kv.key, kv.value)));
+      (entries.collect::<Vec<_>>().join("|"), "Mrecord")
+    } else {
+      let mut fields = vec![String::from("<p0>")];
+      for (i, kv) in self.keys.iter().enumerate() {
+        fields.push(record_escape(&kv.key.to_string()));
+        fields.push(format!("<p{}>", i + 1));
+      }
+      (fields.join("|"), "record")
+    };
+    writeln!(buffer, "  n{} [shape={}, label=\"{}\"];", id, shape, label).unwrap();
+    for (i, child) in self.pivots.iter().enumerate() {
+      let child_id = child.borrow().to_dot(next_id, buffer);
+      let range = match (
+        i.checked_sub(1).map(|j| &self.keys[j].key),
+        self.keys.get(i).map(|kv| &kv.key),
+      ) {
+        (None, Some(upper)) => format!("< {upper}"),
+        (Some(lower), Some(upper)) => format!("{lower} .. {upper}"),
+        (Some(lower), None) => format!("> {lower}"),
+        (None, None) => String::new(),
+      };
+      writeln!(
+        buffer,
+        "  n{}:p{} -> n{} [label=\"{}\"];",
+        id,
+        i,
+        child_id,
+        dot_escape(&range)
+      )
+      .unwrap();
+    }
+    id
+  }
+
+  /// 指定されたキーのエントリを検索し、見つかった場合はそのエントリに `f` を適用した結果を返します。
   ///
-  fn lookup(&self, key: &KEY) -> Option<VALUE> {
+  fn lookup_entry<Q, R, F>(&self, key: &Q, f: F) -> Option<R>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+    F: FnOnce(&KeyVal<KEY, VALUE>) -> R,
+  {
     match self.find_index(key) {
-      Ok(i) => Some(self.keys[i].value),
+      Ok(i) => Some(f(&self.keys[i])),
       Err(i) => {
         if self.is_leaf {
           None
         } else {
-          self.pivots[i].borrow().lookup(key)
+          self.pivots[i].borrow().lookup_entry(key, f)
         }
       }
     }
   }
 
+  /// 指定されたキーのエントリを検索し、見つかった場合はそのエントリを変更する `f` を適用した結果を返します。
+  ///
+  fn update_entry<Q, R, F>(&mut self, key: &Q, f: F) -> Option<R>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+    F: FnOnce(&mut KeyVal<KEY, VALUE>) -> R,
+  {
+    match self.find_index(key) {
+      Ok(i) => Some(f(&mut self.keys[i])),
+      Err(_) if self.is_leaf => None,
+      Err(i) => self.pivots[i].borrow_mut().update_entry(key, f),
+    }
+  }
+
+  /// このノードをルートとする部分木のすべての Key-Value をキーの昇順で `f` に渡します。`f` が `false` を返
+  /// した時点で走査を打ち切り、このメソッドも `false` を返します。
+  ///
+  fn for_each<F>(&self, f: &mut F) -> bool
+  where
+    F: FnMut(&KeyVal<KEY, VALUE>) -> bool,
+  {
+    for (i, kv) in self.keys.iter().enumerate() {
+      if !self.is_leaf && !self.pivots[i].borrow().for_each(f) {
+        return false;
+      }
+      if !f(kv) {
+        return false;
+      }
+    }
+    self.is_leaf || self.pivots[self.keys.len()].borrow().for_each(f)
+  }
+
+  /// このノードをルートとする部分木を解体し、すべての Key-Value をキーの昇順で `out` に移動します。
+  ///
+  fn drain_into(self, out: &mut Vec<KeyVal<KEY, VALUE>>) {
+    let mut pivots = self.pivots.into_iter().map(|child| {
+      Rc::try_unwrap(child).map_or_else(|child| child.borrow().clone(), RefCell::into_inner)
+    });
+    for kv in self.keys {
+      if let Some(child) = pivots.next() {
+        child.drain_into(out);
+      }
+      out.push(kv);
+    }
+    if let Some(child) = pivots.next() {
+      child.drain_into(out);
+    }
+  }
+
   /// このノードをルートとする部分木に指定された Key-Value を追加します。すでに同じキーが存在する場合は
   /// 値を更新する UPSERT の動作となります。
   ///
-  fn upsert(&mut self, key: KEY, value: VALUE) -> (SplitPropagation<KEY, VALUE, S>, Option<VALUE>) {
+  fn upsert(
+    &mut self,
+    key: KEY,
+    value: VALUE,
+    reserved: &mut usize,
+  ) -> Result<(SplitPropagation<KEY, VALUE, S>, Option<VALUE>)> {
     match self.find_index(&key) {
       Ok(i) => {
         // 既にキーが存在する場合はその値を置き換えて以前の値を返す
-        let old_value = self.keys[i].value;
-        self.keys[i].value = value;
-        (None, Some(old_value))
+        let old_value = core::mem::replace(&mut self.keys[i].value, value);
+        Ok((None, Some(old_value)))
       }
       Err(i) => {
         if self.is_leaf {
           self.keys.insert(i, KeyVal::new(key, value));
-          let parent_insertion = self.split();
-          (parent_insertion, None)
+          self.subtree_size += 1;
+          let parent_insertion = self.split(reserved)?;
+          Ok((parent_insertion, None))
         } else {
-          let (new_node, old_value) = self.pivots[i].borrow_mut().upsert(key, value);
+          if i >= self.pivots.len() {
+            return Err(Error::InvariantViolation(format!(
+              "internal node with {} keys has only {} children",
+              self.keys.len(),
+              self.pivots.len()
+            )));
+          }
+          let (new_node, old_value) = self.pivots[i].borrow_mut().upsert(key, value, reserved)?;
+          if old_value.is_none() {
+            self.subtree_size += 1;
+          }
           if let Some((keyval, node)) = new_node {
             self.keys.insert(i, keyval);
             self.pivots.insert(i + 1, Rc::new(RefCell::new(node)));
-            let parent_insertion = self.split();
-            (parent_insertion, old_value)
+            let parent_insertion = self.split(reserved)?;
+            Ok((parent_insertion, old_value))
           } else {
-            (None, old_value)
+            Ok((None, old_value))
           }
         }
       }
     }
   }
 
-  /// このノードのキー数が `2S` を超えていれば分割を行います。
+  /// このノードのキー数が `2S` を超えていれば分割を行います。ノードのキー数と子ノードの数が一致しない場合
+  /// や、キー数が 1 回の挿入で到達し得る `2S + 1` を超えている場合は `Error::InvariantViolation` を返します。
   ///
-  fn split(&mut self) -> SplitPropagation<KEY, VALUE, S> {
-    debug_assert!(self.is_leaf || self.keys.len() + 1 == self.pivots.len());
+  fn split(&mut self, reserved: &mut usize) -> Result<SplitPropagation<KEY, VALUE, S>> {
+    if !self.is_leaf && self.keys.len() + 1 != self.pivots.len() {
+      return Err(Error::InvariantViolation(format!(
+        "internal node has {} keys but {} children",
+        self.keys.len(),
+        self.pivots.len()
+      )));
+    }
+    if self.keys.len() > 2 * S + 1 {
+      return Err(Error::InvariantViolation(format!(
+        "node has {} keys, exceeding the capacity of {}",
+        self.keys.len(),
+        2 * S
+      )));
+    }
     if self.keys.len() == 2 * S + 1 {
-      let mut right_node = Node::new(self.is_leaf);
-      right_node.keys = self.keys.split_off(S);
-      let keyval = right_node.keys.remove(0);
+      let mut right_node = Node::reserved(self.is_leaf, reserved);
+      right_node.keys.extend(self.keys.drain(S + 1..));
+      let keyval = self.keys.pop().unwrap();
       if !self.is_leaf {
-        right_node.pivots = self.pivots.split_off(S + 1);
+        right_node.pivots.extend(self.pivots.drain(S + 1..));
       }
       debug_assert_eq!(S, self.keys.len());
       debug_assert_eq!(S, right_node.keys.len());
-      Some((keyval, right_node))
+      right_node.update_size();
+      self.update_size();
+      #[cfg(feature = "std")]
+      STRUCTURE_CHANGES.with(|c| c.set((c.get().0 + 1, c.get().1)));
+      Ok(Some((keyval, right_node)))
     } else {
-      debug_assert!(self.keys.len() <= 2 * S);
-      None
+      Ok(None)
     }
   }
 
-  fn delete(&mut self, key: &KEY) -> Option<VALUE> {
+  /// 指定されたキーのエントリを削除し、削除したエントリを返します。
+  ///
+  fn delete<Q>(&mut self, key: &Q) -> Result<Option<KeyVal<KEY, VALUE>>>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
     match self.find_index(key) {
-      Ok(i) if self.is_leaf => Some(self.keys.remove(i).value),
-      Err(_) if self.is_leaf => None,
+      Ok(i) if self.is_leaf => {
+        self.subtree_size -= 1;
+        Ok(Some(self.keys.remove(i)))
+      }
+      Err(_) if self.is_leaf => Ok(None),
       Ok(i) => {
-        let old_value = self.keys[i].value;
         let mut left = self.pivots[i].borrow_mut();
         let mut right = self.pivots[i + 1].borrow_mut();
-        if let Some(keyval) = left
+        let removed = if let Some(keyval) = left
           .remove_most_leftright(false, false)
           .or_else(|| right.remove_most_leftright(true, false))
         {
-          self.keys[i] = keyval;
+          core::mem::replace(&mut self.keys[i], keyval)
         } else {
           let remove_from_left = i % 2 == 0;
           let child = if remove_from_left {
@@ -229,19 +2270,26 @@ where
           };
           let keyval = child
             .remove_most_leftright(!remove_from_left, true)
-            .unwrap();
-          self.keys[i] = keyval;
-          child.rebalance_most_leftright(!remove_from_left);
+            .ok_or_else(|| {
+              Error::InvariantViolation("empty leaf node under an internal node".into())
+            })?;
+          let removed = core::mem::replace(&mut self.keys[i], keyval);
+          child.rebalance_most_leftright(!remove_from_left)?;
           drop(left);
           drop(right);
-          self.rebalance(i + if remove_from_left { 0 } else { 1 });
-        }
-        Some(old_value)
+          self.rebalance(i + if remove_from_left { 0 } else { 1 })?;
+          removed
+        };
+        self.subtree_size -= 1;
+        Ok(Some(removed))
       }
       Err(i) => {
-        let old_value = self.pivots[i].borrow_mut().delete(key);
-        self.rebalance(i);
-        old_value
+        let removed = self.pivots[i].borrow_mut().delete(key)?;
+        self.rebalance(i)?;
+        if removed.is_some() {
+          self.subtree_size -= 1;
+        }
+        Ok(removed)
       }
     }
   }
@@ -249,34 +2297,156 @@ where
   fn remove_most_leftright(&mut self, leftmost: bool, force: bool) -> Option<KeyVal<KEY, VALUE>> {
     if !self.is_leaf {
       let i = if leftmost { 0 } else { self.pivots.len() - 1 };
-      self.pivots[i]
+      let keyval = self.pivots[i]
         .borrow_mut()
-        .remove_most_leftright(leftmost, force)
+        .remove_most_leftright(leftmost, force)?;
+      self.subtree_size -= 1;
+      Some(keyval)
     } else if self.keys.len() > S || force {
       let keyval = if leftmost {
         self.keys.remove(0)
       } else {
         self.keys.pop().unwrap()
       };
+      self.subtree_size -= 1;
       Some(keyval)
     } else {
       None
     }
   }
 
-  fn rebalance_most_leftright(&mut self, leftmost: bool) {
+  fn rebalance_most_leftright(&mut self, leftmost: bool) -> Result<()> {
     if !self.is_leaf {
       let i = if leftmost { 0 } else { self.pivots.len() - 1 };
       self.pivots[i]
         .borrow_mut()
-        .rebalance_most_leftright(leftmost);
-      self.rebalance(i);
+        .rebalance_most_leftright(leftmost)?;
+      self.rebalance(i)?;
+    }
+    Ok(())
+  }
+
+  /// このノードをルートとする部分木を `key` の位置で分割し、`key` 以上のキーを持つ右側の部分木を返します。
+  /// 分割経路上のノードはキー数が不足する可能性があるため、呼び出し側で `fix_right_border` および
+  /// `fix_left_border` による修復が必要です。
+  ///
+  fn split_off<Q>(&mut self, key: &Q) -> Node<KEY, VALUE, S>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let i = match self.find_index(key) {
+      Ok(i) | Err(i) => i,
+    };
+    let mut right = Node::new(self.is_leaf);
+    right.keys = self.keys.split_off(i);
+    if !self.is_leaf {
+      let child = self.pivots[i].borrow_mut().split_off(key);
+      right.pivots.push(Rc::new(RefCell::new(child)));
+      right.pivots.extend(self.pivots.drain(i + 1..));
+    }
+    right.update_size();
+    self.update_size();
+    right
+  }
+
+  /// `split_off` で残された左側の部分木について、最も右の枝の各ノードが `S` 以上のキーを持つように左の兄弟
+  /// ノードからキーを移動、または兄弟ノードとマージします。このノードは 1 つ以上のキーを持つ内部ノードで
+  /// なければなりません。
+  ///
+  fn fix_right_border(&mut self) -> Result<()> {
+    let last = self.pivots.len() - 1;
+    {
+      let mut left = self.pivots[last - 1].borrow_mut();
+      let mut child = self.pivots[last].borrow_mut();
+      if child.keys.len() <= S {
+        if left.keys.len() + 1 + child.keys.len() <= 2 * S {
+          // 左ノードとマージ
+          left.keys.push(self.keys.remove(last - 1));
+          left.keys.append(&mut child.keys);
+          left.pivots.append(&mut child.pivots);
+        } else if left.keys.len() > S {
+          // 左ノードのキーを再配分
+          let count = (S + 1 - child.keys.len()).min(left.keys.len() - S);
+          let at = left.keys.len() - count;
+          let mut keys = left.keys.split_off(at);
+          let separator = core::mem::replace(&mut self.keys[last - 1], keys.remove(0));
+          keys.push(separator);
+          keys.append(&mut child.keys);
+          child.keys = keys;
+          if !child.is_leaf {
+            let at = left.pivots.len() - count;
+            let mut pivots = left.pivots.split_off(at);
+            pivots.append(&mut child.pivots);
+            child.pivots = pivots;
+          }
+        }
+        left.update_size();
+        child.update_size();
+      }
+    }
+    if self.keys.len() < last {
+      self.pivots.pop();
+    }
+    let last = self.pivots.len() - 1;
+    if !self.pivots[last].borrow().is_leaf {
+      self.pivots[last].borrow_mut().fix_right_border()?;
+    }
+    if last > 0 {
+      self.rebalance(last)?;
+    }
+    self.update_size();
+    Ok(())
+  }
+
+  /// `split_off` で返された右側の部分木について、最も左の枝の各ノードが `S` 以上のキーを持つように右の兄弟
+  /// ノードからキーを移動、または兄弟ノードとマージします。このノードは 1 つ以上のキーを持つ内部ノードで
+  /// なければなりません。
+  ///
+  fn fix_left_border(&mut self) -> Result<()> {
+    {
+      let mut child = self.pivots[0].borrow_mut();
+      let mut right = self.pivots[1].borrow_mut();
+      if child.keys.len() <= S {
+        if child.keys.len() + 1 + right.keys.len() <= 2 * S {
+          // 右ノードとマージ
+          child.keys.push(self.keys.remove(0));
+          child.keys.append(&mut right.keys);
+          child.pivots.append(&mut right.pivots);
+        } else if right.keys.len() > S {
+          // 右ノードのキーを再配分
+          let count = (S + 1 - child.keys.len()).min(right.keys.len() - S);
+          let mut keys = right.keys.drain(..count).collect::<Vec<_>>();
+          let separator = core::mem::replace(&mut self.keys[0], keys.pop().unwrap());
+          child.keys.push(separator);
+          child.keys.append(&mut keys);
+          if !child.is_leaf {
+            child.pivots.extend(right.pivots.drain(..count));
+          }
+        }
+        child.update_size();
+        right.update_size();
+      }
+    }
+    if self.keys.len() + 1 < self.pivots.len() {
+      self.pivots.remove(1);
+    }
+    if !self.pivots[0].borrow().is_leaf {
+      self.pivots[0].borrow_mut().fix_left_border()?;
     }
+    if self.pivots.len() > 1 {
+      self.rebalance(0)?;
+    }
+    self.update_size();
+    Ok(())
   }
 
-  fn rebalance(&mut self, i: usize) {
+  /// `i` 番目の子ノードのキー数が `S` 未満であれば、兄弟ノードからのキーの再配分またはマージによって修復
+  /// します。兄弟ノードの一方のみが葉ノードである場合は `Error::InvariantViolation` を返します。
+  ///
+  fn rebalance(&mut self, i: usize) -> Result<()> {
     if self.pivots[i].borrow().keys.len() >= S {
-      return;
+      return Ok(());
     }
     if i + 1 < self.pivots.len() && self.pivots[i + 1].borrow().keys.len() > S {
       // 右ノードのキーを再配分
@@ -284,45 +2454,76 @@ where
       let mut right = self.pivots[i + 1].borrow_mut();
       left.keys.push(self.keys[i].clone());
       self.keys[i] = right.keys.remove(0);
-      assert!(left.is_leaf == right.is_leaf);
+      check_same_level(&left, &right)?;
       if !left.is_leaf {
         left.pivots.push(right.pivots.remove(0));
       }
+      left.update_size();
+      right.update_size();
     } else if i != 0 && self.pivots[i - 1].borrow().keys.len() > S {
       // 左ノードのキーを再配分
       let mut right = self.pivots[i].borrow_mut();
       let mut left = self.pivots[i - 1].borrow_mut();
       right.keys.insert(0, self.keys[i - 1].clone());
       self.keys[i - 1] = left.keys.pop().unwrap();
-      assert!(right.is_leaf == left.is_leaf);
+      check_same_level(&left, &right)?;
       if !right.is_leaf {
         right.pivots.insert(0, left.pivots.pop().unwrap());
       }
+      left.update_size();
+      right.update_size();
     } else if i + 1 < self.pivots.len() {
       // 右ノードとマージ
+      #[cfg(feature = "std")]
+      STRUCTURE_CHANGES.with(|c| c.set((c.get().0, c.get().1 + 1)));
       let kv = self.keys.remove(i);
       let right_rc = self.pivots.remove(i + 1);
       let mut left = self.pivots[i].borrow_mut();
       let mut right = right_rc.borrow_mut();
       left.keys.push(kv);
       left.keys.append(&mut right.keys);
-      assert!(left.is_leaf == right.is_leaf);
+      check_same_level(&left, &right)?;
       if !left.is_leaf {
         left.pivots.append(&mut right.pivots);
       }
+      left.update_size();
     } else {
       // 左ノードとマージ
+      #[cfg(feature = "std")]
+      STRUCTURE_CHANGES.with(|c| c.set((c.get().0, c.get().1 + 1)));
       let kv = self.keys.remove(i - 1);
       let right_rc = self.pivots.remove(i);
       let mut right = right_rc.borrow_mut();
       let mut left = self.pivots[i - 1].borrow_mut();
       left.keys.push(kv);
       left.keys.append(&mut right.keys);
-      assert!(left.is_leaf == right.is_leaf);
+      check_same_level(&left, &right)?;
       if !left.is_leaf {
         left.pivots.append(&mut right.pivots);
       }
+      left.update_size();
     }
+    Ok(())
+  }
+}
+
+/// 再配分またはマージの対象となる 2 つの兄弟ノードが同じ階層にあることを確認します。
+///
+fn check_same_level<KEY, VALUE, const S: usize>(
+  left: &Node<KEY, VALUE, S>,
+  right: &Node<KEY, VALUE, S>,
+) -> Result<()>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  if left.is_leaf == right.is_leaf {
+    Ok(())
+  } else {
+    Err(Error::InvariantViolation(format!(
+      "sibling nodes are at different levels (left is_leaf={}, right is_leaf={})",
+      left.is_leaf, right.is_leaf
+    )))
   }
 }
 
@@ -334,6 +2535,8 @@ where
 {
   key: KEY,
   value: VALUE,
+  /// `get` などによってこのエントリの値が参照された回数です。
+  accesses: Cell<u64>,
 }
 
 impl<KEY, VALUE> KeyVal<KEY, VALUE>
@@ -342,9 +2545,142 @@ where
   VALUE: Clone,
 {
   fn new(key: KEY, value: VALUE) -> Self {
-    KeyVal { key, value }
+    KeyVal {
+      key,
+      value,
+      accesses: Cell::new(0),
+    }
+  }
+
+  fn touch(&self) {
+    self.accesses.set(self.accesses.get().saturating_add(1));
   }
 }
 
 type SplitPropagation<KEY, VALUE, const S: usize> =
   Option<(KeyVal<KEY, VALUE>, Node<KEY, VALUE, S>)>;
+
+/// DOT 言語の二重引用符で囲まれた文字列の中で特別な意味を持つ文字をエスケープします。
+///
+fn dot_escape(text: &str) -> String {
+  text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// レコード形式のラベルの 1 つのフィールドとして表示されるよう、`dot_escape` がエスケープする文字に加えて
+/// フィールドの区切りやポートとして解釈される文字を `\` でエスケープします。
+///
+fn record_escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    if matches!(c, '\\' | '"' | '{' | '}' | '|' | '<' | '>') {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+  escaped
+}
+
+/// 最小値からの距離が `offset` の整数を、幅 `span + 1` の範囲を `buckets` 個に等分した区間の番号
+/// `floor(offset * buckets / (span + 1))` に変換します。`i128` の全域にわたる範囲でも溢れないよう、
+/// `offset * buckets` や `span + 1` が `u128` に収まらない場合は `buckets` の上位のビットから順に商と余りを
+/// 求める筆算で計算します。
+///
+fn bucket_index(offset: u128, span: u128, buckets: usize) -> usize {
+  debug_assert!(offset <= span && buckets > 0);
+  if let (Some(scaled), Some(width)) = (offset.checked_mul(buckets as u128), span.checked_add(1)) {
+    return (scaled / width) as usize;
+  }
+  // 処理済みの上位のビットを `prefix` として `offset * prefix = quotient * (span + 1) + remainder` を保つ。
+  // `remainder + x >= span + 1` の判定は溢れないよう `remainder > span - x` で行う
+  let (mut quotient, mut remainder) = (0usize, 0u128);
+  for i in (0..usize::BITS).rev() {
+    quotient <<= 1;
+    if remainder > span - remainder {
+      quotient += 1;
+      remainder -= span - remainder + 1;
+    } else {
+      remainder += remainder;
+    }
+    if buckets >> i & 1 == 1 {
+      if remainder > span - offset {
+        quotient += 1;
+        remainder -= span - offset + 1;
+      } else {
+        remainder += offset;
+      }
+    }
+  }
+  quotient
+}
+
+/// 64 ビットの FNV-1a ハッシュ関数です。`DefaultHasher` と異なりアルゴリズムが固定されているため、プロセスや
+/// バージョンをまたいで同じ入力に対して同じ値を返します。
+///
+/// `Hasher` の既定の実装は整数をネイティブのバイト順と幅で `write` に渡すため、整数の書き込みはすべて
+/// リトルエンディアンに、`usize` と `isize` は 64 ビットに揃えてからハッシュします。これによりスライスや文字列の
+/// 長さを含めてターゲットのポインタの幅やバイト順に依存しない値となります。
+///
+struct Fnv1a(u64);
+
+impl Fnv1a {
+  const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+  const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+  fn new() -> Self {
+    Fnv1a(Self::OFFSET_BASIS)
+  }
+}
+
+impl Hasher for Fnv1a {
+  fn finish(&self) -> u64 {
+    self.0
+  }
+
+  fn write(&mut self, bytes: &[u8]) {
+    for b in bytes {
+      self.0 = (self.0 ^ *b as u64).wrapping_mul(Self::PRIME);
+    }
+  }
+
+  fn write_u16(&mut self, i: u16) {
+    self.write(&i.to_le_bytes());
+  }
+
+  fn write_u32(&mut self, i: u32) {
+    self.write(&i.to_le_bytes());
+  }
+
+  fn write_u64(&mut self, i: u64) {
+    self.write(&i.to_le_bytes());
+  }
+
+  fn write_u128(&mut self, i: u128) {
+    self.write(&i.to_le_bytes());
+  }
+
+  fn write_usize(&mut self, i: usize) {
+    self.write_u64(i as u64);
+  }
+
+  fn write_i16(&mut self, i: i16) {
+    self.write_u16(i as u16);
+  }
+
+  fn write_i32(&mut self, i: i32) {
+    self.write_u32(i as u32);
+  }
+
+  fn write_i64(&mut self, i: i64) {
+    self.write_u64(i as u64);
+  }
+
+  fn write_i128(&mut self, i: i128) {
+    self.write_u128(i as u128);
+  }
+
+  fn write_isize(&mut self, i: isize) {
+    self.write_i64(i as i64);
+  }
+}
+
+type NodeRef<KEY, VALUE, const S: usize> = Rc<RefCell<Node<KEY, VALUE, S>>>;
@@ -0,0 +1,30 @@
+/// リテラルの Key-Value ペアを列挙した `BTree` を構築します。次数 `S` は先頭の `S = ...;` で指定します。
+///
+/// `btree!{ S = 3; "a" => 1, "b" => 2, "c" => 3 }` のようにキーが狭義単調増加の順に記述されている場合は
+/// `from_sorted_iter` による一括構築を、そうでない場合は `put` の繰り返しを行います。
+///
+/// すべてのキーがリテラルの場合、キーを `match` のパターンとして並べて `unreachable_patterns` を拒否する
+/// ため、同じキーのリテラルが複数回現れるとコンパイルエラーとなります。
+///
+/// ```compile_fail
+/// let btree = btree::btree! { S = 2; 1 => "a", 2 => "b", 1 => "c" };
+/// ```
+///
+/// キーに変数などの式を含む場合は重複をコンパイル時に判定できないため、構築時に判定して panic します。
+///
+#[macro_export]
+macro_rules! btree {
+  { S = $s:expr; $first:literal => $first_value:expr $(, $key:literal => $value:expr)* $(,)? } => {{
+    #[deny(unreachable_patterns)]
+    let _ = match $first {
+      $first => (),
+      $($key => (),)*
+      #[allow(unreachable_patterns)]
+      _ => (),
+    };
+    $crate::BTree::<_, _, { $s }>::from_literal_entries([($first, $first_value) $(, ($key, $value))*])
+  }};
+  { S = $s:expr; $($key:expr => $value:expr),* $(,)? } => {
+    $crate::BTree::<_, _, { $s }>::from_literal_entries([$(($key, $value)),*])
+  };
+}
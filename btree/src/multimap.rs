@@ -0,0 +1,133 @@
+use crate::BTree;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+
+/// 1 つのキーに複数の値を関連付けることができる B-Tree です。転置インデックスや一対多の関係、一意でない
+/// データベースの索引に使用します。
+///
+/// 内部ではキーごとに挿入された順に値を並べた `Vec` を `BTree` の値として格納します。値を 1 つも持たない
+/// キーはツリーから取り除かれるため、ツリーに存在するキーは常に 1 つ以上の値を持ちます。
+///
+#[derive(Debug, Clone, Default)]
+pub struct BTreeMultiMap<KEY, VALUE, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  tree: BTree<KEY, Vec<VALUE>, S>,
+  len: usize,
+}
+
+impl<KEY, VALUE, const S: usize> BTreeMultiMap<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  pub fn new() -> Self {
+    BTreeMultiMap {
+      tree: BTree::new(),
+      len: 0,
+    }
+  }
+
+  /// 格納されている値の総数を参照します。
+  ///
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// 指定されたキーに値を追加します。同じキーに既に値が存在する場合も置き換えずに末尾に追加します。
+  ///
+  pub fn insert(&mut self, key: KEY, value: VALUE) {
+    self.len += 1;
+    if let Some(mut values) = self.tree.get_mut(&key) {
+      values.push(value);
+      return;
+    }
+    self.tree.put(key, vec![value]);
+  }
+
+  /// 指定されたキーに関連付けられたすべての値を挿入された順に返します。キーが存在しない場合は空のスライスを
+  /// 返します。
+  ///
+  pub fn get_all<Q>(&self, key: &Q) -> &[VALUE]
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.tree.get(key).map_or(&[], Vec::as_slice)
+  }
+
+  pub fn contains_key<Q>(&self, key: &Q) -> bool
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.tree.contains_key(key)
+  }
+
+  /// 指定されたキーに関連付けられた値のうち `value` と等しい最も古いものを 1 つ削除します。削除した場合は
+  /// true を返します。
+  ///
+  pub fn delete_one<Q>(&mut self, key: &Q, value: &VALUE) -> bool
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+    VALUE: PartialEq,
+  {
+    let Some(mut values) = self.tree.get_mut(key) else {
+      return false;
+    };
+    let Some(i) = values.iter().position(|v| v == value) else {
+      return false;
+    };
+    values.remove(i);
+    let emptied = values.is_empty();
+    drop(values);
+    if emptied {
+      self.tree.delete(key);
+    }
+    self.len -= 1;
+    true
+  }
+
+  /// 指定されたキーに関連付けられたすべての値を削除し、削除した値の数を返します。
+  ///
+  pub fn delete_all<Q>(&mut self, key: &Q) -> usize
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let removed = self.tree.delete(key).map_or(0, |values| values.len());
+    self.len -= removed;
+    removed
+  }
+
+  /// すべての Key-Value をキーの昇順で列挙します。同じキーの値は挿入された順に並びます。
+  ///
+  pub fn iter(&self) -> impl Iterator<Item = (KEY, VALUE)> + '_ {
+    self
+      .tree
+      .iter()
+      .flat_map(|(key, values)| values.into_iter().map(move |value| (key.clone(), value)))
+  }
+}
+
+impl<KEY, VALUE, const S: usize> FromIterator<(KEY, VALUE)> for BTreeMultiMap<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn from_iter<I: IntoIterator<Item = (KEY, VALUE)>>(iter: I) -> Self {
+    let mut map = BTreeMultiMap::new();
+    for (key, value) in iter {
+      map.insert(key, value);
+    }
+    map
+  }
+}
@@ -0,0 +1,107 @@
+use crate::{BTree, Node};
+use core::ops::{Bound, RangeBounds};
+
+/// 他のスレッドへ渡すノードへのポインタです。
+///
+struct NodePtr<KEY, VALUE, const S: usize>(*const Node<KEY, VALUE, S>)
+where
+  KEY: Ord + Clone,
+  VALUE: Clone;
+
+// SAFETY: 走査の間はツリーが `&self` で借用されているため変更されず、ポインタの先のノードは読み取られる
+// だけである。`Rc` の参照カウントや `RefCell` の借用フラグには触れないため、複数のスレッドから同時に参照
+// しても競合しない。
+unsafe impl<KEY, VALUE, const S: usize> Send for NodePtr<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Sync,
+  VALUE: Clone + Sync,
+{
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Sync,
+  VALUE: Clone + Sync,
+{
+  /// 指定された範囲のエントリをルートの部分木ごとに Rayon のタスクに分割し、並列に `f` を呼び出します。
+  /// ルートの区切りのキーはすべての部分木の処理が終わった後に呼び出したスレッドで処理します。`f` の呼び出し
+  /// 順序は保証されず、参照回数は更新されません。
+  ///
+  /// 並列度はルートの子ノードの数が上限となるため、エントリごとの処理が重い大きな範囲で効果があります。走査
+  /// の間はノードの `RefCell` を借用せずに参照するため、`f` の中でこのツリーを操作することはできません。
+  ///
+  pub fn par_for_each<R, F>(&self, range: R, f: F)
+  where
+    R: RangeBounds<KEY> + Send + Sync,
+    F: Fn(&KEY, &VALUE) + Send + Sync,
+  {
+    // SAFETY: ノードが可変に借用されるのは `&mut self` を受け取る操作の中だけであり、このメソッドを抜ける
+    // まで (`&self` の借用期間) はツリーが変更されることはない。
+    let root = unsafe { &*self.root.as_ptr() };
+    if root.is_leaf {
+      root.for_each_in_range(&range, &f);
+      return;
+    }
+    let children = (0..root.pivots.len())
+      .filter(|&i| !root.skips_child(i, &range))
+      .map(|i| NodePtr(root.pivots[i].as_ptr() as *const Node<KEY, VALUE, S>))
+      .collect::<Vec<_>>();
+    let (range, f) = (&range, &f);
+    rayon::scope(|scope| {
+      for child in children {
+        scope.spawn(move |_| {
+          let child = child;
+          // SAFETY: `root` と同様に、スコープを抜けるまでツリーが変更されることはない。
+          unsafe { &*child.0 }.for_each_in_range(range, f);
+        });
+      }
+    });
+    for kv in root.keys.iter().filter(|kv| range.contains(&kv.key)) {
+      f(&kv.key, &kv.value);
+    }
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Node<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// この部分木のうち指定された範囲に含まれるエントリについてキーの昇順に `f` を呼び出します。
+  ///
+  fn for_each_in_range<R, F>(&self, range: &R, f: &F)
+  where
+    R: RangeBounds<KEY>,
+    F: Fn(&KEY, &VALUE),
+  {
+    for i in 0..=self.keys.len() {
+      if !self.is_leaf && !self.skips_child(i, range) {
+        // SAFETY: 呼び出し元がツリーを `&self` で借用している間はノードが変更されることはない。
+        unsafe { &*self.pivots[i].as_ptr() }.for_each_in_range(range, f);
+      }
+      if let Some(kv) = self.keys.get(i).filter(|kv| range.contains(&kv.key)) {
+        f(&kv.key, &kv.value);
+      }
+    }
+  }
+
+  /// `i` 番目の子ノードが担当するキーの範囲が `range` と重ならない場合に true を返します。
+  ///
+  fn skips_child<R: RangeBounds<KEY>>(&self, i: usize, range: &R) -> bool {
+    let below = self
+      .keys
+      .get(i)
+      .is_some_and(|kv| match range.start_bound() {
+        Bound::Included(start) => kv.key < *start,
+        Bound::Excluded(start) => kv.key <= *start,
+        Bound::Unbounded => false,
+      });
+    let above = i > 0
+      && match range.end_bound() {
+        Bound::Included(end) => self.keys[i - 1].key > *end,
+        Bound::Excluded(end) => self.keys[i - 1].key >= *end,
+        Bound::Unbounded => false,
+      };
+    below || above
+  }
+}
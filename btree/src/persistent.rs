@@ -0,0 +1,385 @@
+use alloc::rc::{Rc, Weak};
+use alloc::vec::Vec;
+use core::borrow;
+use core::cell::RefCell;
+use core::fmt;
+
+/// 更新によって元のツリーを変更せず、新しいバージョンのツリーを返す永続 B-Tree です。
+///
+/// 更新はルートから対象のエントリまでの経路上のノードのみを複製し (パスコピー)、変更されない部分木は元の
+/// ツリーと `Rc` で共有します。ノードは一度構築されると変更されないため `RefCell` を必要とせず、古いバージョン
+/// のツリーはそのまま読み取り専用のスナップショットとして参照できます。
+///
+/// `Clone` はルートを共有するだけの安価な操作で、同じバージョンのツリーを複製します。
+///
+pub struct PersistentBTree<KEY, VALUE, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  root: Rc<Node<KEY, VALUE>>,
+  len: usize,
+  /// 同じツリーから派生したすべてのバージョンのルートです。
+  versions: Versions<KEY, VALUE>,
+}
+
+impl<KEY, VALUE, const S: usize> PersistentBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  pub fn new() -> Self {
+    let root = Rc::new(Node::new());
+    let versions = Rc::new(RefCell::new(Vec::from([Rc::downgrade(&root)])));
+    PersistentBTree {
+      root,
+      len: 0,
+      versions,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn get<Q>(&self, key: &Q) -> Option<&VALUE>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut node = &*self.root;
+    loop {
+      match node.find_index(key) {
+        Ok(i) => return Some(&node.keys[i].1),
+        Err(i) if !node.is_leaf() => node = &node.children[i],
+        Err(_) => return None,
+      }
+    }
+  }
+
+  pub fn contains_key<Q>(&self, key: &Q) -> bool
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.get(key).is_some()
+  }
+
+  /// Key-Value ペアを挿入した新しいバージョンのツリーを返します。既に同じキーが存在する場合、新しいツリーでは
+  /// その値が置き換えられます。このツリーは変更されません。
+  ///
+  pub fn put(&self, key: KEY, value: VALUE) -> Self {
+    let mut root = self.root.clone();
+    let (split, old_value) = Rc::make_mut(&mut root).upsert::<S>(key, value);
+    if let Some((keyval, right)) = split {
+      root = Rc::new(Node {
+        keys: Vec::from([keyval]),
+        children: Vec::from([root, Rc::new(right)]),
+      });
+    }
+    let len = if old_value.is_none() {
+      self.len + 1
+    } else {
+      self.len
+    };
+    self.derive(root, len)
+  }
+
+  /// 指定されたキーのエントリを削除した新しいバージョンのツリーを返します。キーが存在しない場合はこのツリー
+  /// と同じバージョンを返します。このツリーは変更されません。
+  ///
+  pub fn delete<Q>(&self, key: &Q) -> Self
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    if !self.contains_key(key) {
+      return self.clone();
+    }
+    let mut root = self.root.clone();
+    Rc::make_mut(&mut root).delete::<Q, S>(key);
+    if root.keys.is_empty() && !root.is_leaf() {
+      root = root.children[0].clone();
+    }
+    self.derive(root, self.len - 1)
+  }
+
+  /// このツリーと同じツリーから派生したバージョンのうち、現在も参照可能なもの (このツリー自身を含む) の数を
+  /// 返します。破棄されたバージョンのルートは、他のバージョンから共有されていなければ数えられません。
+  ///
+  pub fn version_count(&self) -> usize {
+    self
+      .versions
+      .borrow()
+      .iter()
+      .filter(|root| root.strong_count() > 0)
+      .count()
+  }
+
+  /// すべての Key-Value ペアをキーの昇順で参照するイテレータを返します。
+  ///
+  pub fn iter(&self) -> Iter<'_, KEY, VALUE> {
+    let mut iter = Iter { stack: Vec::new() };
+    iter.push_leftmost(&self.root);
+    iter
+  }
+
+  fn derive(&self, root: Rc<Node<KEY, VALUE>>, len: usize) -> Self {
+    {
+      let mut versions = self.versions.borrow_mut();
+      versions.retain(|root| root.strong_count() > 0);
+      versions.push(Rc::downgrade(&root));
+    }
+    PersistentBTree {
+      root,
+      len,
+      versions: self.versions.clone(),
+    }
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Clone for PersistentBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn clone(&self) -> Self {
+    PersistentBTree {
+      root: self.root.clone(),
+      len: self.len,
+      versions: self.versions.clone(),
+    }
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Default for PersistentBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<KEY, VALUE, const S: usize> fmt::Debug for PersistentBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + fmt::Debug,
+  VALUE: Clone + fmt::Debug,
+{
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_map().entries(self.iter()).finish()
+  }
+}
+
+impl<KEY, VALUE, const S: usize> FromIterator<(KEY, VALUE)> for PersistentBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// 各エントリを順に挿入したツリーを構築します。途中のバージョンは保持されないため、構築したツリーの
+  /// `version_count` は 1 となります。
+  ///
+  fn from_iter<I: IntoIterator<Item = (KEY, VALUE)>>(iter: I) -> Self {
+    let mut tree = PersistentBTree::new();
+    for (key, value) in iter {
+      tree = tree.put(key, value);
+    }
+    tree
+  }
+}
+
+/// `PersistentBTree` のエントリをキーの昇順で参照するイテレータです。
+///
+pub struct Iter<'a, KEY, VALUE> {
+  stack: Vec<(&'a Node<KEY, VALUE>, usize)>,
+}
+
+impl<'a, KEY, VALUE> Iter<'a, KEY, VALUE> {
+  fn push_leftmost(&mut self, mut node: &'a Node<KEY, VALUE>) {
+    loop {
+      self.stack.push((node, 0));
+      if node.children.is_empty() {
+        break;
+      }
+      node = &node.children[0];
+    }
+  }
+}
+
+impl<'a, KEY, VALUE> Iterator for Iter<'a, KEY, VALUE> {
+  type Item = (&'a KEY, &'a VALUE);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    while let Some((node, i)) = self.stack.pop() {
+      if i < node.keys.len() {
+        self.stack.push((node, i + 1));
+        if !node.children.is_empty() {
+          self.push_leftmost(&node.children[i + 1]);
+        }
+        let (key, value) = &node.keys[i];
+        return Some((key, value));
+      }
+    }
+    None
+  }
+}
+
+/// 永続 B-Tree のノードです。子ノードを持たないノードが葉ノードとなります。ノードが複数のバージョンから共有
+/// されている場合、更新は `Rc::make_mut` によってそのノードを複製してから行います。
+///
+#[derive(Clone)]
+struct Node<KEY, VALUE> {
+  keys: Vec<(KEY, VALUE)>,
+  children: Vec<Rc<Node<KEY, VALUE>>>,
+}
+
+type Versions<KEY, VALUE> = Rc<RefCell<Vec<Weak<Node<KEY, VALUE>>>>>;
+
+type SplitPropagation<KEY, VALUE> = Option<((KEY, VALUE), Node<KEY, VALUE>)>;
+
+impl<KEY, VALUE> Node<KEY, VALUE>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn new() -> Self {
+    Node {
+      keys: Vec::new(),
+      children: Vec::new(),
+    }
+  }
+
+  fn is_leaf(&self) -> bool {
+    self.children.is_empty()
+  }
+
+  fn find_index<Q>(&self, key: &Q) -> Result<usize, usize>
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self
+      .keys
+      .binary_search_by(|(k, _)| borrow::Borrow::borrow(k).cmp(key))
+  }
+
+  fn upsert<const S: usize>(
+    &mut self,
+    key: KEY,
+    value: VALUE,
+  ) -> (SplitPropagation<KEY, VALUE>, Option<VALUE>) {
+    match self.find_index(&key) {
+      Ok(i) => {
+        let old_value = core::mem::replace(&mut self.keys[i].1, value);
+        (None, Some(old_value))
+      }
+      Err(i) if self.is_leaf() => {
+        self.keys.insert(i, (key, value));
+        (self.split::<S>(), None)
+      }
+      Err(i) => {
+        let (split, old_value) = Rc::make_mut(&mut self.children[i]).upsert::<S>(key, value);
+        if let Some((keyval, right)) = split {
+          self.keys.insert(i, keyval);
+          self.children.insert(i + 1, Rc::new(right));
+          (self.split::<S>(), old_value)
+        } else {
+          (None, old_value)
+        }
+      }
+    }
+  }
+
+  fn split<const S: usize>(&mut self) -> SplitPropagation<KEY, VALUE> {
+    if self.keys.len() <= 2 * S {
+      return None;
+    }
+    let mut right = Node::new();
+    right.keys.extend(self.keys.drain(S + 1..));
+    let keyval = self.keys.pop().unwrap();
+    if !self.is_leaf() {
+      right.children.extend(self.children.drain(S + 1..));
+    }
+    Some((keyval, right))
+  }
+
+  /// 指定されたキーのエントリを削除します。キーはこの部分木に存在しなければなりません。
+  ///
+  fn delete<Q, const S: usize>(&mut self, key: &Q) -> (KEY, VALUE)
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    match self.find_index(key) {
+      Ok(i) if self.is_leaf() => self.keys.remove(i),
+      Err(_) if self.is_leaf() => unreachable!("the key must exist in the subtree"),
+      Ok(i) => {
+        let predecessor = Rc::make_mut(&mut self.children[i]).remove_max::<S>();
+        let removed = core::mem::replace(&mut self.keys[i], predecessor);
+        self.rebalance::<S>(i);
+        removed
+      }
+      Err(i) => {
+        let removed = Rc::make_mut(&mut self.children[i]).delete::<Q, S>(key);
+        self.rebalance::<S>(i);
+        removed
+      }
+    }
+  }
+
+  fn remove_max<const S: usize>(&mut self) -> (KEY, VALUE) {
+    if self.is_leaf() {
+      return self.keys.pop().unwrap();
+    }
+    let last = self.children.len() - 1;
+    let keyval = Rc::make_mut(&mut self.children[last]).remove_max::<S>();
+    self.rebalance::<S>(last);
+    keyval
+  }
+
+  /// `i` 番目の子ノードのキー数が `S` 未満であれば、兄弟ノードからキーを移動するかマージします。変更する兄弟
+  /// ノードが他のバージョンと共有されている場合は複製されます。
+  ///
+  fn rebalance<const S: usize>(&mut self, i: usize) {
+    if self.children[i].keys.len() >= S {
+      return;
+    }
+    if i > 0 && self.children[i - 1].keys.len() > S {
+      // 左ノードのキーを再配分
+      let left = Rc::make_mut(&mut self.children[i - 1]);
+      let keyval = left.keys.pop().unwrap();
+      let child = left.children.pop();
+      let separator = core::mem::replace(&mut self.keys[i - 1], keyval);
+      let node = Rc::make_mut(&mut self.children[i]);
+      node.keys.insert(0, separator);
+      if let Some(child) = child {
+        node.children.insert(0, child);
+      }
+    } else if i + 1 < self.children.len() && self.children[i + 1].keys.len() > S {
+      // 右ノードのキーを再配分
+      let right = Rc::make_mut(&mut self.children[i + 1]);
+      let keyval = right.keys.remove(0);
+      let child = (!right.is_leaf()).then(|| right.children.remove(0));
+      let separator = core::mem::replace(&mut self.keys[i], keyval);
+      let node = Rc::make_mut(&mut self.children[i]);
+      node.keys.push(separator);
+      if let Some(child) = child {
+        node.children.push(child);
+      }
+    } else {
+      // 左右いずれかのノードとマージ
+      let j = if i > 0 { i - 1 } else { i };
+      let separator = self.keys.remove(j);
+      let right = self.children.remove(j + 1);
+      let left = Rc::make_mut(&mut self.children[j]);
+      left.keys.push(separator);
+      left.keys.extend(right.keys.iter().cloned());
+      left.children.extend(right.children.iter().cloned());
+    }
+  }
+}
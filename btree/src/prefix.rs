@@ -0,0 +1,168 @@
+use crate::{BTree, Iter};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Bound;
+
+/// 文字列のキーを 1 文字ずつの枝で表したトライ木です。`BTree::build_prefix_tree` で構築し、共通の接頭辞を
+/// 持つキーの列挙に使用します。
+///
+#[derive(Debug, Clone)]
+pub struct PrefixTree<VALUE> {
+  root: TrieNode<VALUE>,
+  len: usize,
+}
+
+#[derive(Debug, Clone)]
+struct TrieNode<VALUE> {
+  /// このノードまでの文字列と一致するキーとその値です。
+  entry: Option<(String, VALUE)>,
+  children: BTreeMap<char, TrieNode<VALUE>>,
+}
+
+impl<VALUE> TrieNode<VALUE> {
+  fn new() -> Self {
+    TrieNode {
+      entry: None,
+      children: BTreeMap::new(),
+    }
+  }
+}
+
+impl<VALUE> PrefixTree<VALUE> {
+  fn new() -> Self {
+    PrefixTree {
+      root: TrieNode::new(),
+      len: 0,
+    }
+  }
+
+  fn insert(&mut self, key: String, value: VALUE) {
+    let mut node = &mut self.root;
+    for ch in key.chars() {
+      node = node.children.entry(ch).or_insert_with(TrieNode::new);
+    }
+    if node.entry.replace((key, value)).is_none() {
+      self.len += 1;
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn get(&self, key: &str) -> Option<&VALUE> {
+    self.find(key)?.entry.as_ref().map(|(_, value)| value)
+  }
+
+  /// `prefix` で始まるすべてのキーとその値をキーの昇順で列挙します。接頭辞に対応するノードまでを辿った後は
+  /// その部分木だけを走査するため、他のキーの数に影響されません。
+  ///
+  pub fn prefix_iter(&self, prefix: &str) -> impl Iterator<Item = (&str, &VALUE)> {
+    PrefixIter {
+      stack: self.find(prefix).into_iter().collect(),
+    }
+  }
+
+  fn find(&self, key: &str) -> Option<&TrieNode<VALUE>> {
+    let mut node = &self.root;
+    for ch in key.chars() {
+      node = node.children.get(&ch)?;
+    }
+    Some(node)
+  }
+}
+
+/// トライ木の部分木を行きがけ順に走査するイテレータです。あるキーはそれを接頭辞とするキーより前に、兄弟は
+/// 文字の昇順に訪れるため、列挙はキーの昇順となります。
+///
+struct PrefixIter<'a, VALUE> {
+  stack: Vec<&'a TrieNode<VALUE>>,
+}
+
+impl<'a, VALUE> Iterator for PrefixIter<'a, VALUE> {
+  type Item = (&'a str, &'a VALUE);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    while let Some(node) = self.stack.pop() {
+      self.stack.extend(node.children.values().rev());
+      if let Some((key, value)) = &node.entry {
+        return Some((key.as_str(), value));
+      }
+    }
+    None
+  }
+}
+
+impl<VALUE, const S: usize> BTree<String, VALUE, S>
+where
+  VALUE: Clone,
+{
+  /// このツリーのすべてのキーからトライ木を構築します。キーの平均長を `m` として O(n m) で動作します。
+  ///
+  pub fn build_prefix_tree(&self) -> PrefixTree<VALUE> {
+    let mut tree = PrefixTree::new();
+    for (key, value) in self.iter() {
+      tree.insert(key, value);
+    }
+    tree
+  }
+
+  /// `prefix` で始まるすべてのキーのエントリをキーの昇順で参照するイテレータを返します。`String` の順序は
+  /// UTF-8 のバイト列の順序と一致するため、`BTree<Vec<u8>, _, _>::prefix_range` と同じくバイト列として範囲を
+  /// 求めます。参照回数は更新されません。
+  ///
+  pub fn prefix_range<'a>(
+    &'a self,
+    prefix: &'a str,
+  ) -> impl Iterator<Item = (&'a String, &'a VALUE)> {
+    let upper = prefix_upper_bound(prefix.as_bytes());
+    let mut iter = Iter::seek(self, Bound::Included(&String::from(prefix)));
+    core::iter::from_fn(move || iter.next_entry())
+      .take_while(move |kv| {
+        upper
+          .as_ref()
+          .is_none_or(|upper| kv.key.as_bytes() < upper.as_slice())
+      })
+      .map(|kv| (&kv.key, &kv.value))
+  }
+}
+
+impl<VALUE, const S: usize> BTree<Vec<u8>, VALUE, S>
+where
+  VALUE: Clone,
+{
+  /// `prefix` で始まるすべてのキーのエントリをキーの昇順で参照するイテレータを返します。`prefix` 以上
+  /// `prefix_upper_bound` 未満の範囲を探索するため、範囲の先頭までの O(log n) の探索の後は該当するエントリ
+  /// のみを走査します。参照回数は更新されません。
+  ///
+  pub fn prefix_range<'a>(
+    &'a self,
+    prefix: &'a [u8],
+  ) -> impl Iterator<Item = (&'a Vec<u8>, &'a VALUE)> {
+    let upper = prefix_upper_bound(prefix);
+    let mut iter = Iter::seek(self, Bound::Included(&Vec::from(prefix)));
+    core::iter::from_fn(move || iter.next_entry())
+      .take_while(move |kv| upper.as_ref().is_none_or(|upper| kv.key < *upper))
+      .map(|kv| (&kv.key, &kv.value))
+  }
+}
+
+/// `prefix` で始まるすべてのバイト列より大きい最小のバイト列、すなわち末尾の `0xFF` を取り除いた上で最後の
+/// バイトを 1 つ増やしたものを返します。`prefix` が空か `0xFF` のみからなる場合は上限が存在しないため None を
+/// 返します。
+///
+fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+  let mut upper = Vec::from(prefix);
+  while let Some(last) = upper.pop() {
+    if last < 0xFF {
+      upper.push(last + 1);
+      return Some(upper);
+    }
+  }
+  None
+}
@@ -0,0 +1,83 @@
+use crate::BTree;
+
+/// 優先度の昇順に要素を取り出す B-Tree による優先度付きキューです。同じ優先度の要素はキューに追加された
+/// 順に取り出されます。
+///
+/// 内部では追加ごとに割り当てる通し番号を優先度に加えた `(PRIORITY, u64)` をキーとして使用するため、同じ
+/// 優先度の要素を重複して格納できます。通し番号は要素の ID として `push` から返され、`change_priority` で
+/// 要素を指定するために使用します。
+///
+#[derive(Debug, Clone, Default)]
+pub struct BTreePriorityQueue<PRIORITY, ITEM, const S: usize>
+where
+  PRIORITY: Ord + Clone,
+  ITEM: Copy,
+{
+  queue: BTree<(PRIORITY, u64), ITEM, S>,
+  /// 各要素の ID から現在の優先度を引く索引です。
+  priorities: BTree<u64, PRIORITY, S>,
+  next_id: u64,
+}
+
+impl<PRIORITY, ITEM, const S: usize> BTreePriorityQueue<PRIORITY, ITEM, S>
+where
+  PRIORITY: Ord + Clone,
+  ITEM: Copy,
+{
+  pub fn new() -> Self {
+    BTreePriorityQueue {
+      queue: BTree::new(),
+      priorities: BTree::new(),
+      next_id: 0,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.queue.size()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// 指定された優先度で要素を追加し、その要素に割り当てた ID を返します。
+  ///
+  pub fn push(&mut self, priority: PRIORITY, item: ITEM) -> u64 {
+    let id = self.next_id;
+    self.next_id += 1;
+    self.priorities.put(id, priority.clone());
+    self.queue.put((priority, id), item);
+    id
+  }
+
+  /// 最も優先度の小さい要素を取り除いて返します。同じ優先度の要素が複数ある場合は最も先に追加されたものを
+  /// 返します。
+  ///
+  pub fn pop(&mut self) -> Option<(PRIORITY, ITEM)> {
+    let ((priority, id), item) = self.queue.pop_first()?;
+    self.priorities.delete(&id);
+    Some((priority, item))
+  }
+
+  /// 次に `pop` で取り出される要素を参照します。
+  ///
+  pub fn peek(&self) -> Option<(&PRIORITY, &ITEM)> {
+    self
+      .queue
+      .select(0)
+      .map(|((priority, _), item)| (priority, item))
+  }
+
+  /// 指定された ID の要素の優先度を変更します。優先度を変更した要素は、同じ優先度の要素の中では元の ID の
+  /// 順に並びます。ID の要素がキューに存在しない場合は何もせずに false を返します。
+  ///
+  pub fn change_priority(&mut self, id: u64, new_priority: PRIORITY) -> bool {
+    let Some(old_priority) = self.priorities.get_cloned(&id) else {
+      return false;
+    };
+    self.priorities.put(id, new_priority.clone());
+    let item = self.queue.delete(&(old_priority, id)).unwrap();
+    self.queue.put((new_priority, id), item);
+    true
+  }
+}
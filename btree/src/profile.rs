@@ -0,0 +1,167 @@
+use crate::{BTree, NodeRef, STRUCTURE_CHANGES};
+use std::borrow;
+use std::time::{Duration, Instant};
+
+/// ツリーに対する 1 つの操作です。
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op<KEY, VALUE> {
+  Get(KEY),
+  Put(KEY, VALUE),
+  Delete(KEY),
+}
+
+/// `BTree::profile_operations` で計測した結果です。平均時間はその種類の操作が 1 つも含まれていない場合に
+/// `None` となります。
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OperationProfile {
+  pub total_time: Duration,
+  pub average_get_time: Option<Duration>,
+  pub average_put_time: Option<Duration>,
+  pub average_delete_time: Option<Duration>,
+  /// 操作によってノードが分割された回数です。
+  pub node_splits: u64,
+  /// 操作によってノードが併合された回数です。
+  pub node_merges: u64,
+  /// すべての操作を終えた時点のツリーの階層数です。
+  pub levels_reached: usize,
+  /// いずれかの操作がルートから辿った可能性のある最大の階層数、すなわち各操作の開始時点での階層数の最大値です。
+  pub max_traversal_depth: usize,
+}
+
+/// `BTree::lookup_trace` で記録したキーの探索の経路です。
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LookupTrace {
+  /// 探索を終えたノードの深さです。ルートを 0 とします。
+  pub depth: usize,
+  pub nodes_visited: usize,
+  pub found: bool,
+  /// ルートから順に、訪れた各ノードで選んだ位置とそのノードのキー数の組です。位置は内部ノードでは辿った子
+  /// ノードのインデックス、探索を終えたノードではキーが一致した、または挿入されるべきインデックスです。
+  pub path: Vec<(usize, usize)>,
+}
+
+/// `BTree::measure_iter_overhead` で計測した 1 回の走査の所要時間です。
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IterOverhead {
+  /// すべてのエントリを走査する時間の中央値です。
+  pub traversal_time: Duration,
+  /// `traversal_time` をエントリ数で割った 1 エントリあたりの時間です。空のツリーでは 0 となります。
+  pub per_entry_time: Duration,
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// このツリーに `ops` を先頭から順に適用し、その所要時間やノードの構造の変化を計測します。ツリーの現在の
+  /// 状態で特定の負荷がどのような性能を示すかを調べるための簡易的なベンチマークです。
+  ///
+  pub fn profile_operations(&mut self, ops: &[Op<KEY, VALUE>]) -> OperationProfile {
+    let mut times = [Duration::ZERO; 3];
+    let mut counts = [0u32; 3];
+    let mut max_traversal_depth = 0;
+    let (splits_before, merges_before) = STRUCTURE_CHANGES.with(|c| c.get());
+    for op in ops {
+      max_traversal_depth = max_traversal_depth.max(self.level());
+      let start = Instant::now();
+      let kind = match op {
+        Op::Get(key) => {
+          std::hint::black_box(self.get(key));
+          0
+        }
+        Op::Put(key, value) => {
+          self.put(key.clone(), value.clone());
+          1
+        }
+        Op::Delete(key) => {
+          self.delete(key);
+          2
+        }
+      };
+      times[kind] += start.elapsed();
+      counts[kind] += 1;
+    }
+    let (splits_after, merges_after) = STRUCTURE_CHANGES.with(|c| c.get());
+    let average = |kind: usize| (counts[kind] > 0).then(|| times[kind] / counts[kind]);
+    OperationProfile {
+      total_time: times.iter().sum(),
+      average_get_time: average(0),
+      average_put_time: average(1),
+      average_delete_time: average(2),
+      node_splits: splits_after - splits_before,
+      node_merges: merges_after - merges_before,
+      levels_reached: self.level(),
+      max_traversal_depth,
+    }
+  }
+
+  /// `key` を探索する過程で訪れたノードとその中で選んだ位置を記録します。`print` による調査なしに探索で触れた
+  /// ノードの数を調べたり、探索が特定の経路に偏っていないかを確認したりするために使用します。参照回数は更新
+  /// されません。
+  ///
+  pub fn lookup_trace<Q>(&self, key: &Q) -> LookupTrace
+  where
+    KEY: borrow::Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut path = Vec::new();
+    let mut node: NodeRef<KEY, VALUE, S> = self.root.clone();
+    let found = loop {
+      let next = {
+        let n = node.borrow();
+        match n.find_index(key) {
+          Ok(i) => {
+            path.push((i, n.keys.len()));
+            break true;
+          }
+          Err(i) => {
+            path.push((i, n.keys.len()));
+            if n.is_leaf {
+              break false;
+            }
+            n.pivots[i].clone()
+          }
+        }
+      };
+      node = next;
+    };
+    LookupTrace {
+      depth: path.len() - 1,
+      nodes_visited: path.len(),
+      found,
+      path,
+    }
+  }
+
+  /// `iter` によるキーの昇順の全件走査を 10 回繰り返してそれぞれの所要時間を計測し、その中央値と 1 エントリ
+  /// あたりの時間を返します。現在の実装の走査のオーバーヘッドを定量化し、性能の劣化を追跡するために使用
+  /// します。計測する時間には各エントリのキーと値の複製が含まれます。
+  ///
+  pub fn measure_iter_overhead(&self) -> IterOverhead {
+    const REPS: usize = 10;
+    let mut times = [Duration::ZERO; REPS];
+    for time in times.iter_mut() {
+      let start = Instant::now();
+      for entry in self.iter() {
+        std::hint::black_box(entry);
+      }
+      *time = start.elapsed();
+    }
+    times.sort_unstable();
+    let traversal_time = times[REPS / 2];
+    let per_entry_time = match u32::try_from(self.size()) {
+      Ok(0) => Duration::ZERO,
+      Ok(size) => traversal_time / size,
+      Err(_) => Duration::from_secs_f64(traversal_time.as_secs_f64() / self.size() as f64),
+    };
+    IterOverhead {
+      traversal_time,
+      per_entry_time,
+    }
+  }
+}
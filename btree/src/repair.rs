@@ -0,0 +1,54 @@
+use crate::{BTree, NodeRef};
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// ルートから `path` の各インデックスの子ノードを順に辿った位置にあるノードのキーを並べ直します。空の
+  /// `path` はルートを表します。ノードが存在しない場合は何もせずに false を返します。
+  ///
+  /// ノード内でキーの順序が崩れてしまったツリーから、可能な限りエントリを取り出すための調査・復旧用の操作です。
+  /// 並べ直したノードの最小または最大のキーが親ノードの左右の区切りのキーを越えている場合は、区切りのキーと
+  /// 交換してノードを並べ直すことを繰り返し、ノードのキーが区切りの範囲に収まるようにします。
+  ///
+  /// 内部ノードではキーのみを並べ直し、子ノードの並びは変更しません。また、交換によって親ノードに移動した
+  /// キーが他の子ノードとの順序を満たす保証はないため、兄弟ノードを改めて修復する必要が生じることがあります。
+  /// いずれの場合もツリーが論理的に矛盾した状態となり得るため、修復後のツリーは `validate` などで確認して
+  /// ください。
+  ///
+  pub fn repair_node(&mut self, path: &[usize]) -> bool {
+    let mut parent: Option<(NodeRef<KEY, VALUE, S>, usize)> = None;
+    let mut node = self.root.clone();
+    for &i in path {
+      let child = {
+        let n = node.borrow();
+        match n.pivots.get(i) {
+          Some(child) if !n.is_leaf => child.clone(),
+          _ => return false,
+        }
+      };
+      parent = Some((node, i));
+      node = child;
+    }
+
+    let mut n = node.borrow_mut();
+    n.keys.sort_by(|a, b| a.key.cmp(&b.key));
+    if let Some((parent, i)) = parent {
+      let mut p = parent.borrow_mut();
+      if i > 0 && !n.keys.is_empty() {
+        while p.keys[i - 1].key > n.keys[0].key {
+          core::mem::swap(&mut p.keys[i - 1], &mut n.keys[0]);
+          n.keys.sort_by(|a, b| a.key.cmp(&b.key));
+        }
+      }
+      if i < p.keys.len() {
+        while let Some(last) = n.keys.last_mut().filter(|last| last.key > p.keys[i].key) {
+          core::mem::swap(&mut p.keys[i], last);
+          n.keys.sort_by(|a, b| a.key.cmp(&b.key));
+        }
+      }
+    }
+    true
+  }
+}
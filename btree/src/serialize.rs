@@ -0,0 +1,174 @@
+use crate::{storage, BTree, Error, Result};
+use serde::de::{self, DeserializeOwned, SeqAccess, Visitor};
+use serde::ser::{SerializeSeq, SerializeTuple};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+
+/// `Serialize` が出力する形式のバージョンです。形式を変更した場合はこの値を更新します。
+///
+pub const FORMAT_VERSION: u8 = 1;
+
+/// `save_to_file` が出力するファイルの先頭を示すマジックナンバーです。
+///
+const FILE_MAGIC: [u8; 4] = *b"BTRE";
+
+/// `save_to_file` が出力するファイルのヘッダです。ツリー本体より前に配置され、本体のデシリアライズ前に検証
+/// されます。
+///
+#[derive(Debug, Serialize, Deserialize)]
+struct FileHeader {
+  magic: [u8; 4],
+  version: u8,
+  order: u64,
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize,
+  VALUE: Clone + Serialize,
+{
+  /// マジックナンバー `b"BTRE"`、形式のバージョン、次数 `S` からなるヘッダに続けて、このツリーを指定された
+  /// ファイルに保存します。書き込んだバイト数を返します。
+  ///
+  pub fn save_to_file(&self, path: &str) -> Result<usize> {
+    let header = FileHeader {
+      magic: FILE_MAGIC,
+      version: FORMAT_VERSION,
+      order: S as u64,
+    };
+    storage::write_to_file(&(header, self), path)
+  }
+
+  /// このツリーのスナップショットを指定された出力先に書き込みます。
+  ///
+  pub fn save_snapshot<W: Write>(&self, writer: &mut W) -> Result<()> {
+    bincode::serialize_into(writer, self)?;
+    Ok(())
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + DeserializeOwned,
+  VALUE: Clone + DeserializeOwned,
+{
+  /// `save_to_file` で保存されたファイルからツリーを読み込みます。ヘッダのマジックナンバーやバージョンが一致
+  /// しない場合は `Error::InvalidHeader` を、保存されたツリーの次数が `S` と異なる場合は
+  /// `Error::IncompatibleFormat` を返します。
+  ///
+  pub fn load_from_file(path: &str) -> Result<Self> {
+    let bytes = storage::read_bytes(path)?;
+    let header: FileHeader = bincode::deserialize(&bytes)?;
+    if header.magic != FILE_MAGIC {
+      return Err(Error::InvalidHeader(format!(
+        "unknown magic number {:?}",
+        header.magic
+      )));
+    }
+    if header.version != FORMAT_VERSION {
+      return Err(Error::InvalidHeader(format!(
+        "unsupported format version {}",
+        header.version
+      )));
+    }
+    if header.order != S as u64 {
+      return Err(Error::IncompatibleFormat {
+        expected_s: S,
+        found_s: header.order as usize,
+      });
+    }
+    let (_, btree): (FileHeader, Self) = bincode::deserialize(&bytes)?;
+    Ok(btree)
+  }
+
+  /// `save_snapshot` で書き込まれたスナップショットを読み込みます。
+  ///
+  pub fn load_snapshot<R: Read>(reader: &mut R) -> Result<Self> {
+    Ok(bincode::deserialize_from(reader)?)
+  }
+}
+
+/// ツリーは `(FORMAT_VERSION, [(key, value), ...])` の 2 要素のタプルとしてシリアライズされます。エントリは
+/// キーの昇順に並び、ノード構造は含まれません。
+///
+impl<KEY, VALUE, const S: usize> Serialize for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize,
+  VALUE: Clone + Serialize,
+{
+  fn serialize<SE: Serializer>(&self, serializer: SE) -> std::result::Result<SE::Ok, SE::Error> {
+    let mut tuple = serializer.serialize_tuple(2)?;
+    tuple.serialize_element(&FORMAT_VERSION)?;
+    tuple.serialize_element(&Entries(self))?;
+    tuple.end()
+  }
+}
+
+struct Entries<'a, KEY, VALUE, const S: usize>(&'a BTree<KEY, VALUE, S>)
+where
+  KEY: Ord + Clone,
+  VALUE: Clone;
+
+impl<KEY, VALUE, const S: usize> Serialize for Entries<'_, KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize,
+  VALUE: Clone + Serialize,
+{
+  fn serialize<SE: Serializer>(&self, serializer: SE) -> std::result::Result<SE::Ok, SE::Error> {
+    // bincode などシーケンスの長さを先に必要とする形式があるため要素数を数えてから出力する
+    let mut seq = serializer.serialize_seq(Some(self.0.size()))?;
+    for entry in self.0.iter() {
+      seq.serialize_element(&entry)?;
+    }
+    seq.end()
+  }
+}
+
+/// キーの昇順に並んだエントリから `from_sorted_iter` によって O(n) でツリーを再構築します。形式のバージョンが
+/// 一致しない場合やキーが狭義単調増加でない場合はエラーとなります。
+///
+impl<'de, KEY, VALUE, const S: usize> Deserialize<'de> for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + DeserializeOwned,
+  VALUE: Clone + DeserializeOwned,
+{
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    deserializer.deserialize_tuple(2, BTreeVisitor(PhantomData))
+  }
+}
+
+struct BTreeVisitor<KEY, VALUE, const S: usize>(PhantomData<(KEY, VALUE)>);
+
+impl<'de, KEY, VALUE, const S: usize> Visitor<'de> for BTreeVisitor<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + DeserializeOwned,
+  VALUE: Clone + DeserializeOwned,
+{
+  type Value = BTree<KEY, VALUE, S>;
+
+  fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("a format version and a sequence of key-value pairs")
+  }
+
+  fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+    let version: u8 = seq
+      .next_element()?
+      .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+    if version != FORMAT_VERSION {
+      return Err(de::Error::custom(format!(
+        "unsupported BTree format version: {version} (expected {FORMAT_VERSION})"
+      )));
+    }
+    let entries: Vec<(KEY, VALUE)> = seq
+      .next_element()?
+      .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+    if entries.windows(2).any(|w| w[0].0 >= w[1].0) {
+      return Err(de::Error::custom(
+        "BTree entries are not in strictly ascending key order",
+      ));
+    }
+    Ok(BTree::from_sorted_iter(entries))
+  }
+}
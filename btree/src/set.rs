@@ -0,0 +1,183 @@
+use crate::{BTree, Node};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::ops::{Bound, RangeBounds};
+
+/// 値を持たずキーだけを格納する B-Tree による集合です。値の型 `()` は大きさを持たないため、値のための領域は
+/// 消費しません。`std::collections::BTreeSet` に相当します。
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BTreeSet<KEY: Ord + Clone, const S: usize>(BTree<KEY, (), S>);
+
+impl<KEY: Ord + Clone, const S: usize> BTreeSet<KEY, S> {
+  pub fn new() -> Self {
+    BTreeSet(BTree::new())
+  }
+
+  pub fn len(&self) -> usize {
+    self.0.size()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// キーを追加します。集合に存在しなかったキーを追加した場合は true を返します。
+  ///
+  pub fn insert(&mut self, key: KEY) -> bool {
+    self.0.put(key, ()).is_none()
+  }
+
+  /// キーを削除します。集合に存在したキーを削除した場合は true を返します。
+  ///
+  pub fn remove<Q>(&mut self, key: &Q) -> bool
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.0.delete(key).is_some()
+  }
+
+  pub fn contains<Q>(&self, key: &Q) -> bool
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.0.get(key).is_some()
+  }
+
+  /// すべてのキーを昇順で列挙します。
+  ///
+  pub fn iter(&self) -> impl Iterator<Item = &KEY> {
+    Keys::seek(&self.0, Bound::Unbounded)
+  }
+
+  /// `r` に含まれるキーを昇順で列挙します。範囲の下限の位置まではルートから直接辿るため O(log n) で最初の
+  /// キーに到達します。
+  ///
+  pub fn range<R: RangeBounds<KEY>>(&self, r: R) -> impl Iterator<Item = &KEY> {
+    let end = r.end_bound().cloned();
+    Keys::seek(&self.0, r.start_bound()).take_while(move |key| match &end {
+      Bound::Included(end) => *key <= end,
+      Bound::Excluded(end) => *key < end,
+      Bound::Unbounded => true,
+    })
+  }
+
+  /// `self` と `other` の少なくとも一方に含まれるキーからなる集合を返します。
+  ///
+  pub fn union(&self, other: &BTreeSet<KEY, S>) -> BTreeSet<KEY, S> {
+    self.merge_join(other, true, true, true)
+  }
+
+  /// `self` と `other` の両方に含まれるキーからなる集合を返します。
+  ///
+  pub fn intersection(&self, other: &BTreeSet<KEY, S>) -> BTreeSet<KEY, S> {
+    self.merge_join(other, false, true, false)
+  }
+
+  /// `self` に含まれ `other` に含まれないキーからなる集合を返します。
+  ///
+  pub fn difference(&self, other: &BTreeSet<KEY, S>) -> BTreeSet<KEY, S> {
+    self.merge_join(other, true, false, false)
+  }
+
+  /// 両方の集合のキーを昇順に突き合わせ、`self` だけ、両方、`other` だけに含まれるキーのうち指定されたものを
+  /// 含む集合を O(n + m) で構築します。
+  ///
+  fn merge_join(&self, other: &BTreeSet<KEY, S>, left: bool, both: bool, right: bool) -> Self {
+    let (mut a, mut b) = (self.iter().peekable(), other.iter().peekable());
+    let mut keys = Vec::new();
+    loop {
+      let (key, keep) = match (a.peek(), b.peek()) {
+        (None, None) => break,
+        (Some(_), None) => (a.next(), left),
+        (None, Some(_)) => (b.next(), right),
+        (Some(x), Some(y)) => match x.cmp(y) {
+          Ordering::Less => (a.next(), left),
+          Ordering::Greater => (b.next(), right),
+          Ordering::Equal => {
+            b.next();
+            (a.next(), both)
+          }
+        },
+      };
+      if keep {
+        keys.extend(key.cloned().map(|key| (key, ())));
+      }
+    }
+    BTreeSet(BTree::from_sorted_iter(keys))
+  }
+}
+
+impl<KEY: Ord + Clone, const S: usize> FromIterator<KEY> for BTreeSet<KEY, S> {
+  fn from_iter<I: IntoIterator<Item = KEY>>(iter: I) -> Self {
+    BTreeSet(iter.into_iter().map(|key| (key, ())).collect())
+  }
+}
+
+/// 集合のキーへの参照を昇順で列挙するイテレータです。`Iter` と同じく根から現在位置までのノードと、各ノードで
+/// 次に参照するキーのインデックスをスタックとして保持します。
+///
+struct Keys<'a, KEY: Ord + Clone, const S: usize> {
+  stack: Vec<(&'a Node<KEY, (), S>, usize)>,
+}
+
+impl<'a, KEY: Ord + Clone, const S: usize> Keys<'a, KEY, S> {
+  /// `start` 以降の最初のキーを指すイテレータを構築します。
+  ///
+  fn seek(tree: &'a BTree<KEY, (), S>, start: Bound<&KEY>) -> Self {
+    let mut keys = Keys { stack: Vec::new() };
+    let mut node = node_ref(tree.root.as_ptr());
+    loop {
+      let i = match start {
+        Bound::Included(key) => node.keys.partition_point(|kv| kv.key < *key),
+        Bound::Excluded(key) => node.keys.partition_point(|kv| kv.key <= *key),
+        Bound::Unbounded => 0,
+      };
+      keys.stack.push((node, i));
+      if node.is_leaf {
+        break;
+      }
+      node = node_ref(node.pivots[i].as_ptr());
+    }
+    keys
+  }
+}
+
+impl<'a, KEY: Ord + Clone, const S: usize> Iterator for Keys<'a, KEY, S> {
+  type Item = &'a KEY;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      let (node, i) = self.stack.last_mut()?;
+      let node: &'a Node<KEY, (), S> = node;
+      if *i >= node.keys.len() {
+        self.stack.pop();
+        continue;
+      }
+      *i += 1;
+      let key = &node.keys[*i - 1].key;
+      if !node.is_leaf {
+        let mut child = node_ref(node.pivots[*i].as_ptr());
+        loop {
+          self.stack.push((child, 0));
+          if child.is_leaf {
+            break;
+          }
+          child = node_ref(child.pivots[0].as_ptr());
+        }
+      }
+      return Some(key);
+    }
+  }
+}
+
+fn node_ref<'a, KEY: Ord + Clone, const S: usize>(
+  node: *mut Node<KEY, (), S>,
+) -> &'a Node<KEY, (), S> {
+  // SAFETY: `Index` と同じく、ノードが可変に借用されるのは `&mut self` を受け取る操作の中だけであり、イテレータ
+  // が有効な間 (集合の借用期間) はツリーが変更されることはない。
+  unsafe { &*node }
+}
@@ -0,0 +1,116 @@
+use crate::{BTree, Iter, KeyVal};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+/// 両方のツリーに存在するキーのエントリからなるツリーを O(n + m) で構築します。値は `resolve` に
+/// `(キー, a の値, b の値)` を渡して決定します。
+///
+pub fn intersection<KEY, VALUE, const S: usize>(
+  a: &BTree<KEY, VALUE, S>,
+  b: &BTree<KEY, VALUE, S>,
+  resolve: impl Fn(&KEY, &VALUE, &VALUE) -> VALUE,
+) -> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  merge_join(a, b, |entry| match entry {
+    Joined::Both(x, y) => Some(resolve(&x.key, &x.value, &y.value)),
+    Joined::Left(_) | Joined::Right(_) => None,
+  })
+}
+
+/// いずれかのツリーに存在するキーのエントリからなるツリーを O(n + m) で構築します。両方に存在するキーの値は
+/// `resolve` に `(キー, a の値, b の値)` を渡して決定します。
+///
+pub fn union<KEY, VALUE, const S: usize>(
+  a: &BTree<KEY, VALUE, S>,
+  b: &BTree<KEY, VALUE, S>,
+  resolve: impl Fn(&KEY, &VALUE, &VALUE) -> VALUE,
+) -> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  merge_join(a, b, |entry| match entry {
+    Joined::Both(x, y) => Some(resolve(&x.key, &x.value, &y.value)),
+    Joined::Left(x) | Joined::Right(x) => Some(x.value.clone()),
+  })
+}
+
+/// `a` に存在し `b` に存在しないキーのエントリからなるツリーを、`a` の値のまま O(n + m) で構築します。
+///
+pub fn difference<KEY, VALUE, const S: usize>(
+  a: &BTree<KEY, VALUE, S>,
+  b: &BTree<KEY, VALUE, S>,
+) -> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  merge_join(a, b, |entry| match entry {
+    Joined::Left(x) => Some(x.value.clone()),
+    Joined::Both(..) | Joined::Right(_) => None,
+  })
+}
+
+/// `merge_join` で突き合わせたエントリが `a` と `b` のどちらに存在したかを表します。
+///
+enum Joined<'a, KEY: Clone, VALUE: Clone> {
+  Left(&'a KeyVal<KEY, VALUE>),
+  Both(&'a KeyVal<KEY, VALUE>, &'a KeyVal<KEY, VALUE>),
+  Right(&'a KeyVal<KEY, VALUE>),
+}
+
+/// 両方のツリーのエントリをキーの昇順に突き合わせ、`f` が値を返したキーのエントリからなるツリーを構築します。
+///
+fn merge_join<KEY, VALUE, const S: usize, F>(
+  a: &BTree<KEY, VALUE, S>,
+  b: &BTree<KEY, VALUE, S>,
+  mut f: F,
+) -> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+  F: FnMut(Joined<'_, KEY, VALUE>) -> Option<VALUE>,
+{
+  let mut left = Iter::new(a);
+  let mut right = Iter::new(b);
+  let (mut x, mut y) = (left.next_entry(), right.next_entry());
+  let mut entries = Vec::new();
+  loop {
+    let joined = match (x, y) {
+      (None, None) => break,
+      (Some(kv), None) => {
+        x = left.next_entry();
+        Joined::Left(kv)
+      }
+      (None, Some(kv)) => {
+        y = right.next_entry();
+        Joined::Right(kv)
+      }
+      (Some(p), Some(q)) => match p.key.cmp(&q.key) {
+        Ordering::Less => {
+          x = left.next_entry();
+          Joined::Left(p)
+        }
+        Ordering::Greater => {
+          y = right.next_entry();
+          Joined::Right(q)
+        }
+        Ordering::Equal => {
+          x = left.next_entry();
+          y = right.next_entry();
+          Joined::Both(p, q)
+        }
+      },
+    };
+    let key = match &joined {
+      Joined::Left(kv) | Joined::Right(kv) | Joined::Both(kv, _) => kv.key.clone(),
+    };
+    if let Some(value) = f(joined) {
+      entries.push((key, value));
+    }
+  }
+  BTree::from_sorted_iter(entries)
+}
@@ -0,0 +1,181 @@
+#[cfg(feature = "std")]
+use crate::bucket_index;
+use crate::{BTree, Node};
+#[cfg(feature = "std")]
+use alloc::vec;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+use core::ops::{Add, RangeBounds};
+
+/// `BTree::stats` で集計したツリーの構造の統計情報です。
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TreeStats {
+  /// 格納されているエントリの数です。
+  pub size: usize,
+  /// 葉を 1 とした階層数です。
+  pub height: usize,
+  pub internal_node_count: usize,
+  pub leaf_count: usize,
+  pub total_node_count: usize,
+  pub min_keys_per_leaf: usize,
+  pub max_keys_per_leaf: usize,
+  /// すべてのノードのキー数の合計を、すべてのノードが最大の `2S` 個のキーを持つ場合の合計で割った値です。
+  pub avg_fill_factor: f64,
+}
+
+/// `BTree::summarize` で集計した範囲内のエントリの件数と値の最小値、最大値、合計です。
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeSummary<VALUE> {
+  pub count: usize,
+  pub min: VALUE,
+  pub max: VALUE,
+  pub sum: VALUE,
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// すべてのノードを 1 回走査してツリーの統計情報を集計します。性能の分析や圧縮の要否、適切な次数 `S` の
+  /// 判断に使用します。
+  ///
+  pub fn stats(&self) -> TreeStats {
+    let mut stats = TreeStats {
+      size: 0,
+      height: self.level(),
+      internal_node_count: 0,
+      leaf_count: 0,
+      total_node_count: 0,
+      min_keys_per_leaf: usize::MAX,
+      max_keys_per_leaf: 0,
+      avg_fill_factor: 0.0,
+    };
+    collect(&self.root.borrow(), &mut stats);
+    stats.total_node_count = stats.internal_node_count + stats.leaf_count;
+    stats.avg_fill_factor = stats.size as f64 / (2 * S * stats.total_node_count) as f64;
+    stats
+  }
+
+  /// 指定された範囲に含まれるキーを持つエントリの件数と値の最小値、最大値、合計を 1 回の O(k) の走査で
+  /// 集計します。`SELECT COUNT(*), MIN(v), MAX(v), SUM(v) FROM t WHERE k BETWEEN a AND b` に相当します。
+  /// 範囲にエントリが存在しない場合は最小値と最大値が定まらないため None を返します。参照回数は更新されません。
+  ///
+  pub fn summarize<R>(&self, key_range: R) -> Option<RangeSummary<VALUE>>
+  where
+    R: RangeBounds<KEY>,
+    VALUE: Ord + Add<Output = VALUE>,
+  {
+    self.fold_range(key_range, None, |summary, (_, value)| {
+      Some(match summary {
+        None => RangeSummary {
+          count: 1,
+          min: value.clone(),
+          max: value.clone(),
+          sum: value.clone(),
+        },
+        Some(RangeSummary {
+          count,
+          min,
+          max,
+          sum,
+        }) => RangeSummary {
+          count: count + 1,
+          min: if *value < min { value.clone() } else { min },
+          max: if *value > max { value.clone() } else { max },
+          sum: sum + value.clone(),
+        },
+      })
+    })
+  }
+}
+
+fn collect<KEY, VALUE, const S: usize>(node: &Node<KEY, VALUE, S>, stats: &mut TreeStats)
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  let keys = node.keys.len();
+  stats.size += keys;
+  if node.is_leaf {
+    stats.leaf_count += 1;
+    stats.min_keys_per_leaf = stats.min_keys_per_leaf.min(keys);
+    stats.max_keys_per_leaf = stats.max_keys_per_leaf.max(keys);
+  } else {
+    stats.internal_node_count += 1;
+    for pivot in node.pivots.iter() {
+      collect(&pivot.borrow(), stats);
+    }
+  }
+}
+
+#[cfg(feature = "std")]
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Into<i128>,
+  VALUE: Clone,
+{
+  /// 各キーに `1/n` の重みを与えたときの、キー空間上の分布のシャノンエントロピー (ビット) を返します。最小の
+  /// キーから最大のキーまでの範囲をエントリ数 `n` と同じ数の等幅の区間に分け、各区間に含まれるキーの割合から
+  /// 算出します。キーが範囲全体に均等に散らばっている場合は最大値の `log2(n)` に近づき、一部に集中している
+  /// ほど小さくなります。エントリが 1 個以下の場合は 0 を返します。
+  ///
+  pub fn key_entropy(&self) -> f64 {
+    let keys = self
+      .iter()
+      .map(|(key, _)| key.into())
+      .collect::<Vec<i128>>();
+    let n = keys.len();
+    if n <= 1 {
+      return 0.0;
+    }
+    let (min, max) = (keys[0], keys[n - 1]);
+    let span = max.abs_diff(min);
+    let mut counts = vec![0usize; n];
+    for key in keys.iter() {
+      counts[bucket_index(key.abs_diff(min), span, n)] += 1;
+    }
+    shannon_entropy(&counts, n)
+  }
+
+  /// キーの絶対値を 10 進数で表したときの各桁の数字の分布のシャノンエントロピー (ビット) を、1 の位から順に
+  /// 返します。各桁のエントロピーはその桁を持つキーだけから算出し、最大値は `log2(10)` です。「すべてのキーの
+  /// 末尾が 0」のように特定の桁に偏りがある場合、その桁のエントロピーが 0 に近づきます。
+  ///
+  pub fn digit_entropy(&self) -> Vec<f64> {
+    let mut counts: Vec<[usize; 10]> = Vec::new();
+    for (key, _) in self.iter() {
+      let mut rest = key.into().unsigned_abs();
+      let mut position = 0;
+      loop {
+        if counts.len() <= position {
+          counts.push([0; 10]);
+        }
+        counts[position][(rest % 10) as usize] += 1;
+        rest /= 10;
+        position += 1;
+        if rest == 0 {
+          break;
+        }
+      }
+    }
+    counts
+      .iter()
+      .map(|digits| shannon_entropy(digits, digits.iter().sum()))
+      .collect()
+  }
+}
+
+#[cfg(feature = "std")]
+fn shannon_entropy(counts: &[usize], total: usize) -> f64 {
+  counts
+    .iter()
+    .filter(|count| **count > 0)
+    .map(|count| {
+      let p = *count as f64 / total as f64;
+      -p * p.log2()
+    })
+    .sum()
+}
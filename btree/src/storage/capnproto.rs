@@ -0,0 +1,66 @@
+use crate::{BTree, Error, KeyVal, Result};
+use capnp::message::{Allocator, Builder, Reader, ReaderSegments};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[allow(clippy::all, dead_code)]
+pub mod btree_capnp {
+  include!(concat!(env!("OUT_DIR"), "/btree_capnp.rs"));
+}
+
+use self::btree_capnp::b_tree_message;
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize,
+  VALUE: Clone + Serialize,
+{
+  /// このツリーを `schema/btree.capnp` の `BTreeMessage` として指定された Cap'n Proto メッセージに書き込み、
+  /// そのメッセージを返します。エントリはキーの昇順に並び、キーと値はそれぞれ bincode でエンコードされた
+  /// `Data` となります。
+  ///
+  pub fn into_capnproto<A: Allocator>(&self, mut builder: Builder<A>) -> Result<Builder<A>> {
+    let size = self.size();
+    let mut message = builder.init_root::<b_tree_message::Builder>();
+    message.set_order(S as u32);
+    let mut entries = message.init_entries(size as u32);
+    for (i, (key, value)) in self.iter().enumerate() {
+      let mut entry = entries.reborrow().get(i as u32);
+      entry.set_key(&bincode::serialize(&key)?);
+      entry.set_value(&bincode::serialize(&value)?);
+    }
+    Ok(builder)
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + DeserializeOwned,
+  VALUE: Clone + DeserializeOwned,
+{
+  /// `into_capnproto` で書き込まれた Cap'n Proto メッセージからツリーを構築します。メッセージの次数が `S` と
+  /// 異なる場合は `Error::IncompatibleFormat` を返します。
+  ///
+  pub fn from_capnproto<R: ReaderSegments>(reader: &Reader<R>) -> Result<Self> {
+    let message = reader.get_root::<b_tree_message::Reader>()?;
+    if message.get_order() as usize != S {
+      return Err(Error::IncompatibleFormat {
+        expected_s: S,
+        found_s: message.get_order() as usize,
+      });
+    }
+    let mut entries: Vec<KeyVal<KEY, VALUE>> = Vec::new();
+    let mut sorted = true;
+    for entry in message.get_entries()?.iter() {
+      let key: KEY = bincode::deserialize(entry.get_key()?)?;
+      let value: VALUE = bincode::deserialize(entry.get_value()?)?;
+      sorted = sorted && entries.last().is_none_or(|last| last.key < key);
+      entries.push(KeyVal::new(key, value));
+    }
+    if sorted {
+      Ok(BTree::bulk_load(entries))
+    } else {
+      Ok(entries.into_iter().map(|kv| (kv.key, kv.value)).collect())
+    }
+  }
+}
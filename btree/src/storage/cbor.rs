@@ -0,0 +1,43 @@
+use crate::{BTree, KeyVal, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{Read, Write};
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize,
+  VALUE: Clone + Serialize,
+{
+  /// このツリーを `[[key, value], ...]` の CBOR 配列として書き込みます。CBOR のマップはキーの順序を保証しない
+  /// ため、エントリはキーの昇順に並べた配列として表現します。書き込んだバイト数を返します。
+  ///
+  pub fn write_to_cbor(&self, mut writer: impl Write) -> Result<usize> {
+    let entries = self.iter().collect::<Vec<_>>();
+    let mut encoded = Vec::new();
+    ciborium::into_writer(&entries, &mut encoded)?;
+    writer.write_all(&encoded)?;
+    Ok(encoded.len())
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + DeserializeOwned,
+  VALUE: Clone + DeserializeOwned,
+{
+  /// `write_to_cbor` で書き込まれた CBOR 配列からツリーを読み込みます。エントリがキーの昇順に並んでいない場合
+  /// も読み込むことができ、同じキーが複数存在する場合は後のエントリの値が採用されます。
+  ///
+  pub fn read_from_cbor(reader: impl Read) -> Result<Self> {
+    let entries: Vec<(KEY, VALUE)> = ciborium::from_reader(reader)?;
+    if entries.windows(2).all(|w| w[0].0 < w[1].0) {
+      let entries = entries
+        .into_iter()
+        .map(|(k, v)| KeyVal::new(k, v))
+        .collect();
+      Ok(BTree::bulk_load(entries))
+    } else {
+      Ok(entries.into_iter().collect())
+    }
+  }
+}
@@ -0,0 +1,60 @@
+use crate::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// ストレージへの書き込み時に値とバイト列とを相互に変換する形式です。
+///
+pub trait Codec {
+  fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>>;
+
+  fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T>;
+}
+
+/// bincode による `Codec` です。`write_to_file` や `read_from_file` が使用する既定の形式です。
+///
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+  fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(value)?)
+  }
+
+  fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+    Ok(bincode::deserialize(bytes)?)
+  }
+}
+
+/// JSON による `Codec` です。人が読める形式で保存する場合に使用します。
+///
+#[cfg(feature = "json")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonCodec;
+
+#[cfg(feature = "json")]
+impl Codec for JsonCodec {
+  fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(value)?)
+  }
+
+  fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+    Ok(serde_json::from_slice(bytes)?)
+  }
+}
+
+/// MessagePack による `Codec` です。
+///
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MessagePackCodec;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePackCodec {
+  fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+    Ok(rmp_serde::to_vec(value)?)
+  }
+
+  fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+    Ok(rmp_serde::from_slice(bytes)?)
+  }
+}
@@ -0,0 +1,69 @@
+use crate::storage::page::FLAG_FREE;
+use crate::storage::{replace_file, FilePageStore, PageStore};
+use crate::Result;
+
+/// `needs_compaction` が圧縮を必要と判断する解放済みページの割合の既定値です。
+///
+pub const DEFAULT_COMPACTION_THRESHOLD: f64 = 0.3;
+
+/// `compact` による圧縮の結果です。
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactionStats {
+  /// 圧縮前のファイルのバイト数です。
+  pub bytes_before: u64,
+  /// 圧縮後のファイルのバイト数です。
+  pub bytes_after: u64,
+  /// 圧縮によって削減されたバイト数の圧縮前に対する百分率です。
+  pub space_reclaimed_percent: f64,
+}
+
+/// `input_path` のページストアから使用中のページだけを読み出し、解放済みのページを含まない新しいページストア
+/// として `output_path` に書き出します。SQLite の `VACUUM` に相当する操作です。
+///
+/// 使用中のページは元の順序を保ったまま 1 から連続する番号に詰めて配置されるため、ページ番号を参照として
+/// 保持している場合は圧縮後に振り直す必要があります。圧縮したページストアは一時ファイルに書き出してから
+/// `output_path` と置き換えるため、`output_path` に既存のファイルがある場合は上書きし、`input_path` と同じ
+/// ファイルを指定した場合はその場で圧縮します。
+///
+pub fn compact(input_path: &str, output_path: &str) -> Result<CompactionStats> {
+  let mut input = FilePageStore::open_existing(input_path)?;
+  let bytes_before = std::fs::metadata(input_path)?.len();
+  replace_file(output_path, |temp| {
+    let mut output = FilePageStore::open(temp, input.page_size())?;
+    for id in 1..input.page_count() {
+      let (flags, data) = input.read_raw(id)?;
+      if flags & FLAG_FREE == 0 {
+        let new_id = output.alloc_page()?;
+        output.write_page(new_id, &data)?;
+      }
+    }
+    output.sync()
+  })?;
+
+  let bytes_after = std::fs::metadata(output_path)?.len();
+  let space_reclaimed_percent = if bytes_before == 0 {
+    0.0
+  } else {
+    bytes_before.saturating_sub(bytes_after) as f64 * 100.0 / bytes_before as f64
+  };
+  Ok(CompactionStats {
+    bytes_before,
+    bytes_after,
+    space_reclaimed_percent,
+  })
+}
+
+/// 指定されたページストアのファイルヘッダに記録されている解放済みページの割合が
+/// `DEFAULT_COMPACTION_THRESHOLD` を超えている場合に true を返します。
+///
+pub fn needs_compaction(path: &str) -> Result<bool> {
+  needs_compaction_with_threshold(path, DEFAULT_COMPACTION_THRESHOLD)
+}
+
+/// 指定されたページストアの解放済みページの割合が `threshold` (0.0 から 1.0) を超えている場合に true を返し
+/// ます。
+///
+pub fn needs_compaction_with_threshold(path: &str, threshold: f64) -> Result<bool> {
+  Ok(FilePageStore::open_existing(path)?.fragmentation() > threshold)
+}
@@ -0,0 +1,92 @@
+use crate::{BTree, Error, Result};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::{DeliveryFuture, FutureProducer, FutureRecord};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::future::Future;
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize,
+  VALUE: Clone + Serialize,
+{
+  /// このツリーのすべての Key-Value ペアをキーの昇順に 1 件ずつ Kafka のメッセージとして `topic` に送信し、
+  /// 送信したメッセージ数を返します。メッセージのキーと値はそれぞれツリーのキーと値を bincode でエンコード
+  /// したものです。
+  ///
+  /// エンコードはこのメソッドの呼び出し時に行われるため、返される `Future` はツリーを借用しません。すべての
+  /// メッセージの配信が確認された時点で完了し、いずれかの配信に失敗した場合はそのエラーを返します。
+  ///
+  pub fn stream_to_kafka(
+    &self,
+    topic: &str,
+    producer: &FutureProducer,
+  ) -> impl Future<Output = Result<usize>> + Send {
+    let messages = encode_messages(self);
+    let (topic, producer) = (topic.to_string(), producer.clone());
+    async move {
+      let messages = messages?;
+      let mut pending = VecDeque::<DeliveryFuture>::new();
+      for (key, value) in messages.iter() {
+        let mut record = to_record(&topic, key, value);
+        loop {
+          match producer.send_result(record) {
+            Ok(delivery) => {
+              pending.push_back(delivery);
+              break;
+            }
+            Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), r)) => {
+              // 送信キューに空きができるまで最も古いメッセージの配信を待つ
+              record = r;
+              match pending.pop_front() {
+                Some(delivery) => wait_for_delivery(delivery).await?,
+                None => producer.poll(std::time::Duration::from_millis(100)),
+              }
+            }
+            Err((e, _)) => return Err(e.into()),
+          }
+        }
+      }
+      while let Some(delivery) = pending.pop_front() {
+        wait_for_delivery(delivery).await?;
+      }
+      Ok(messages.len())
+    }
+  }
+}
+
+/// ツリーのすべての Key-Value ペアを、キーの昇順に bincode でエンコードしたメッセージのキーと値の組に変換
+/// します。
+///
+pub(crate) fn encode_messages<KEY, VALUE, const S: usize>(
+  btree: &BTree<KEY, VALUE, S>,
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>>
+where
+  KEY: Ord + Clone + Serialize,
+  VALUE: Clone + Serialize,
+{
+  btree
+    .iter()
+    .map(|(key, value)| Ok((bincode::serialize(&key)?, bincode::serialize(&value)?)))
+    .collect()
+}
+
+/// エンコードしたメッセージから `topic` へ送信するレコードを構築します。Kafka はメッセージのキーのハッシュで
+/// パーティションを決めるため、エンコードしたツリーのキーをそのままレコードのキーとし、同じキーのエントリが
+/// 常に同じパーティションへ送られるようにします。
+///
+pub(crate) fn to_record<'a>(
+  topic: &'a str,
+  key: &'a [u8],
+  value: &'a [u8],
+) -> FutureRecord<'a, [u8], [u8]> {
+  FutureRecord::to(topic).key(key).payload(value)
+}
+
+async fn wait_for_delivery(delivery: DeliveryFuture) -> Result<()> {
+  match delivery.await {
+    Ok(Ok(_)) => Ok(()),
+    Ok(Err((e, _))) => Err(e.into()),
+    Err(_) => Err(Error::Kafka(KafkaError::Canceled)),
+  }
+}
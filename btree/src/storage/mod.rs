@@ -1,11 +1,63 @@
-use crate::Result;
+use crate::{BTree, Result};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
+use std::path::Path;
 
+#[cfg(feature = "capnp")]
+pub mod capnproto;
+#[cfg(feature = "cbor")]
+mod cbor;
+mod codec;
+mod compact;
+#[cfg(all(feature = "async", feature = "kafka"))]
+pub(crate) mod kafka;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+#[cfg(feature = "json")]
+mod ndjson;
+mod page;
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "redis")]
+pub(crate) mod redis;
+#[cfg(feature = "aws")]
+mod s3;
+mod schema;
+mod wal;
+
+#[cfg(feature = "json")]
+pub use self::codec::JsonCodec;
+#[cfg(feature = "msgpack")]
+pub use self::codec::MessagePackCodec;
+pub use self::codec::{BincodeCodec, Codec};
+pub use self::compact::{
+  compact, needs_compaction, needs_compaction_with_threshold, CompactionStats,
+  DEFAULT_COMPACTION_THRESHOLD,
+};
+pub use self::page::{FilePageStore, PageId, PageStore, MAX_PAGE_SIZE, PAGE_HEADER_SIZE};
+#[cfg(feature = "parquet")]
+pub use self::parquet::PAGE_SIZE;
+#[cfg(feature = "aws")]
+pub use self::s3::MULTIPART_THRESHOLD;
+pub use self::schema::{Schema, TypeDescriptor, SCHEMA_VERSION};
+pub use self::wal::{ConsistencyReport, Wal, WalBTree, WalOp};
+
+/// 指定されたオブジェクトを bincode でエンコードしてファイルに保存し、書き込んだバイト数を返します。
+///
 pub fn write_to_file<T: Serialize>(obj: &T, filename: &str) -> Result<usize> {
-  let encoded = bincode::serialize(obj)?;
+  write_to_file_with(&BincodeCodec, obj, filename)
+}
+
+/// 指定されたオブジェクトを `codec` でエンコードしてファイルに保存し、書き込んだバイト数を返します。
+///
+pub fn write_to_file_with<C: Codec, T: Serialize>(
+  codec: &C,
+  obj: &T,
+  filename: &str,
+) -> Result<usize> {
+  let encoded = codec.encode(obj)?;
   let mut file = OpenOptions::new()
     .create(true)
     .truncate(true)
@@ -15,10 +67,155 @@ pub fn write_to_file<T: Serialize>(obj: &T, filename: &str) -> Result<usize> {
   Ok(encoded.len())
 }
 
+/// `write_to_file` で保存されたファイルからオブジェクトを読み込みます。
+///
 pub fn read_from_file<T: DeserializeOwned>(filename: &str) -> Result<T> {
+  read_from_file_with(&BincodeCodec, filename)
+}
+
+/// `write_to_file_with` で保存されたファイルから、同じ `codec` を使用してオブジェクトを読み込みます。
+///
+pub fn read_from_file_with<C: Codec, T: DeserializeOwned>(codec: &C, filename: &str) -> Result<T> {
+  let buffer = read_bytes(filename)?;
+  codec.decode(&buffer)
+}
+
+/// `path` と同じディレクトリの一時ファイルに `write` で内容を書き込み、ディスクへ同期してから `path` へ名前を
+/// 変更して置き換えます。名前の変更の後にディレクトリも同期するため、途中でプロセスが異常終了しても `path` には
+/// 置き換える前か後のいずれかの完全な内容が残ります。
+///
+pub(crate) fn replace_file<F>(path: &str, write: F) -> Result<()>
+where
+  F: FnOnce(&str) -> Result<()>,
+{
+  let temp = format!("{path}.{}.tmp", std::process::id());
+  let _ = std::fs::remove_file(&temp);
+  let result = write(&temp)
+    .and_then(|_| Ok(File::open(&temp)?.sync_all()?))
+    .and_then(|_| Ok(std::fs::rename(&temp, path)?));
+  if result.is_err() {
+    let _ = std::fs::remove_file(&temp);
+    return result;
+  }
+  #[cfg(unix)]
+  {
+    let dir = match Path::new(path).parent() {
+      Some(dir) if !dir.as_os_str().is_empty() => dir,
+      _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()?;
+  }
+  Ok(())
+}
+
+pub(crate) fn read_bytes(filename: &str) -> Result<Vec<u8>> {
   let mut file = OpenOptions::new().read(true).open(filename)?;
   let mut buffer = Vec::with_capacity(8 * 1024);
   file.read_to_end(&mut buffer)?;
-  let decoded = bincode::deserialize(&buffer)?;
-  Ok(decoded)
+  Ok(buffer)
+}
+
+/// リトルエンディアンの `u32` を読み込みます。1 バイトも読み込まずにストリームが終了した場合は None を返します。
+///
+fn read_u32_or_eof(reader: &mut impl Read) -> Result<Option<u32>> {
+  let mut bytes = [0u8; 4];
+  let mut filled = 0;
+  while filled < bytes.len() {
+    match reader.read(&mut bytes[filled..]) {
+      Ok(0) if filled == 0 => return Ok(None),
+      Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+      Ok(n) => filled += n,
+      Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+      Err(e) => return Err(e.into()),
+    }
+  }
+  Ok(Some(u32::from_le_bytes(bytes)))
+}
+
+/// `stream` から `len` バイトを `buffer` に読み込みます。長さはストリームから読んだ信頼できない値であるため、
+/// 先に `len` バイトの領域を確保せず、実際に読み込んだ分だけ `buffer` を伸ばします。
+///
+fn read_record(stream: &mut impl Read, len: u32, buffer: &mut Vec<u8>) -> Result<()> {
+  buffer.clear();
+  stream.take(len as u64).read_to_end(buffer)?;
+  if buffer.len() != len as usize {
+    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+  }
+  Ok(())
+}
+
+/// IEEE 802.3 の多項式による CRC32 を算出します。
+///
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+  const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+      let mut c = i as u32;
+      let mut k = 0;
+      while k < 8 {
+        c = if c & 1 != 0 {
+          0xEDB8_8320 ^ (c >> 1)
+        } else {
+          c >> 1
+        };
+        k += 1;
+      }
+      table[i] = c;
+      i += 1;
+    }
+    table
+  };
+  !bytes.iter().fold(!0u32, |crc, b| {
+    TABLE[((crc ^ *b as u32) & 0xFF) as usize] ^ (crc >> 8)
+  })
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize,
+  VALUE: Clone + Serialize,
+{
+  /// このツリーのすべての Key-Value ペアをキーの昇順に並べた列として bincode でエンコードします。
+  ///
+  pub fn encode_to_bytes(&self) -> Result<Vec<u8>> {
+    let entries = self.iter().collect::<Vec<_>>();
+    Ok(bincode::serialize(&entries)?)
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + DeserializeOwned,
+  VALUE: Clone + DeserializeOwned,
+{
+  /// `encode_to_bytes` でエンコードされたバイト列からツリーを復元します。
+  ///
+  pub fn decode_from_bytes(bytes: &[u8]) -> Result<Self> {
+    let entries: Vec<(KEY, VALUE)> = bincode::deserialize(bytes)?;
+    Ok(entries.into_iter().collect())
+  }
+
+  /// `[key_len: u32][key_bytes][value_len: u32][value_bytes]` を繰り返した長さ付きのバイナリレコードの列を
+  /// `stream` から読み込み、各レコードをこのツリーに挿入します。長さはリトルエンディアンで、キーと値はそれぞれ
+  /// bincode でデコードします。挿入したレコードの数を返します。
+  ///
+  /// レコードの境界でストリームが終了した時点で読み込みを終えます。レコードの途中で終了した場合は
+  /// `Error::IO` を返しますが、それまでに読み込んだレコードはツリーに挿入されたままとなります。
+  ///
+  pub fn byte_stream_insert(&mut self, mut stream: impl Read) -> Result<usize> {
+    let mut count = 0;
+    let mut buffer = Vec::new();
+    while let Some(key_len) = read_u32_or_eof(&mut stream)? {
+      read_record(&mut stream, key_len, &mut buffer)?;
+      let key: KEY = bincode::deserialize(&buffer)?;
+      let mut value_len = [0u8; 4];
+      stream.read_exact(&mut value_len)?;
+      read_record(&mut stream, u32::from_le_bytes(value_len), &mut buffer)?;
+      let value: VALUE = bincode::deserialize(&buffer)?;
+      self.put(key, value);
+      count += 1;
+    }
+    Ok(count)
+  }
 }
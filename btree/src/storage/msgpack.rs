@@ -0,0 +1,55 @@
+use crate::{BTree, Error, KeyVal, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{Read, Write};
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize,
+  VALUE: Clone + Serialize,
+{
+  /// このツリーを `[S, エントリ数, [[key, value], ...]]` の MessagePack 配列として書き込みます。エントリはキーの
+  /// 昇順に並びます。書き込んだバイト数を返します。
+  ///
+  pub fn write_msgpack(&self, mut writer: impl Write) -> Result<usize> {
+    let entries = self.iter().collect::<Vec<_>>();
+    let encoded = rmp_serde::to_vec(&(S as u64, entries.len() as u64, entries))?;
+    writer.write_all(&encoded)?;
+    Ok(encoded.len())
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + DeserializeOwned,
+  VALUE: Clone + DeserializeOwned,
+{
+  /// `write_msgpack` で書き込まれた MessagePack からツリーを読み込みます。ヘッダの次数が `S` と異なる場合は
+  /// `Error::IncompatibleFormat` を、エントリ数がヘッダと一致しない場合は `Error::InvalidHeader` を返します。
+  ///
+  pub fn read_msgpack(reader: impl Read) -> Result<Self> {
+    let (order, count, entries): (u64, u64, Vec<(KEY, VALUE)>) = rmp_serde::from_read(reader)?;
+    if order != S as u64 {
+      return Err(Error::IncompatibleFormat {
+        expected_s: S,
+        found_s: order as usize,
+      });
+    }
+    if count != entries.len() as u64 {
+      return Err(Error::InvalidHeader(format!(
+        "entry count {} does not match the {} entries stored",
+        count,
+        entries.len()
+      )));
+    }
+    if entries.windows(2).all(|w| w[0].0 < w[1].0) {
+      let entries = entries
+        .into_iter()
+        .map(|(k, v)| KeyVal::new(k, v))
+        .collect();
+      Ok(BTree::bulk_load(entries))
+    } else {
+      Ok(entries.into_iter().collect())
+    }
+  }
+}
@@ -0,0 +1,34 @@
+use crate::{BTree, Result};
+use serde::Serialize;
+use std::io::Write;
+
+/// NDJSON の 1 行として出力される Key-Value ペアです。
+///
+#[derive(Serialize)]
+struct Line<'a, KEY, VALUE> {
+  k: &'a KEY,
+  v: &'a VALUE,
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize,
+  VALUE: Clone + Serialize,
+{
+  /// すべての Key-Value ペアをキーの昇順に `{"k": key, "v": value}` の JSON オブジェクトとして 1 行ずつ書き込
+  /// み、書き込んだバイト数を返します。エントリは 1 件ずつエンコードして書き込まれるため、ツリー全体の JSON を
+  /// メモリ上に構築することはありません。
+  ///
+  pub fn write_to_ndjson(&self, mut writer: impl Write) -> Result<usize> {
+    let mut written = 0;
+    let mut line = Vec::new();
+    for (key, value) in self.iter() {
+      line.clear();
+      serde_json::to_writer(&mut line, &Line { k: &key, v: &value })?;
+      line.push(b'\n');
+      writer.write_all(&line)?;
+      written += line.len();
+    }
+    Ok(written)
+  }
+}
@@ -0,0 +1,304 @@
+use crate::storage::crc32;
+use crate::{Error, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// ページを識別する番号です。`FilePageStore` ではファイル先頭からのページ位置を表し、0 はファイルヘッダが使用
+/// します。
+///
+pub type PageId = u64;
+
+/// 固定長のページ単位で読み書きを行うストレージです。ディスク上に配置する B-Tree のノードを格納するための
+/// 基本操作を提供します。
+///
+pub trait PageStore {
+  /// 1 ページに格納できるデータの最大バイト数を参照します。
+  ///
+  fn page_capacity(&self) -> usize;
+
+  /// 指定されたページに書き込まれているデータを読み込みます。
+  ///
+  fn read_page(&mut self, id: PageId) -> Result<Box<[u8]>>;
+
+  /// 指定されたページにデータを書き込みます。`data` は `page_capacity()` 以下でなければなりません。
+  ///
+  fn write_page(&mut self, id: PageId, data: &[u8]) -> Result<()>;
+
+  /// 新しい空のページを割り当ててその番号を返します。解放済みのページがあれば再利用されます。
+  ///
+  fn alloc_page(&mut self) -> Result<PageId>;
+
+  /// 指定されたページを解放します。解放されたページは以後の `alloc_page` で再利用されます。
+  ///
+  fn free_page(&mut self, id: PageId) -> Result<()>;
+}
+
+const MAGIC: [u8; 4] = *b"BTPG";
+const VERSION: u32 = 2;
+const FILE_HEADER_SIZE: usize = 40;
+
+/// 各ページの先頭に置かれるヘッダのバイト数です。ヘッダは後続するバイト列の CRC32、データ長、フラグからなり
+/// ます。
+///
+pub const PAGE_HEADER_SIZE: usize = 16;
+
+pub(super) const FLAG_FREE: u8 = 1;
+
+/// ページサイズとして受け付ける最大のバイト数です。ページの読み込みではページサイズ分の領域を確保するため、
+/// 破損したファイルヘッダによって過大な領域が確保されないよう上限を設けます。
+///
+pub const MAX_PAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// ページサイズがファイルヘッダとページヘッダを格納でき、`MAX_PAGE_SIZE` を超えない場合に true を返します。
+///
+fn is_valid_page_size(page_size: usize) -> bool {
+  (PAGE_HEADER_SIZE + FILE_HEADER_SIZE..=MAX_PAGE_SIZE).contains(&page_size)
+}
+
+/// 固定長のページを 1 つのファイルに並べた `PageStore` の実装です。
+///
+/// ページ 0 はファイルヘッダで、マジックナンバー `b"BTPG"`、バージョン、ページサイズ、ページ数、解放済み
+/// ページのリストの先頭、解放済みページの数を持ちます。以降の各ページは `PAGE_HEADER_SIZE` バイトの
+/// ページヘッダとデータ領域からなり、解放済みのページはデータ領域に次の解放済みページの番号を持つ単方向
+/// リストを構成します。
+///
+#[derive(Debug)]
+pub struct FilePageStore {
+  file: File,
+  page_size: usize,
+  page_count: u64,
+  free_head: PageId,
+  free_count: u64,
+}
+
+impl FilePageStore {
+  /// 指定されたファイルをページストアとして開きます。ファイルが存在しない場合は `page_size` バイトのページを
+  /// 持つ新しいページストアを作成します。既存のファイルのページサイズが `page_size` と異なる場合はエラーと
+  /// なります。
+  ///
+  pub fn open<P: AsRef<Path>>(path: P, page_size: usize) -> Result<Self> {
+    if !is_valid_page_size(page_size) {
+      return Err(Error::IO(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("unsupported page size: {page_size}"),
+      )));
+    }
+    let file = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create(true)
+      .truncate(false)
+      .open(path)?;
+    if file.metadata()?.len() == 0 {
+      let mut store = FilePageStore {
+        file,
+        page_size,
+        page_count: 1,
+        free_head: 0,
+        free_count: 0,
+      };
+      store.write_file_header()?;
+      return Ok(store);
+    }
+
+    let store = Self::read_file_header(file)?;
+    if store.page_size != page_size {
+      return Err(Error::InvalidHeader(format!(
+        "page size mismatch: expected {page_size}, but found {}",
+        store.page_size
+      )));
+    }
+    Ok(store)
+  }
+
+  /// 既存のページストアのファイルを、ファイルヘッダに記録されているページサイズで開きます。
+  ///
+  pub fn open_existing<P: AsRef<Path>>(path: P) -> Result<Self> {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    Self::read_file_header(file)
+  }
+
+  fn read_file_header(mut file: File) -> Result<Self> {
+    let mut header = [0u8; FILE_HEADER_SIZE];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+    if header[0..4] != MAGIC || read_u32(&header, 4) != VERSION {
+      return Err(Error::InvalidHeader("not a page store file".to_string()));
+    }
+    let page_size = read_u32(&header, 8) as usize;
+    if !is_valid_page_size(page_size) {
+      return Err(Error::InvalidHeader(format!(
+        "unsupported page size: {page_size}"
+      )));
+    }
+    Ok(FilePageStore {
+      file,
+      page_size,
+      page_count: read_u64(&header, 16),
+      free_head: read_u64(&header, 24),
+      free_count: read_u64(&header, 32),
+    })
+  }
+
+  /// ファイルヘッダを含むページの総数を参照します。
+  ///
+  pub fn page_count(&self) -> u64 {
+    self.page_count
+  }
+
+  /// ページヘッダを含む 1 ページのバイト数を参照します。
+  ///
+  pub fn page_size(&self) -> usize {
+    self.page_size
+  }
+
+  /// 解放されて再利用を待っているページの数を参照します。
+  ///
+  pub fn dead_page_count(&self) -> u64 {
+    self.free_count
+  }
+
+  /// ファイルヘッダを除くページのうち、解放済みのページが占める割合を 0.0 から 1.0 の範囲で返します。
+  ///
+  pub fn fragmentation(&self) -> f64 {
+    if self.page_count <= 1 {
+      0.0
+    } else {
+      self.free_count as f64 / (self.page_count - 1) as f64
+    }
+  }
+
+  /// 書き込んだ内容をストレージデバイスに同期します。
+  ///
+  pub fn sync(&mut self) -> Result<()> {
+    self.file.sync_all()?;
+    Ok(())
+  }
+
+  fn write_file_header(&mut self) -> Result<()> {
+    let mut header = vec![0u8; self.page_size];
+    header[0..4].copy_from_slice(&MAGIC);
+    header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+    header[8..12].copy_from_slice(&(self.page_size as u32).to_le_bytes());
+    header[16..24].copy_from_slice(&self.page_count.to_le_bytes());
+    header[24..32].copy_from_slice(&self.free_head.to_le_bytes());
+    header[32..40].copy_from_slice(&self.free_count.to_le_bytes());
+    self.file.seek(SeekFrom::Start(0))?;
+    self.file.write_all(&header)?;
+    Ok(())
+  }
+
+  /// ページを読み込んでチェックサムを検証し、フラグとデータを返します。
+  ///
+  pub(super) fn read_raw(&mut self, id: PageId) -> Result<(u8, Vec<u8>)> {
+    self.check_range(id)?;
+    let mut page = vec![0u8; self.page_size];
+    self
+      .file
+      .seek(SeekFrom::Start(id * self.page_size as u64))?;
+    self.file.read_exact(&mut page)?;
+    let len = read_u32(&page, 4) as usize;
+    if read_u32(&page, 0) != crc32(&page[4..]) || len > self.page_capacity() {
+      return Err(Error::CorruptedPage(id));
+    }
+    let flags = page[8];
+    page.truncate(PAGE_HEADER_SIZE + len);
+    Ok((flags, page.split_off(PAGE_HEADER_SIZE)))
+  }
+
+  fn write_raw(&mut self, id: PageId, flags: u8, data: &[u8]) -> Result<()> {
+    if data.len() > self.page_capacity() {
+      return Err(Error::IO(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!(
+          "data size {} exceeds the page capacity {}",
+          data.len(),
+          self.page_capacity()
+        ),
+      )));
+    }
+    let mut page = vec![0u8; self.page_size];
+    page[4..8].copy_from_slice(&(data.len() as u32).to_le_bytes());
+    page[8] = flags;
+    page[PAGE_HEADER_SIZE..][..data.len()].copy_from_slice(data);
+    let checksum = crc32(&page[4..]);
+    page[0..4].copy_from_slice(&checksum.to_le_bytes());
+    self
+      .file
+      .seek(SeekFrom::Start(id * self.page_size as u64))?;
+    self.file.write_all(&page)?;
+    Ok(())
+  }
+
+  fn check_range(&self, id: PageId) -> Result<()> {
+    if id == 0 || id >= self.page_count {
+      return Err(Error::IO(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("page {id} is out of range"),
+      )));
+    }
+    Ok(())
+  }
+
+  fn check_in_use(&mut self, id: PageId) -> Result<Vec<u8>> {
+    let (flags, data) = self.read_raw(id)?;
+    if flags & FLAG_FREE != 0 {
+      return Err(Error::IO(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("page {id} is not allocated"),
+      )));
+    }
+    Ok(data)
+  }
+}
+
+impl PageStore for FilePageStore {
+  fn page_capacity(&self) -> usize {
+    self.page_size - PAGE_HEADER_SIZE
+  }
+
+  fn read_page(&mut self, id: PageId) -> Result<Box<[u8]>> {
+    Ok(self.check_in_use(id)?.into_boxed_slice())
+  }
+
+  fn write_page(&mut self, id: PageId, data: &[u8]) -> Result<()> {
+    self.check_in_use(id)?;
+    self.write_raw(id, 0, data)
+  }
+
+  fn alloc_page(&mut self) -> Result<PageId> {
+    let id = if self.free_head != 0 {
+      let id = self.free_head;
+      let (flags, data) = self.read_raw(id)?;
+      if flags & FLAG_FREE == 0 || data.len() != 8 {
+        return Err(Error::CorruptedPage(id));
+      }
+      self.free_head = read_u64(&data, 0);
+      self.free_count = self.free_count.saturating_sub(1);
+      id
+    } else {
+      self.page_count += 1;
+      self.page_count - 1
+    };
+    self.write_raw(id, 0, &[])?;
+    self.write_file_header()?;
+    Ok(id)
+  }
+
+  fn free_page(&mut self, id: PageId) -> Result<()> {
+    self.check_in_use(id)?;
+    self.write_raw(id, FLAG_FREE, &self.free_head.to_le_bytes())?;
+    self.free_head = id;
+    self.free_count += 1;
+    self.write_file_header()
+  }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+  u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+  u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
@@ -0,0 +1,94 @@
+use crate::{BTree, KeyVal, Result};
+use parquet::basic::Type as PhysicalType;
+use parquet::data_type::{ByteArray, ByteArrayType};
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::writer::SerializedFileWriter;
+use parquet::record::RowAccessor;
+use parquet::schema::types::Type;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+/// 1 つの行グループに書き込むエントリ数です。
+///
+pub const PAGE_SIZE: usize = 8 * 1024;
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize + DeserializeOwned,
+  VALUE: Clone + Serialize + DeserializeOwned,
+{
+  /// このツリーのすべての Key-Value を Apache Parquet 形式のファイルに書き込みます。キーと値はそれぞれ bincode
+  /// でエンコードされた `key` 列と `value` 列となり、行はキーの昇順で `PAGE_SIZE` 件ごとの行グループに分割
+  /// されます。
+  ///
+  pub fn write_to_parquet(&self, path: &Path) -> Result<()> {
+    let mut writer = SerializedFileWriter::new(
+      File::create(path)?,
+      schema()?,
+      Arc::new(WriterProperties::builder().build()),
+    )?;
+    let mut iter = self.iter().peekable();
+    while iter.peek().is_some() {
+      let mut keys = Vec::with_capacity(PAGE_SIZE);
+      let mut values = Vec::with_capacity(PAGE_SIZE);
+      for (key, value) in iter.by_ref().take(PAGE_SIZE) {
+        keys.push(ByteArray::from(bincode::serialize(&key)?));
+        values.push(ByteArray::from(bincode::serialize(&value)?));
+      }
+      let mut row_group = writer.next_row_group()?;
+      for column in [keys, values] {
+        if let Some(mut writer) = row_group.next_column()? {
+          writer
+            .typed::<ByteArrayType>()
+            .write_batch(&column, None, None)?;
+          writer.close()?;
+        }
+      }
+      row_group.close()?;
+    }
+    writer.close()?;
+    Ok(())
+  }
+
+  /// `write_to_parquet` で書き込まれた Parquet ファイルからツリーを構築します。行がキーの昇順に並んでいる場合
+  /// はそのままボトムアップに一括構築し、そうでなければ整列してから構築します。
+  ///
+  pub fn read_from_parquet(path: &Path) -> Result<Self> {
+    let reader = SerializedFileReader::new(File::open(path)?)?;
+    let mut entries: Vec<KeyVal<KEY, VALUE>> =
+      Vec::with_capacity(reader.metadata().file_metadata().num_rows() as usize);
+    let mut sorted = true;
+    for row in reader.get_row_iter(None)? {
+      let row = row?;
+      let key: KEY = bincode::deserialize(row.get_bytes(0)?.data())?;
+      let value: VALUE = bincode::deserialize(row.get_bytes(1)?.data())?;
+      sorted = sorted && entries.last().is_none_or(|last| last.key < key);
+      entries.push(KeyVal::new(key, value));
+    }
+    if sorted {
+      Ok(BTree::bulk_load(entries))
+    } else {
+      Ok(entries.into_iter().map(|kv| (kv.key, kv.value)).collect())
+    }
+  }
+}
+
+fn schema() -> Result<Arc<Type>> {
+  let fields = ["key", "value"]
+    .into_iter()
+    .map(|name| {
+      Type::primitive_type_builder(name, PhysicalType::BYTE_ARRAY)
+        .with_repetition(parquet::basic::Repetition::REQUIRED)
+        .build()
+        .map(Arc::new)
+    })
+    .collect::<parquet::errors::Result<Vec<_>>>()?;
+  let schema = Type::group_type_builder("btree")
+    .with_fields(fields)
+    .build()?;
+  Ok(Arc::new(schema))
+}
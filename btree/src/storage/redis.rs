@@ -0,0 +1,106 @@
+use crate::{BTree, Error, KeyVal, Result};
+use redis::Commands;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize,
+  VALUE: Clone + Serialize,
+{
+  /// このツリーのすべてのエントリを、bincode でエンコードしたキーを 16 進数で表した `{key_prefix}:{encoded_key}`
+  /// をキー、bincode でエンコードした値をバイト列の値とする Redis の文字列として格納します。すべての `SET` は
+  /// 1 つのトランザクションとして送信されます。格納したエントリの数を返します。
+  ///
+  pub fn write_to_redis(&self, client: &redis::Client, key_prefix: &str) -> Result<usize> {
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    let mut count = 0;
+    for (key, value) in self.iter() {
+      let (key, value) = encode_entry(key_prefix, &key, &value)?;
+      pipe.set(key, value).ignore();
+      count += 1;
+    }
+    let mut con = client.get_connection()?;
+    pipe.query::<()>(&mut con)?;
+    Ok(count)
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + DeserializeOwned,
+  VALUE: Clone + DeserializeOwned,
+{
+  /// `write_to_redis` で格納された `{key_prefix}:` で始まるすべてのキーを走査してツリーを構築します。Redis の
+  /// 走査順序は不定であるため、取得したエントリをキーで整列してからボトムアップに一括構築します。
+  ///
+  pub fn read_from_redis(client: &redis::Client, key_prefix: &str) -> Result<Self> {
+    let mut con = client.get_connection()?;
+    let keys = con
+      .scan_match::<_, String>(format!("{key_prefix}:*"))?
+      .collect::<Vec<_>>();
+    if keys.is_empty() {
+      return Ok(BTree::new());
+    }
+    let values: Vec<Vec<u8>> = con.mget(&keys)?;
+    let mut entries = Vec::with_capacity(keys.len());
+    for (key, value) in keys.iter().zip(values.iter()) {
+      let (key, value): (KEY, VALUE) = decode_entry(key_prefix, key, value)?;
+      entries.push(KeyVal::new(key, value));
+    }
+    entries.sort_by(|a, b| a.key.cmp(&b.key));
+    entries.dedup_by(|a, b| a.key == b.key);
+    Ok(BTree::bulk_load(entries))
+  }
+}
+
+/// 1 つのエントリを `write_to_redis` が格納する Redis のキーと値に変換します。
+///
+pub(crate) fn encode_entry<KEY: Serialize, VALUE: Serialize>(
+  key_prefix: &str,
+  key: &KEY,
+  value: &VALUE,
+) -> Result<(String, Vec<u8>)> {
+  let key = format!("{}:{}", key_prefix, to_hex(&bincode::serialize(key)?));
+  Ok((key, bincode::serialize(value)?))
+}
+
+/// `encode_entry` で変換された Redis のキーと値からエントリを復元します。キーが `{key_prefix}:` で始まらない
+/// 場合や 16 進数として解釈できない場合はエラーとなります。
+///
+pub(crate) fn decode_entry<KEY: DeserializeOwned, VALUE: DeserializeOwned>(
+  key_prefix: &str,
+  key: &str,
+  value: &[u8],
+) -> Result<(KEY, VALUE)> {
+  let encoded = key
+    .strip_prefix(key_prefix)
+    .and_then(|key| key.strip_prefix(':'))
+    .ok_or_else(|| Error::IO(std::io::Error::other(format!("unexpected key: {key}"))))?;
+  let encoded = from_hex(encoded)?;
+  Ok((
+    bincode::deserialize(&encoded)?,
+    bincode::deserialize(value)?,
+  ))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+  let invalid = || Error::IO(std::io::Error::other(format!("invalid encoded key: {hex}")));
+  if !hex.len().is_multiple_of(2) {
+    return Err(invalid());
+  }
+  (0..hex.len())
+    .step_by(2)
+    .map(|i| {
+      hex
+        .get(i..i + 2)
+        .and_then(|b| u8::from_str_radix(b, 16).ok())
+        .ok_or_else(invalid)
+    })
+    .collect()
+}
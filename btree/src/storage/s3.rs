@@ -0,0 +1,137 @@
+use crate::{BTree, Result};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::Client;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+
+/// この大きさを超えるツリーはマルチパートアップロードで転送されます。S3 のマルチパートアップロードでは最後を
+/// 除く各パートが 5MiB 以上である必要があるため、パートの大きさも同じ値とします。
+///
+pub const MULTIPART_THRESHOLD: usize = 5 * 1024 * 1024;
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize,
+  VALUE: Clone + Serialize,
+{
+  /// このツリーを `encode_to_bytes` でエンコードし、Amazon S3 の `bucket` に `key` という名前の 1 つのオブジェ
+  /// クトとしてアップロードします。エンコード後の大きさが `MULTIPART_THRESHOLD` を超える場合はマルチパート
+  /// アップロードを使用します。
+  ///
+  /// エンコードはこのメソッドの呼び出し時に行われるため、返される `Future` はツリーを借用しません。
+  ///
+  pub fn write_to_s3(
+    &self,
+    bucket: &str,
+    key: &str,
+    client: &Client,
+  ) -> impl Future<Output = Result<()>> + Send {
+    let encoded = self.encode_to_bytes();
+    let (bucket, key, client) = (bucket.to_string(), key.to_string(), client.clone());
+    async move {
+      let encoded = encoded?;
+      if encoded.len() <= MULTIPART_THRESHOLD {
+        client
+          .put_object()
+          .bucket(&bucket)
+          .key(&key)
+          .body(ByteStream::from(encoded))
+          .send()
+          .await
+          .map_err(aws_sdk_s3::Error::from)?;
+        return Ok(());
+      }
+
+      let upload = client
+        .create_multipart_upload()
+        .bucket(&bucket)
+        .key(&key)
+        .send()
+        .await
+        .map_err(aws_sdk_s3::Error::from)?;
+      let upload_id = upload.upload_id().unwrap_or_default().to_string();
+      let result = upload_parts(&client, &bucket, &key, &upload_id, &encoded).await;
+      if result.is_err() {
+        // 失敗したアップロードのパートが課金対象として残らないように破棄する
+        let _ = client
+          .abort_multipart_upload()
+          .bucket(&bucket)
+          .key(&key)
+          .upload_id(&upload_id)
+          .send()
+          .await;
+      }
+      result
+    }
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + DeserializeOwned,
+  VALUE: Clone + DeserializeOwned,
+{
+  /// `write_to_s3` でアップロードされたオブジェクトをダウンロードし、ツリーを復元します。
+  ///
+  pub async fn read_from_s3(bucket: &str, key: &str, client: &Client) -> Result<Self> {
+    let object = client
+      .get_object()
+      .bucket(bucket)
+      .key(key)
+      .send()
+      .await
+      .map_err(aws_sdk_s3::Error::from)?;
+    let bytes = object
+      .body
+      .collect()
+      .await
+      .map_err(std::io::Error::other)?
+      .into_bytes();
+    BTree::decode_from_bytes(&bytes)
+  }
+}
+
+async fn upload_parts(
+  client: &Client,
+  bucket: &str,
+  key: &str,
+  upload_id: &str,
+  encoded: &[u8],
+) -> Result<()> {
+  let mut parts = Vec::new();
+  for (i, chunk) in encoded.chunks(MULTIPART_THRESHOLD).enumerate() {
+    let part_number = i as i32 + 1;
+    let part = client
+      .upload_part()
+      .bucket(bucket)
+      .key(key)
+      .upload_id(upload_id)
+      .part_number(part_number)
+      .body(ByteStream::from(chunk.to_vec()))
+      .send()
+      .await
+      .map_err(aws_sdk_s3::Error::from)?;
+    parts.push(
+      CompletedPart::builder()
+        .set_e_tag(part.e_tag)
+        .part_number(part_number)
+        .build(),
+    );
+  }
+  client
+    .complete_multipart_upload()
+    .bucket(bucket)
+    .key(key)
+    .upload_id(upload_id)
+    .multipart_upload(
+      CompletedMultipartUpload::builder()
+        .set_parts(Some(parts))
+        .build(),
+    )
+    .send()
+    .await
+    .map_err(aws_sdk_s3::Error::from)?;
+  Ok(())
+}
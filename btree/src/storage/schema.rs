@@ -0,0 +1,63 @@
+use crate::BTree;
+use serde::{Deserialize, Serialize};
+use std::mem::size_of;
+
+/// `Schema` の形式のバージョンです。
+///
+pub const SCHEMA_VERSION: u8 = 1;
+
+/// キーまたは値の Rust の型を表す記述子です。
+///
+/// 型名は `std::any::type_name` によるもので、コンパイラのバージョンによって表記が変わる可能性があるため、
+/// 同じビルド環境で保存されたファイルの検証に使用します。
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TypeDescriptor {
+  pub name: String,
+  pub size: usize,
+}
+
+impl TypeDescriptor {
+  /// 型 `T` の記述子を返します。
+  ///
+  pub fn of<T>() -> Self {
+    TypeDescriptor {
+      name: std::any::type_name::<T>().to_string(),
+      size: size_of::<T>(),
+    }
+  }
+}
+
+/// ツリーのキーと値の型、次数からなるスキーマです。ファイルに保存したツリーを読み込む前に、現在のコードが
+/// 期待する型と一致するかを検証するために使用します。
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Schema {
+  pub key_type: TypeDescriptor,
+  pub value_type: TypeDescriptor,
+  pub order: usize,
+  pub version: u8,
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// この型のツリーのスキーマを返します。
+  ///
+  pub fn export_schema() -> Schema {
+    Schema {
+      key_type: TypeDescriptor::of::<KEY>(),
+      value_type: TypeDescriptor::of::<VALUE>(),
+      order: S,
+      version: SCHEMA_VERSION,
+    }
+  }
+
+  /// 保存されたファイルなどのスキーマがこの型のツリーのスキーマと一致する場合に true を返します。
+  ///
+  pub fn check_schema_compatibility(schema: &Schema) -> bool {
+    *schema == Self::export_schema()
+  }
+}
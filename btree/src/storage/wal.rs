@@ -0,0 +1,267 @@
+use crate::storage::{crc32, replace_file, write_to_file};
+use crate::{BTree, Error, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::marker::PhantomData;
+
+const ENTRY_HEADER_SIZE: usize = 8;
+
+/// WAL に記録される 1 つの更新操作です。
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WalOp<KEY, VALUE> {
+  Put(KEY, VALUE),
+  Delete(KEY),
+}
+
+/// B-Tree に対する更新操作を適用前に記録する先行書き込みログ (write-ahead log) です。
+///
+/// 各エントリはペイロード長 (u32 LE)、ペイロードの CRC32 (u32 LE)、bincode でエンコードされた `WalOp` の
+/// ペイロードからなり、ファイルの末尾に追記されます。書き込み途中でクラッシュしたことによる末尾の不完全な
+/// エントリは `recover` で読み捨てられます。
+///
+#[derive(Debug)]
+pub struct Wal<KEY, VALUE> {
+  file: File,
+  _marker: PhantomData<fn(KEY, VALUE)>,
+}
+
+impl<KEY, VALUE> Wal<KEY, VALUE>
+where
+  KEY: Serialize + DeserializeOwned,
+  VALUE: Serialize + DeserializeOwned,
+{
+  /// 指定されたファイルを追記用の WAL として開きます。ファイルが存在しない場合は新しく作成します。
+  ///
+  pub fn open(path: &str) -> Result<Self> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(Wal {
+      file,
+      _marker: PhantomData,
+    })
+  }
+
+  /// 操作を WAL の末尾に追記し、ディスクへの書き込みが完了するまで待機します。
+  ///
+  pub fn append(&mut self, op: &WalOp<KEY, VALUE>) -> Result<()> {
+    let payload = bincode::serialize(op)?;
+    let mut entry = Vec::with_capacity(ENTRY_HEADER_SIZE + payload.len());
+    entry.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    entry.extend_from_slice(&crc32(&payload).to_le_bytes());
+    entry.extend_from_slice(&payload);
+    self.file.write_all(&entry)?;
+    self.file.sync_data()?;
+    Ok(())
+  }
+
+  /// WAL に記録されているすべてのエントリを破棄します。
+  ///
+  pub fn truncate(&self) -> Result<()> {
+    self.file.set_len(0)?;
+    self.file.sync_data()?;
+    Ok(())
+  }
+
+  /// 指定された WAL に記録されている操作を先頭から順に `tree` に適用し、適用した操作の数を返します。WAL が
+  /// 存在しない場合は何も適用せずに 0 を返します。長さの不足やチェックサムの不一致があるエントリに到達した
+  /// 場合は、それ以降を書き込み途中で中断されたものとみなして適用しません。
+  ///
+  pub fn recover<const S: usize>(path: &str, tree: &mut BTree<KEY, VALUE, S>) -> Result<usize>
+  where
+    KEY: Ord + Clone,
+    VALUE: Clone,
+  {
+    Self::replay(path, tree, false).map(|(count, _)| count)
+  }
+
+  /// WAL の操作を `tree` に適用し、適用した操作の数と完全なエントリが占めるバイト数を返します。`strict` が
+  /// true の場合、不完全なエントリやチェックサムの一致しないエントリに到達すると `Error::WalCorrupted` を返し
+  /// ます。
+  ///
+  fn replay<const S: usize>(
+    path: &str,
+    tree: &mut BTree<KEY, VALUE, S>,
+    strict: bool,
+  ) -> Result<(usize, u64)>
+  where
+    KEY: Ord + Clone,
+    VALUE: Clone,
+  {
+    let mut buffer = Vec::new();
+    match File::open(path) {
+      Ok(mut file) => file.read_to_end(&mut buffer)?,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok((0, 0)),
+      Err(e) => return Err(e.into()),
+    };
+    let mut count = 0;
+    let mut pos = 0;
+    while buffer.len() - pos >= ENTRY_HEADER_SIZE {
+      let len = u32::from_le_bytes(buffer[pos..pos + 4].try_into().unwrap()) as usize;
+      let checksum = u32::from_le_bytes(buffer[pos + 4..pos + 8].try_into().unwrap());
+      let start = pos + ENTRY_HEADER_SIZE;
+      if buffer.len() - start < len || crc32(&buffer[start..start + len]) != checksum {
+        break;
+      }
+      match bincode::deserialize::<WalOp<KEY, VALUE>>(&buffer[start..start + len])? {
+        WalOp::Put(key, value) => {
+          tree.put(key, value);
+        }
+        WalOp::Delete(key) => {
+          tree.delete(&key);
+        }
+      }
+      count += 1;
+      pos = start + len;
+    }
+    if strict && pos < buffer.len() {
+      return Err(Error::WalCorrupted(pos as u64));
+    }
+    Ok((count, pos as u64))
+  }
+}
+
+/// すべての更新操作を WAL に記録してからメモリ上のツリーに適用する B-Tree です。
+///
+#[derive(Debug)]
+pub struct WalBTree<KEY, VALUE, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  tree: BTree<KEY, VALUE, S>,
+  wal: Wal<KEY, VALUE>,
+}
+
+impl<KEY, VALUE, const S: usize> WalBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize + DeserializeOwned,
+  VALUE: Clone + Serialize + DeserializeOwned,
+{
+  /// 指定された WAL に記録されている操作を `tree` に適用し、以後の更新操作をその WAL に記録するツリーを
+  /// 構築します。`tree` には直前のチェックポイントで保存された状態を指定します。WAL の末尾に不完全なエントリ
+  /// がある場合、以後の追記に先立ってそれを切り詰めます。
+  ///
+  pub fn open(wal_path: &str, mut tree: BTree<KEY, VALUE, S>) -> Result<Self> {
+    let (_, valid_len) = Wal::replay(wal_path, &mut tree, false)?;
+    let wal = Wal::open(wal_path)?;
+    if wal.file.metadata()?.len() > valid_len {
+      wal.file.set_len(valid_len)?;
+    }
+    Ok(WalBTree { tree, wal })
+  }
+
+  /// メモリ上のツリーを参照します。
+  ///
+  pub fn tree(&self) -> &BTree<KEY, VALUE, S> {
+    &self.tree
+  }
+
+  pub fn get<Q>(&self, key: &Q) -> Option<VALUE>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.tree.get_cloned(key)
+  }
+
+  /// 操作を WAL に記録してからツリーに値を設定します。
+  ///
+  pub fn put(&mut self, key: KEY, value: VALUE) -> Result<Option<VALUE>> {
+    self.wal.append(&WalOp::Put(key.clone(), value.clone()))?;
+    Ok(self.tree.put(key, value))
+  }
+
+  /// 操作を WAL に記録してからツリーから値を削除します。WAL には所有した形式のキーを記録するため、借用した
+  /// 形式のキーは `ToOwned` で `KEY` に変換します。
+  ///
+  pub fn delete<Q>(&mut self, key: &Q) -> Result<Option<VALUE>>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ToOwned<Owned = KEY> + ?Sized,
+  {
+    self.wal.append(&WalOp::Delete(key.to_owned()))?;
+    Ok(self.tree.delete(key))
+  }
+
+  /// 現在のツリーのすべての Key-Value ペアをキーの昇順に並べた列として指定されたファイルに保存し、WAL を
+  /// 空にします。保存されたファイルは `storage::read_from_file::<Vec<(KEY, VALUE)>>` で読み込むことが
+  /// できます。
+  ///
+  /// チェックポイントは一時ファイルに書き込んでディスクへ同期してから既存のファイルと置き換え、その後で WAL
+  /// を空にします。途中で異常終了した場合でも、以前のチェックポイントと WAL、または新しいチェックポイントの
+  /// いずれかが残ります。
+  ///
+  pub fn checkpoint(&self, path: &str) -> Result<()> {
+    let entries = self.tree.iter().collect::<Vec<_>>();
+    replace_file(path, |temp| write_to_file(&entries, temp).map(|_| ()))?;
+    self.wal.truncate()
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize + DeserializeOwned,
+  VALUE: Clone + Serialize + DeserializeOwned,
+{
+  /// `initial_state` (`None` の場合は空のツリー) に WAL のすべての操作を順に適用したツリーを返します。
+  /// チェックポイントからの完全な復旧に使用します。`Wal::recover` と異なり末尾の不完全なエントリも許容せず、
+  /// いずれかのエントリのチェックサムが一致しない場合はそのエントリの位置を持つ `Error::WalCorrupted` を返し
+  /// ます。
+  ///
+  pub fn rebuild_from_wal(
+    initial_state: Option<BTree<KEY, VALUE, S>>,
+    wal_path: &str,
+  ) -> Result<Self> {
+    let mut tree = initial_state.unwrap_or_default();
+    Wal::replay(wal_path, &mut tree, true)?;
+    Ok(tree)
+  }
+}
+
+/// `BTree::verify_wal_consistency` による検証の結果です。
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsistencyReport {
+  /// WAL を適用した結果には存在するが、現在のツリーに反映されていないエントリの数です。
+  pub missing_ops: usize,
+  /// 現在のツリーには存在するが、WAL を適用した結果には存在しないエントリの数です。
+  pub extra_ops: usize,
+  /// 現在のツリーが WAL を適用した結果と完全に一致する場合に true となります。
+  pub consistent: bool,
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone + Serialize + DeserializeOwned,
+  VALUE: Clone + PartialEq + Serialize + DeserializeOwned,
+{
+  /// 空のツリーに WAL のすべての操作を適用した結果がこのツリーと一致するかを検証します。
+  ///
+  pub fn verify_wal_consistency(&self, wal_path: &str) -> Result<ConsistencyReport> {
+    self.verify_wal_consistency_from(&BTree::new(), wal_path)
+  }
+
+  /// `initial_state` に WAL のすべての操作を適用した結果がこのツリーと一致するかを検証します。WAL とツリーの
+  /// 状態が食い違った原因を調査するために使用します。値だけが異なるエントリは `missing_ops` と `extra_ops` の
+  /// 両方に数えられます。
+  ///
+  pub fn verify_wal_consistency_from(
+    &self,
+    initial_state: &BTree<KEY, VALUE, S>,
+    wal_path: &str,
+  ) -> Result<ConsistencyReport> {
+    let mut expected = initial_state.clone();
+    Wal::recover(wal_path, &mut expected)?;
+    let report = self.validate_against_reference(&expected);
+    let missing_ops = report.missing_keys.len() + report.value_mismatches.len();
+    let extra_ops = report.extra_keys.len() + report.value_mismatches.len();
+    Ok(ConsistencyReport {
+      missing_ops,
+      extra_ops,
+      consistent: report.is_valid(),
+    })
+  }
+}
@@ -1,9 +1,9 @@
 use rand::{RngCore, SeedableRng};
 
 use crate::{BTree, Node};
-use std::cell::Ref;
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::ops::Bound;
 use std::{cell::RefCell, rc::Rc};
 
 #[test]
@@ -56,7 +56,7 @@ fn basic_structure_change() {
 
   // get
   for i in 0..=16 {
-    assert_eq!(Some(i), btree.get(&i));
+    assert_eq!(Some(&i), btree.get(&i));
   }
 
   // delete with merge leftmost key in leaf
@@ -106,7 +106,7 @@ fn sequential_put_delete() {
   }
 
   for i in 0usize..MAX {
-    assert_eq!(Some(i), btree.get(&i));
+    assert_eq!(Some(&i), btree.get(&i));
   }
 
   assert_eq!(MAX, btree.size());
@@ -143,7 +143,7 @@ fn random_put_delete() {
   }
 
   for (key, expected) in expecteds.iter().take(MAX) {
-    assert_eq!(Some(*expected), btree.get(key));
+    assert_eq!(Some(expected), btree.get(key));
   }
 
   assert_eq!(MAX, btree.size());
@@ -204,7 +204,7 @@ fn fixed_random_put_delele() {
 fn dump<KEY, VALUE, const S: usize>(indent: usize, node: Rc<RefCell<Node<KEY, VALUE, S>>>)
 where
   KEY: Ord + Clone + Debug,
-  VALUE: Copy,
+  VALUE: Clone,
 {
   if node.borrow().is_leaf {
     println!(
@@ -232,100 +232,2899 @@ where
 fn validate<KEY, VALUE, const S: usize>(btree: &BTree<KEY, VALUE, S>)
 where
   KEY: Ord + Clone + Debug,
-  VALUE: Copy,
+  VALUE: Clone,
 {
-  if let Err(msg) = _validate(btree.root.borrow(), true, 0) {
+  if let Err(msg) = btree.validate() {
     println!("{}", msg);
     dump(0, btree.root.clone());
     panic!("validation failed: {msg}");
   }
 }
 
-fn _validate<KEY, VALUE, const S: usize>(
-  node: Ref<Node<KEY, VALUE, S>>,
-  root: bool,
-  _depth: usize,
-) -> std::result::Result<usize, String>
-where
-  KEY: Ord + Clone + Debug,
-  VALUE: Copy,
-{
-  let (min, max) = match (root, node.is_leaf) {
-    (true, true) => (0, 2 * S),
-    (true, false) => (1, 2 * S),
-    (false, true) => (S, 2 * S),
-    (false, false) => (S, 2 * S),
+#[test]
+fn threshold_delete() {
+  const MAX: usize = 500;
+  let mut btree = BTree::<_, _, 3>::new();
+  for i in 0usize..MAX {
+    btree.put(i, i % 10);
+  }
+  assert_eq!(MAX / 2, btree.threshold_delete(5));
+  validate(&btree);
+  assert_eq!(MAX / 2, btree.size());
+  for i in 0usize..MAX {
+    let expected = if i % 10 >= 5 { Some(i % 10) } else { None };
+    assert_eq!(expected.as_ref(), btree.get(&i));
+  }
+  assert_eq!(0, btree.threshold_delete(5));
+  assert_eq!(MAX / 2, btree.threshold_delete(10));
+  validate(&btree);
+  assert_eq!(0, btree.size());
+}
+
+#[test]
+fn extend_and_collect() {
+  for n in [0usize, 1, 4, 5, 6, 24, 25, 26, 100, 1000] {
+    let btree = (0..n).rev().map(|i| (i, i * 2)).collect::<BTree<_, _, 2>>();
+    validate(&btree);
+    assert_eq!(n, btree.size());
+    for i in 0..n {
+      assert_eq!(Some(&(i * 2)), btree.get(&i));
+    }
+  }
+
+  // duplicate keys keep the last value
+  let btree = [(1, 1), (2, 2), (1, 3)]
+    .into_iter()
+    .collect::<BTree<_, _, 2>>();
+  assert_eq!(2, btree.size());
+  assert_eq!(Some(&3), btree.get(&1));
+
+  // extends non-empty tree
+  let mut btree = (0..100).map(|i| (i, i)).collect::<BTree<_, _, 3>>();
+  btree.extend((50..150).map(|i| (i, i + 1)));
+  validate(&btree);
+  assert_eq!(150, btree.size());
+  assert_eq!(Some(&49), btree.get(&49));
+  assert_eq!(Some(&51), btree.get(&50));
+}
+
+#[test]
+fn bisect_by_value() {
+  let mut btree = BTree::<_, _, 2>::new();
+  assert_eq!(0, btree.bisect_by_value(0));
+  for i in 0..100u32 {
+    btree.put(i, i / 3);
+  }
+  assert_eq!(0, btree.bisect_by_value(0));
+  assert_eq!(3, btree.bisect_by_value(1));
+  assert_eq!(30, btree.bisect_by_value(10));
+  assert_eq!(99, btree.bisect_by_value(33));
+  assert_eq!(100, btree.bisect_by_value(34));
+
+  assert!(btree.is_monotone());
+
+  // non-monotone values
+  btree.put(10, 100);
+  assert!(!btree.is_monotone());
+  assert_eq!(10, btree.bisect_by_value(50));
+
+  let large = (0..100_000u32)
+    .map(|k| (k, k / 7))
+    .collect::<BTree<_, _, 3>>();
+  assert!(large.is_monotone());
+  for target in [0, 1, 500, 9_999, 14_285, 14_286, 20_000] {
+    let expected = (0..100_000u32).filter(|k| k / 7 < target).count();
+    assert_eq!(expected, large.bisect_by_value(target));
+    assert_eq!(expected, large.bisect_by_monotone_value(&target));
+  }
+  let nan = BTree::<u32, f64, 2>::from_sorted_iter([(0, 1.0), (1, f64::NAN)]);
+  assert!(!nan.is_monotone());
+}
+
+#[test]
+fn iter_and_merge() {
+  let mut btree = BTree::<_, _, 2>::new();
+  assert_eq!(None, btree.iter().next());
+  for i in (0..200u32).rev() {
+    btree.put(i * 2, i);
+  }
+  let entries = btree.iter().collect::<Vec<_>>();
+  assert_eq!((0..200u32).map(|i| (i * 2, i)).collect::<Vec<_>>(), entries);
+
+  let mut other = BTree::<_, _, 2>::new();
+  for i in 0..200u32 {
+    other.put(i * 3, 1000 + i);
+  }
+  let mut merged = (&btree).into_iter().collect::<BTree<_, _, 2>>();
+  merged.merge(BTree::from_iter(other.iter()));
+  validate(&merged);
+  assert_eq!(200 + 200 - 67, merged.size());
+  assert_eq!(Some(&1000), merged.get(&0));
+  assert_eq!(Some(&1), merged.get(&2));
+  assert_eq!(Some(&1001), merged.get(&3));
+  assert_eq!(Some(&1002), merged.get(&6));
+
+  btree.merge_with(other, |key, v1, v2| {
+    assert_eq!(0, key % 6);
+    v1 + v2
+  });
+  validate(&btree);
+  assert_eq!(merged.size(), btree.size());
+  assert_eq!(Some(&1000), btree.get(&0));
+  assert_eq!(Some(&1005), btree.get(&6));
+  assert_eq!(Some(&1001), btree.get(&3));
+  assert_eq!(Some(&1), btree.get(&2));
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet_roundtrip() {
+  let path = std::env::temp_dir().join(format!("btree-{}.parquet", std::process::id()));
+  let count = crate::storage::PAGE_SIZE * 2 + 10;
+  let btree = (0..count as u64)
+    .map(|i| (i, i * 7))
+    .collect::<BTree<_, _, 4>>();
+  btree.write_to_parquet(&path).unwrap();
+  let restored = BTree::<u64, u64, 4>::read_from_parquet(&path).unwrap();
+  std::fs::remove_file(&path).unwrap();
+  validate(&restored);
+  assert_eq!(
+    btree.iter().collect::<Vec<_>>(),
+    restored.iter().collect::<Vec<_>>()
+  );
+}
+
+#[test]
+fn split_off() {
+  fn check<const S: usize>(keys: &[u32], at: u32) {
+    let mut left = keys.iter().map(|k| (*k, *k)).collect::<BTree<_, _, S>>();
+    let right = left.split_off(&at);
+    validate(&left);
+    validate(&right);
+    let mut expected = keys.to_vec();
+    expected.sort();
+    expected.dedup();
+    let (l, r): (Vec<u32>, Vec<u32>) = expected.iter().partition(|k| **k < at);
+    assert_eq!(l, left.iter().map(|(k, _)| k).collect::<Vec<_>>());
+    assert_eq!(r, right.iter().map(|(k, _)| k).collect::<Vec<_>>());
+  }
+
+  let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+  for n in [0usize, 1, 2, 5, 10, 30, 100, 500] {
+    let keys = (0..n as u32).map(|i| i * 2).collect::<Vec<_>>();
+    for at in 0..=(n as u32 * 2 + 1) {
+      check::<1>(&keys, at);
+      check::<2>(&keys, at);
+      check::<3>(&keys, at);
+    }
+    let keys = (0..n).map(|_| rng.next_u32() % 1000).collect::<Vec<_>>();
+    for _ in 0..50 {
+      let at = rng.next_u32() % 1001;
+      check::<1>(&keys, at);
+      check::<2>(&keys, at);
+    }
+  }
+
+  // trees built by put have a different layout than bulk-loaded ones
+  let mut btree = BTree::<_, _, 2>::new();
+  for i in 0..300u32 {
+    btree.put(i, i);
+  }
+  for i in (0..300u32).step_by(7) {
+    btree.delete(&i);
+  }
+  let right = btree.split_off(&150);
+  validate(&btree);
+  validate(&right);
+  assert!(btree.iter().all(|(k, _)| k < 150));
+  assert!(right.iter().all(|(k, _)| k >= 150));
+  assert_eq!(300 - 43, btree.size() + right.size());
+}
+
+#[test]
+fn from_sorted_iter() {
+  for n in [0usize, 1, 2, 3, 4, 7, 8, 9, 48, 49, 50, 1000] {
+    let btree = BTree::<_, _, 1>::from_sorted_iter((0..n).map(|i| (i, i)));
+    validate(&btree);
+    assert_eq!(n, btree.size());
+    let btree = BTree::<_, _, 3>::from_sorted_iter((0..n).map(|i| (i, i)));
+    validate(&btree);
+    assert_eq!(
+      (0..n).map(|i| (i, i)).collect::<Vec<_>>(),
+      btree.iter().collect::<Vec<_>>()
+    );
+  }
+}
+
+#[test]
+#[should_panic(expected = "strictly ascending")]
+fn from_sorted_iter_out_of_order() {
+  BTree::<_, _, 2>::from_sorted_iter([(1, 1), (3, 3), (2, 2)]);
+}
+
+#[test]
+fn reconstruct_from_leaves() {
+  let leaves = (0..10)
+    .map(|i| (i * 3..i * 3 + 3).map(|k| (k, k * 10)).collect::<Vec<_>>())
+    .collect::<Vec<_>>();
+  let btree = BTree::<_, _, 2>::reconstruct_from_leaves(leaves.clone()).unwrap();
+  validate(&btree);
+  assert_eq!(leaves.concat(), btree.iter().collect::<Vec<_>>());
+  let single = BTree::<_, _, 2>::reconstruct_from_leaves(vec![vec![(1, 1)]]).unwrap();
+  assert_eq!(vec![(1, 1)], single.iter().collect::<Vec<_>>());
+  assert_eq!(
+    0,
+    BTree::<i32, i32, 2>::reconstruct_from_leaves(vec![])
+      .unwrap()
+      .size()
+  );
+
+  let invalid = [
+    vec![vec![(1, 1), (2, 2)], vec![]],
+    vec![vec![(1, 1), (2, 2)], vec![(3, 3)]],
+    vec![vec![(1, 1), (5, 5)], vec![(3, 3), (6, 6)]],
+    vec![vec![(2, 2), (1, 1)], vec![(3, 3), (4, 4)]],
+  ];
+  for leaves in invalid {
+    assert!(matches!(
+      BTree::<_, _, 2>::reconstruct_from_leaves(leaves),
+      Err(crate::Error::InvariantViolation(_))
+    ));
+  }
+}
+
+#[test]
+fn batch_put_if_greater() {
+  let mut btree = BTree::<_, _, 2>::new();
+  assert_eq!(3, btree.batch_put_if_greater(&[(1, 10), (2, 20), (3, 30)]));
+  assert_eq!(
+    2,
+    btree.batch_put_if_greater(&[(1, 5), (2, 21), (3, 30), (4, 1)])
+  );
+  assert_eq!(Some(&10), btree.get(&1));
+  assert_eq!(Some(&21), btree.get(&2));
+  assert_eq!(Some(&30), btree.get(&3));
+  assert_eq!(Some(&1), btree.get(&4));
+}
+
+#[test]
+fn iter_while() {
+  let btree = (0..100u32).map(|i| (i, i % 7)).collect::<BTree<_, _, 2>>();
+  let keys = btree
+    .iter_while(|k, _| *k < 42)
+    .map(|(k, _)| *k)
+    .collect::<Vec<_>>();
+  assert_eq!((0..42).collect::<Vec<_>>(), keys);
+
+  // stops at the first false even if the predicate becomes true again
+  let mut iter = btree.iter_while(|_, v| *v != 6);
+  assert_eq!(6, iter.by_ref().count());
+  assert_eq!(None, iter.next());
+  assert_eq!(0, btree.iter_while(|_, _| false).count());
+  assert_eq!(100, btree.iter_while(|_, _| true).count());
+}
+
+#[test]
+fn deep_clone() {
+  let mut btree = (0..100u32).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  let cloned = btree.clone();
+  assert!(!Rc::ptr_eq(&btree.root, &cloned.root));
+  for i in 0..50u32 {
+    btree.delete(&i);
+    btree.put(i + 100, i);
+  }
+  validate(&cloned);
+  assert_eq!(
+    (0..100u32).map(|i| (i, i)).collect::<Vec<_>>(),
+    cloned.iter().collect::<Vec<_>>()
+  );
+}
+
+#[test]
+fn node_pivot_keys() {
+  let mut btree = BTree::<_, _, 2>::new();
+  for i in 0..=16 {
+    btree.put(i, i);
+  }
+  assert_eq!(3, btree.level());
+  assert_eq!(vec![8], btree.node_pivot_keys(0));
+  assert_eq!(vec![2, 5, 11, 14], btree.node_pivot_keys(1));
+  assert!(btree.node_pivot_keys(2).is_empty());
+  assert!(btree.node_pivot_keys(3).is_empty());
+}
+
+#[test]
+fn semantic_equality() {
+  let mut ascending = BTree::<_, _, 2>::new();
+  let mut descending = BTree::<_, _, 2>::new();
+  for i in 0..100u32 {
+    ascending.put(i, i * 2);
+    descending.put(99 - i, (99 - i) * 2);
+  }
+  let bulk_loaded = BTree::<_, _, 2>::from_sorted_iter((0..100u32).map(|i| (i, i * 2)));
+  assert_eq!(ascending, descending);
+  assert_eq!(ascending, bulk_loaded);
+  assert_eq!(BTree::<u32, u32, 2>::new(), BTree::new());
+
+  descending.put(50, 0);
+  assert_ne!(ascending, descending);
+  descending.put(50, 100);
+  assert_eq!(ascending, descending);
+  descending.delete(&99);
+  assert_ne!(ascending, descending);
+  assert_ne!(descending, ascending);
+}
+
+#[test]
+fn adaptive_order_migration() {
+  let btree = (0..1000u32).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  for (bytes, order) in [
+    (0, 1),
+    (16, 1),
+    (64, 4),
+    (80, 5),
+    (200, 12),
+    (400, 24),
+    (4096, 64),
+  ] {
+    let mut migrated = btree.adaptive_order_migration(bytes);
+    assert_eq!(order, migrated.order());
+    assert_eq!(1000, migrated.size());
+    assert!(btree.iter().eq(migrated.iter()));
+    assert_eq!(Some(10), migrated.put(10, 0));
+    assert_eq!(Some(0), migrated.delete(&10));
+    assert_eq!(None, migrated.get(&10));
+  }
+}
+
+#[test]
+fn hash_consistent_with_eq() {
+  use std::collections::hash_map::DefaultHasher;
+  use std::hash::{Hash, Hasher};
+  fn hash<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+  }
+
+  let mut ascending = BTree::<_, _, 2>::new();
+  let mut descending = BTree::<_, _, 2>::new();
+  for i in 0..100u32 {
+    ascending.put(i, i);
+    descending.put(99 - i, 99 - i);
+  }
+  assert_eq!(hash(&ascending), hash(&descending));
+  descending.put(0, 1);
+  assert_ne!(hash(&ascending), hash(&descending));
+  assert_eq!(
+    hash(&BTree::<u32, u32, 2>::new()),
+    hash(&BTree::<u32, u32, 2>::new())
+  );
+  assert_ne!(hash(&BTree::<u32, u32, 2>::new()), hash(&ascending));
+}
+
+#[test]
+fn fingerprint() {
+  use std::hash::{Hash, Hasher};
+
+  // integers and lengths are hashed as fixed-width little-endian regardless of the target
+  let hash = |f: &dyn Fn(&mut crate::Fnv1a)| {
+    let mut hasher = crate::Fnv1a::new();
+    f(&mut hasher);
+    hasher.finish()
   };
-  if node.keys.len() < min || node.keys.len() > max {
-    return Err(format!(
-      "[{}] The number of keys is not in the range {:?} to {:?}: {:?}",
-      _depth,
-      min,
-      max,
-      node.keys.len()
+  assert_eq!(hash(&|h| h.write(&[1, 0, 0, 0])), hash(&|h| 1u32.hash(h)));
+  assert_eq!(
+    hash(&|h| h.write(&[0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff])),
+    hash(&|h| (-2isize).hash(h))
+  );
+  assert_eq!(
+    hash(&|h| {
+      h.write(&[2, 0, 0, 0, 0, 0, 0, 0]);
+      h.write(&[7, 8]);
+    }),
+    hash(&|h| [7u8, 8].as_slice().hash(h))
+  );
+  assert_eq!(
+    0x500d_7b77_065b_fcda,
+    BTree::<u32, u32, 2>::from_sorted_iter([(1, 10), (2, 20)]).fingerprint()
+  );
+
+  let ascending = (0..100u32).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  let mut descending = BTree::<_, _, 2>::new();
+  for i in (0..100u32).rev() {
+    descending.put(i, i);
+  }
+  assert_eq!(ascending.fingerprint(), descending.fingerprint());
+  descending.put(100, 100);
+  assert_ne!(ascending.fingerprint(), descending.fingerprint());
+  descending.delete(&100);
+  assert_eq!(ascending.fingerprint(), descending.fingerprint());
+  assert_ne!(
+    BTree::<u32, u32, 2>::new().fingerprint(),
+    ascending.fingerprint()
+  );
+}
+
+#[test]
+fn display() {
+  let mut btree = BTree::<_, _, 2>::new();
+  assert_eq!("{}", btree.to_string());
+  btree.put(2, 20);
+  btree.put(1, 10);
+  assert_eq!("{1: 10, 2: 20}", btree.to_string());
+  assert_eq!("[1, 2]\n", btree.display_tree());
+
+  for i in 3..=5 {
+    btree.put(i, i * 10);
+  }
+  assert_eq!("L2: [3]\n  [1, 2]\n  [4, 5]\n", btree.display_tree());
+}
+
+#[test]
+fn flat_map() {
+  let btree = (0..100u32).map(|i| (i * 2, i)).collect::<BTree<_, _, 2>>();
+  let flat = btree.as_flat_map();
+  assert_eq!(100, flat.len());
+  assert_eq!(Some(&5), flat.get(&10));
+  assert_eq!(None, flat.get(&11));
+  assert!(flat.contains_key(&198));
+  assert!(!flat.contains_key(&199));
+  assert!(btree.iter().eq(flat.iter().map(|(k, v)| (*k, *v))));
+  assert_eq!(
+    vec![10, 12, 14],
+    flat.range(10..16).map(|(k, _)| *k).collect::<Vec<_>>()
+  );
+  assert_eq!(
+    vec![12, 14, 16],
+    flat
+      .range((Bound::Excluded(10), Bound::Included(16)))
+      .map(|(k, _)| *k)
+      .collect::<Vec<_>>()
+  );
+  assert_eq!(3, flat.range(..5).count());
+  assert_eq!(0, flat.range(200..).count());
+  assert_eq!(btree, flat.into_btree::<2>());
+}
+
+#[test]
+fn convert_with_std_btreemap() {
+  let mut rng = rand::rngs::StdRng::seed_from_u64(18);
+  let mut expected = std::collections::BTreeMap::new();
+  for _ in 0..1000 {
+    expected.insert(rng.next_u32() % 5000, rng.next_u32());
+  }
+  let btree = BTree::<_, _, 3>::from(expected.clone());
+  validate(&btree);
+  assert!(expected.iter().map(|(k, v)| (*k, *v)).eq(btree.iter()));
+  let converted: std::collections::BTreeMap<_, _> = btree.into();
+  assert_eq!(expected, converted);
+}
+
+#[test]
+fn into_concurrent() {
+  let empty = BTree::<u32, u32, 2>::new().into_concurrent(4);
+  assert_eq!(1, empty.shard_count());
+  assert_eq!(None, empty.put(1, 1));
+  assert_eq!(1, empty.size());
+
+  let few = (0..3u32)
+    .map(|i| (i, i))
+    .collect::<BTree<_, _, 2>>()
+    .into_concurrent(8);
+  assert_eq!(3, few.shard_count());
+
+  let sharded = (0..1000u32)
+    .map(|i| (i * 2, i))
+    .collect::<BTree<_, _, 2>>()
+    .into_concurrent(4);
+  assert_eq!(4, sharded.shard_count());
+  assert_eq!(0, sharded.shard_index(&0));
+  assert_eq!(1, sharded.shard_index(&500));
+  assert_eq!(3, sharded.shard_index(&u32::MAX));
+  std::thread::scope(|scope| {
+    for t in 0..4u32 {
+      let sharded = sharded.clone();
+      scope.spawn(move || {
+        for i in (0..1000u32).filter(|i| i % 4 == t) {
+          assert_eq!(Some(i), sharded.get(&(i * 2)));
+          assert_eq!(None, sharded.put(i * 2 + 1, i));
+          assert_eq!(Some(i), sharded.delete(&(i * 2)));
+        }
+      });
+    }
+  });
+  assert_eq!(1000, sharded.size());
+  for i in 0..1000u32 {
+    assert_eq!(None, sharded.get(&(i * 2)));
+    assert_eq!(Some(i), sharded.get(&(i * 2 + 1)));
+  }
+}
+
+#[test]
+fn index() {
+  let btree = (0..100u32).map(|i| (i, i * 3)).collect::<BTree<_, _, 2>>();
+  for i in 0..100u32 {
+    assert_eq!(i * 3, btree[&i]);
+  }
+}
+
+#[test]
+#[should_panic(expected = "key not found in BTree")]
+fn index_not_found() {
+  let btree = (0..100u32).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  let _ = btree[&100];
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn encode_decode_bytes() {
+  for n in [0u32, 1, 100] {
+    let btree = (0..n)
+      .map(|i| (i, i as u64 * 5))
+      .collect::<BTree<_, _, 2>>();
+    let bytes = btree.encode_to_bytes().unwrap();
+    assert_eq!(
+      btree,
+      BTree::<u32, u64, 2>::decode_from_bytes(&bytes).unwrap()
+    );
+  }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_roundtrip() {
+  for n in [0u32, 1, 4, 5, 100, 1000] {
+    let btree = (0..n)
+      .map(|i| (i, i as u64 * 3))
+      .collect::<BTree<_, _, 2>>();
+    let encoded = bincode::serialize(&btree).unwrap();
+    let decoded: BTree<u32, u64, 2> = bincode::deserialize(&encoded).unwrap();
+    validate(&decoded);
+    assert_eq!(btree, decoded);
+  }
+
+  // unknown format version
+  let encoded = bincode::serialize(&(crate::FORMAT_VERSION + 1, Vec::<(u32, u32)>::new())).unwrap();
+  assert!(bincode::deserialize::<BTree<u32, u32, 2>>(&encoded).is_err());
+
+  // out of order entries
+  let encoded = bincode::serialize(&(crate::FORMAT_VERSION, vec![(2u32, 2u32), (1, 1)])).unwrap();
+  assert!(bincode::deserialize::<BTree<u32, u32, 2>>(&encoded).is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn save_and_load_file() {
+  let path = std::env::temp_dir().join(format!("btree-{}.bin", std::process::id()));
+  let path = path.to_str().unwrap();
+  let btree = (0..500u32)
+    .map(|i| (i, i as u64))
+    .collect::<BTree<_, _, 3>>();
+  assert!(btree.save_to_file(path).unwrap() > 0);
+  assert_eq!(btree, BTree::<u32, u64, 3>::load_from_file(path).unwrap());
+  match BTree::<u32, u64, 2>::load_from_file(path) {
+    Err(crate::Error::IncompatibleFormat {
+      expected_s,
+      found_s,
+    }) => {
+      assert_eq!(2, expected_s);
+      assert_eq!(3, found_s);
+    }
+    other => panic!("{other:?}"),
+  }
+  crate::storage::write_to_file(&(*b"XXXX", 1u8, 3u64), path).unwrap();
+  assert!(matches!(
+    BTree::<u32, u64, 3>::load_from_file(path),
+    Err(crate::Error::InvalidHeader(_))
+  ));
+  std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_roundtrip() {
+  for n in [0u32, 1, 100] {
+    let btree = (0..n).map(|i| (i, -(i as i64))).collect::<BTree<_, _, 2>>();
+    let mut buffer = Vec::new();
+    let written = btree.write_to_cbor(&mut buffer).unwrap();
+    assert_eq!(buffer.len(), written);
+    assert_eq!(
+      btree,
+      BTree::<u32, i64, 2>::read_from_cbor(buffer.as_slice()).unwrap()
+    );
+  }
+
+  // キーの昇順に並んでいない配列
+  let mut buffer = Vec::new();
+  ciborium::into_writer(&[(3u32, 30i64), (1, 10), (2, 20)], &mut buffer).unwrap();
+  let btree = BTree::<u32, i64, 2>::read_from_cbor(buffer.as_slice()).unwrap();
+  assert_eq!(
+    vec![(1, 10), (2, 20), (3, 30)],
+    btree.iter().collect::<Vec<_>>()
+  );
+
+  assert!(matches!(
+    BTree::<u32, i64, 2>::read_from_cbor(&[0xffu8][..]),
+    Err(crate::Error::CborDecode(_))
+  ));
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_roundtrip() {
+  for n in [0u32, 1, 100] {
+    let btree = (0..n).map(|i| (i, -(i as i64))).collect::<BTree<_, _, 2>>();
+    let mut buffer = Vec::new();
+    let written = btree.write_msgpack(&mut buffer).unwrap();
+    assert_eq!(buffer.len(), written);
+    assert_eq!(
+      btree,
+      BTree::<u32, i64, 2>::read_msgpack(buffer.as_slice()).unwrap()
+    );
+    assert!(matches!(
+      BTree::<u32, i64, 3>::read_msgpack(buffer.as_slice()),
+      Err(crate::Error::IncompatibleFormat {
+        expected_s: 3,
+        found_s: 2
+      })
+    ));
+  }
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_entry_encoding() {
+  use crate::storage::redis::{decode_entry, encode_entry};
+  let (key, value) = encode_entry("btree", &0x0102u32, &-1i64).unwrap();
+  assert_eq!("btree:02010000", key);
+  assert_eq!(vec![0xFFu8; 8], value);
+  assert_eq!(
+    (0x0102u32, -1i64),
+    decode_entry("btree", &key, &value).unwrap()
+  );
+
+  let (key, value) = encode_entry("users", &"alice".to_string(), &vec![1u8, 2]).unwrap();
+  let decoded: (String, Vec<u8>) = decode_entry("users", &key, &value).unwrap();
+  assert_eq!(("alice".to_string(), vec![1, 2]), decoded);
+
+  // a foreign prefix or a malformed hex key is an error
+  assert!(decode_entry::<u32, i64>("other", "btree:02010000", &value).is_err());
+  assert!(decode_entry::<u32, i64>("btree", "btree02010000", &value).is_err());
+  assert!(decode_entry::<u32, i64>("btree", "btree:0201000", &value).is_err());
+  assert!(decode_entry::<u32, i64>("btree", "btree:zz010000", &value).is_err());
+}
+
+#[cfg(all(feature = "async", feature = "kafka"))]
+#[test]
+fn kafka_records() {
+  use crate::storage::kafka::{encode_messages, to_record};
+  let btree = [(2u32, "b"), (1, "a")]
+    .into_iter()
+    .collect::<BTree<_, _, 2>>();
+  let messages = encode_messages(&btree).unwrap();
+  assert_eq!(
+    vec![
+      (vec![1u8, 0, 0, 0], bincode::serialize("a").unwrap()),
+      (vec![2u8, 0, 0, 0], bincode::serialize("b").unwrap()),
+    ],
+    messages
+  );
+  assert!(encode_messages(&BTree::<u32, u32, 2>::new())
+    .unwrap()
+    .is_empty());
+
+  let (key, value) = &messages[0];
+  let record = to_record("entries", key, value);
+  assert_eq!("entries", record.topic);
+  assert_eq!(Some(key.as_slice()), record.key);
+  assert_eq!(Some(value.as_slice()), record.payload);
+  assert_eq!(None, record.partition);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn crc32() {
+  assert_eq!(0, crate::storage::crc32(b""));
+  assert_eq!(0xCBF4_3926, crate::storage::crc32(b"123456789"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn file_page_store() {
+  use crate::storage::{FilePageStore, PageStore};
+  let path = std::env::temp_dir().join(format!("btree-{}.pages", std::process::id()));
+  let _ = std::fs::remove_file(&path);
+
+  let mut store = FilePageStore::open(&path, 128).unwrap();
+  assert_eq!(
+    128 - crate::storage::PAGE_HEADER_SIZE,
+    store.page_capacity()
+  );
+  let ids = (0..4)
+    .map(|_| store.alloc_page().unwrap())
+    .collect::<Vec<_>>();
+  assert_eq!(vec![1, 2, 3, 4], ids);
+  for id in ids.iter() {
+    assert!(store.read_page(*id).unwrap().is_empty());
+    store.write_page(*id, &[*id as u8; 10]).unwrap();
+  }
+  assert!(store.write_page(1, &[0u8; 200]).is_err());
+  assert!(store.read_page(5).is_err());
+
+  store.free_page(2).unwrap();
+  store.free_page(3).unwrap();
+  assert!(store.read_page(2).is_err());
+  assert_eq!(3, store.alloc_page().unwrap());
+  drop(store);
+
+  // reopen and reuse the remaining free page
+  assert!(FilePageStore::open(&path, 256).is_err());
+  let mut store = FilePageStore::open(&path, 128).unwrap();
+  assert_eq!(5, store.page_count());
+  assert_eq!(vec![4u8; 10], store.read_page(4).unwrap().to_vec());
+  assert_eq!(2, store.alloc_page().unwrap());
+  assert_eq!(5, store.alloc_page().unwrap());
+  drop(store);
+
+  // corrupt a byte of page 4
+  let mut bytes = std::fs::read(&path).unwrap();
+  bytes[4 * 128 + crate::storage::PAGE_HEADER_SIZE] ^= 0xFF;
+  std::fs::write(&path, bytes).unwrap();
+  let mut store = FilePageStore::open(&path, 128).unwrap();
+  assert!(matches!(
+    store.read_page(4),
+    Err(crate::Error::CorruptedPage(4))
+  ));
+  assert_eq!(vec![1u8; 10], store.read_page(1).unwrap().to_vec());
+  drop(store);
+
+  // a page size in the file header outside the supported range is rejected
+  for page_size in [8u32, u32::MAX] {
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[8..12].copy_from_slice(&page_size.to_le_bytes());
+    std::fs::write(&path, bytes).unwrap();
+    assert!(matches!(
+      FilePageStore::open_existing(&path),
+      Err(crate::Error::InvalidHeader(_))
     ));
   }
-  if !node.is_leaf {
-    if node.pivots.len() < min + 1 || node.keys.len() > max + 1 {
-      return Err(format!(
-        "[{}] The number of pivots is not in the range {:?} to {:?}: {:?}",
-        _depth,
-        min + 1,
-        max + 1,
-        node.pivots.len()
-      ));
+  assert!(FilePageStore::open(&path, crate::storage::MAX_PAGE_SIZE + 1).is_err());
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "capnp")]
+#[test]
+fn capnproto_roundtrip() {
+  let btree = (0..100u32)
+    .map(|i| (i, i as u64 * 11))
+    .collect::<BTree<_, _, 2>>();
+  let builder = btree
+    .into_capnproto(capnp::message::Builder::new_default())
+    .unwrap();
+  let mut buffer = Vec::new();
+  capnp::serialize::write_message(&mut buffer, &builder).unwrap();
+  let reader =
+    capnp::serialize::read_message(buffer.as_slice(), capnp::message::ReaderOptions::new())
+      .unwrap();
+  assert_eq!(
+    btree,
+    BTree::<u32, u64, 2>::from_capnproto(&reader).unwrap()
+  );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn write_ahead_log() {
+  use crate::storage::{read_from_file, Wal, WalBTree};
+  let dir = std::env::temp_dir();
+  let wal_path = dir.join(format!("btree-{}.wal", std::process::id()));
+  let snapshot_path = dir.join(format!("btree-{}.snapshot", std::process::id()));
+  let (wal_path, snapshot_path) = (wal_path.to_str().unwrap(), snapshot_path.to_str().unwrap());
+  let _ = std::fs::remove_file(wal_path);
+  assert_eq!(
+    0,
+    Wal::recover(wal_path, &mut BTree::<u32, u64, 2>::new()).unwrap()
+  );
+
+  let mut btree = WalBTree::open(wal_path, BTree::<u32, u64, 2>::new()).unwrap();
+  for i in 0..20u32 {
+    btree.put(i, i as u64).unwrap();
+  }
+  assert_eq!(Some(5), btree.delete(&5).unwrap());
+  let expected = btree.tree().clone();
+  drop(btree);
+
+  // replay the log after a crash
+  let mut recovered = BTree::<u32, u64, 2>::new();
+  assert_eq!(21, Wal::recover(wal_path, &mut recovered).unwrap());
+  assert_eq!(expected, recovered);
+
+  // a torn entry at the tail is ignored
+  let mut bytes = std::fs::read(wal_path).unwrap();
+  bytes.truncate(bytes.len() - 1);
+  std::fs::write(wal_path, bytes).unwrap();
+  let mut recovered = BTree::<u32, u64, 2>::new();
+  assert_eq!(20, Wal::recover(wal_path, &mut recovered).unwrap());
+  assert_eq!(Some(&5), recovered.get(&5));
+
+  // checkpoint saves the tree and empties the log
+  let mut btree = WalBTree::open(wal_path, BTree::<u32, u64, 2>::new()).unwrap();
+  btree.put(100, 100).unwrap();
+  btree.checkpoint(snapshot_path).unwrap();
+  btree.put(200, 200).unwrap();
+  drop(btree);
+  let mut restored = read_from_file::<Vec<(u32, u64)>>(snapshot_path)
+    .unwrap()
+    .into_iter()
+    .collect::<BTree<_, _, 2>>();
+  assert_eq!(21, restored.size());
+  assert_eq!(1, Wal::recover(wal_path, &mut restored).unwrap());
+  assert_eq!(Some(&200), restored.get(&200));
+
+  // a later checkpoint replaces the earlier one without leaving a temporary file behind
+  std::fs::remove_file(wal_path).unwrap();
+  let mut btree = WalBTree::open(wal_path, BTree::<u32, u64, 2>::new()).unwrap();
+  btree.put(300, 300).unwrap();
+  btree.checkpoint(snapshot_path).unwrap();
+  assert_eq!(
+    vec![(300, 300)],
+    read_from_file::<Vec<(u32, u64)>>(snapshot_path).unwrap()
+  );
+  let temp = format!("{snapshot_path}.{}.tmp", std::process::id());
+  assert!(!std::path::Path::new(&temp).exists());
+  drop(btree);
+
+  std::fs::remove_file(wal_path).unwrap();
+  std::fs::remove_file(snapshot_path).unwrap();
+}
+
+#[test]
+fn validate_against_reference() {
+  let mut reference = std::collections::BTreeMap::new();
+  let mut btree = BTree::<u32, u32, 2>::new();
+  let mut r = rand::rngs::StdRng::seed_from_u64(29);
+  for _ in 0..500 {
+    let key = r.next_u32() % 200;
+    if r.next_u32() % 3 == 0 {
+      assert_eq!(reference.remove(&key), btree.delete(&key));
+    } else {
+      assert_eq!(reference.insert(key, key * 2), btree.put(key, key * 2));
+    }
+  }
+  assert!(btree.validate_against_reference(&reference).is_valid());
+  let hash_map = reference
+    .iter()
+    .map(|(k, v)| (*k, *v))
+    .collect::<HashMap<_, _>>();
+  assert!(btree.validate_against_reference(&hash_map).is_valid());
+
+  let mut expected = crate::ValidationReport {
+    extra_keys: vec![1000],
+    missing_keys: Vec::new(),
+    value_mismatches: Vec::new(),
+  };
+  btree.put(1000, 0);
+  let (mismatch, _) = reference.iter().next().map(|(k, v)| (*k, *v)).unwrap();
+  btree.put(mismatch, 1);
+  expected.value_mismatches.push((mismatch, 1, mismatch * 2));
+  let (missing, _) = reference.iter().last().map(|(k, v)| (*k, *v)).unwrap();
+  btree.delete(&missing);
+  expected.missing_keys.push(missing);
+  assert_eq!(expected, btree.validate_against_reference(&hash_map));
+}
+
+#[test]
+fn snapshot_and_restore() {
+  let mut btree = (0..50u32).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  let snapshot = btree.snapshot();
+  for i in 0..25 {
+    btree.delete(&i);
+  }
+  btree.put(100, 100);
+  assert_eq!(50, snapshot.size());
+  assert_eq!(Some(&0), snapshot.get(&0));
+  assert_eq!(None, snapshot.get(&100));
+
+  btree.restore(snapshot);
+  assert_eq!(50, btree.size());
+  assert_eq!(Some(&0), btree.get(&0));
+  assert_eq!(None, btree.get(&100));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn save_and_load_snapshot() {
+  let btree = (0..50u32)
+    .map(|i| (i, i as u64))
+    .collect::<BTree<_, _, 2>>();
+  let mut buffer = Vec::new();
+  btree.save_snapshot(&mut buffer).unwrap();
+  let loaded = BTree::<u32, u64, 2>::load_snapshot(&mut buffer.as_slice()).unwrap();
+  assert_eq!(btree, loaded);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn codec_file_roundtrip() {
+  use crate::storage::{read_from_file_with, write_to_file_with, BincodeCodec, Codec};
+  fn roundtrip<C: Codec>(codec: C, name: &str) {
+    let path = std::env::temp_dir().join(format!("btree-{}.{}", std::process::id(), name));
+    let path = path.to_str().unwrap();
+    let entries = (0..20u32).map(|i| (i, i as u64 * 3)).collect::<Vec<_>>();
+    write_to_file_with(&codec, &entries, path).unwrap();
+    let loaded: Vec<(u32, u64)> = read_from_file_with(&codec, path).unwrap();
+    assert_eq!(entries, loaded);
+    std::fs::remove_file(path).unwrap();
+  }
+  roundtrip(BincodeCodec, "bincode");
+  #[cfg(feature = "json")]
+  roundtrip(crate::storage::JsonCodec, "json");
+  #[cfg(feature = "msgpack")]
+  roundtrip(crate::storage::MessagePackCodec, "msgpack");
+}
+
+#[test]
+fn hot_cold_split() {
+  let btree = (0..100u32).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  for i in 0..100 {
+    for _ in 0..(i % 5) {
+      btree.get(&i);
+    }
+  }
+  let _ = btree[&0];
+  assert_eq!(Some(1), btree.access_count(&0));
+  assert_eq!(Some(4), btree.access_count(&99));
+  assert_eq!(None, btree.access_count(&100));
+
+  let (hot, cold) = btree.hot_cold_split(2);
+  assert_eq!(40, hot.size());
+  assert_eq!(60, cold.size());
+  assert!(hot.iter().all(|(k, _)| k % 5 > 2));
+  assert!(cold.iter().all(|(k, _)| k % 5 <= 2));
+  assert_eq!(Some(4), hot.access_count(&99));
+  hot.get(&99);
+  assert_eq!(Some(5), hot.access_count(&99));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn storage_compaction() {
+  use crate::storage::{
+    compact, needs_compaction, needs_compaction_with_threshold, FilePageStore, PageStore,
+  };
+  let dir = std::env::temp_dir();
+  let input = dir.join(format!("btree-{}.fragmented", std::process::id()));
+  let output = dir.join(format!("btree-{}.compacted", std::process::id()));
+  let (input, output) = (input.to_str().unwrap(), output.to_str().unwrap());
+  let _ = std::fs::remove_file(input);
+
+  let mut store = FilePageStore::open(input, 128).unwrap();
+  let ids = (0..10)
+    .map(|_| store.alloc_page().unwrap())
+    .collect::<Vec<_>>();
+  for id in ids.iter() {
+    store.write_page(*id, &[*id as u8; 8]).unwrap();
+  }
+  drop(store);
+  assert!(!needs_compaction(input).unwrap());
+
+  let mut store = FilePageStore::open(input, 128).unwrap();
+  for id in [2, 4, 6, 8] {
+    store.free_page(id).unwrap();
+  }
+  assert_eq!(4, store.dead_page_count());
+  drop(store);
+  assert!(needs_compaction(input).unwrap());
+  assert!(!needs_compaction_with_threshold(input, 0.5).unwrap());
+
+  let stats = compact(input, output).unwrap();
+  assert_eq!(11 * 128, stats.bytes_before);
+  assert_eq!(7 * 128, stats.bytes_after);
+  assert!((stats.space_reclaimed_percent - 4.0 * 100.0 / 11.0).abs() < 1e-9);
+  assert!(!needs_compaction(output).unwrap());
+
+  let mut store = FilePageStore::open_existing(output).unwrap();
+  assert_eq!(0, store.dead_page_count());
+  let pages = (1..store.page_count())
+    .map(|id| store.read_page(id).unwrap()[0])
+    .collect::<Vec<_>>();
+  assert_eq!(vec![1, 3, 5, 7, 9, 10], pages);
+
+  // compacting a store onto itself keeps its pages
+  let stats = compact(input, input).unwrap();
+  assert_eq!((11 * 128, 7 * 128), (stats.bytes_before, stats.bytes_after));
+  let mut store = FilePageStore::open_existing(input).unwrap();
+  let pages = (1..store.page_count())
+    .map(|id| store.read_page(id).unwrap()[0])
+    .collect::<Vec<_>>();
+  assert_eq!(vec![1, 3, 5, 7, 9, 10], pages);
+
+  std::fs::remove_file(input).unwrap();
+  std::fs::remove_file(output).unwrap();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn verify_wal_consistency() {
+  use crate::storage::{ConsistencyReport, WalBTree};
+  let path = std::env::temp_dir().join(format!("btree-{}.consistency.wal", std::process::id()));
+  let path = path.to_str().unwrap();
+  let _ = std::fs::remove_file(path);
+
+  let initial = (0..10u32).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  let mut btree = WalBTree::open(path, initial.clone()).unwrap();
+  for i in 5..15 {
+    btree.put(i, i * 10).unwrap();
+  }
+  btree.delete(&0).unwrap();
+  let mut current = btree.tree().clone();
+  drop(btree);
+
+  let report = current.verify_wal_consistency_from(&initial, path).unwrap();
+  assert_eq!(
+    ConsistencyReport {
+      missing_ops: 0,
+      extra_ops: 0,
+      consistent: true
+    },
+    report
+  );
+  assert!(!current.verify_wal_consistency(path).unwrap().consistent);
+
+  current.delete(&14);
+  current.put(100, 100);
+  current.put(1, 0);
+  let report = current.verify_wal_consistency_from(&initial, path).unwrap();
+  assert_eq!(
+    ConsistencyReport {
+      missing_ops: 2,
+      extra_ops: 2,
+      consistent: false
+    },
+    report
+  );
+  std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn compute_coverage_bitmap() {
+  let btree = [0i64, 5, 6, 99, 150, -3]
+    .into_iter()
+    .map(|k| (k, ()))
+    .collect::<BTree<_, _, 2>>();
+  assert_eq!(
+    vec![0b10_0000_0001],
+    btree.compute_coverage_bitmap(0, 99, 10)
+  );
+  let bitmap = btree.compute_coverage_bitmap(0, 199, 100);
+  assert_eq!(2, bitmap.len());
+  assert_eq!(1 | 1 << 2 | 1 << 3 | 1 << 49, bitmap[0]);
+  assert_eq!(1 << (75 - 64), bitmap[1]);
+  assert_eq!(vec![1], btree.compute_coverage_bitmap(-3, -3, 1));
+  assert_eq!(vec![0], btree.compute_coverage_bitmap(10, 0, 8));
+  assert!(btree.compute_coverage_bitmap(0, 10, 0).is_empty());
+
+  // the whole i128 domain must not overflow
+  let wide = [i128::MIN, -1, 0, i128::MAX]
+    .into_iter()
+    .map(|k| (k, ()))
+    .collect::<BTree<_, _, 2>>();
+  assert_eq!(
+    vec![0b1111],
+    wide.compute_coverage_bitmap(i128::MIN, i128::MAX, 4)
+  );
+  assert_eq!(
+    vec![0b11],
+    wide.compute_coverage_bitmap(i128::MIN, i128::MAX, 2)
+  );
+  assert_eq!(
+    vec![1 | 1 << 31 | 1 << 32 | 1 << 63],
+    wide.compute_coverage_bitmap(i128::MIN, i128::MAX, 64)
+  );
+  let near_max = [i128::MAX - 10, i128::MAX]
+    .into_iter()
+    .map(|k| (k, ()))
+    .collect::<BTree<_, _, 2>>();
+  assert_eq!(
+    vec![0, 1],
+    near_max.compute_coverage_bitmap(0, i128::MAX, 65)
+  );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn rebuild_from_wal() {
+  use crate::storage::WalBTree;
+  use crate::Error;
+  let path = std::env::temp_dir().join(format!("btree-{}.rebuild.wal", std::process::id()));
+  let path = path.to_str().unwrap();
+  let _ = std::fs::remove_file(path);
+
+  let initial = (0..10u32).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  let mut btree = WalBTree::open(path, BTree::<u32, u32, 2>::new()).unwrap();
+  for i in 5..15 {
+    btree.put(i, i * 10).unwrap();
+  }
+  btree.delete(&7).unwrap();
+  drop(btree);
+
+  let rebuilt = BTree::<u32, u32, 2>::rebuild_from_wal(None, path).unwrap();
+  assert_eq!(9, rebuilt.size());
+  let rebuilt = BTree::rebuild_from_wal(Some(initial), path).unwrap();
+  assert_eq!(14, rebuilt.size());
+  assert_eq!(Some(&4), rebuilt.get(&4));
+  assert_eq!(Some(&50), rebuilt.get(&5));
+  assert_eq!(None, rebuilt.get(&7));
+
+  let mut bytes = std::fs::read(path).unwrap();
+  let last = bytes.len() - 1;
+  bytes[last] ^= 0xFF;
+  std::fs::write(path, bytes).unwrap();
+  assert!(matches!(
+    BTree::<u32, u32, 2>::rebuild_from_wal(None, path),
+    Err(Error::WalCorrupted(_))
+  ));
+  std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn with_capacity_and_shrink_to_fit() {
+  fn capacities<const S: usize>(node: &Node<u32, u32, S>, out: &mut Vec<(usize, usize)>) {
+    out.push((node.keys.len(), node.keys.capacity()));
+    for pivot in node.pivots.iter() {
+      capacities(&pivot.borrow(), out);
+    }
+  }
+
+  let mut btree = BTree::<u32, u32, 4>::with_capacity(1000);
+  assert_eq!(9, btree.root.borrow().keys.capacity());
+  for i in 0..1000 {
+    btree.put(i, i);
+  }
+  assert_eq!(1000, btree.size());
+  let mut nodes = Vec::new();
+  capacities(&btree.root.borrow(), &mut nodes);
+  assert!(nodes.iter().all(|(_, capacity)| *capacity >= 9));
+
+  for i in (0..1000).filter(|i| i % 3 != 0) {
+    btree.delete(&i);
+  }
+  btree.shrink_to_fit();
+  let mut nodes = Vec::new();
+  capacities(&btree.root.borrow(), &mut nodes);
+  assert!(nodes.iter().all(|(len, capacity)| len == capacity));
+  assert_eq!(334, btree.size());
+  assert_eq!(Some(&999), btree.get(&999));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn export_schema() {
+  use crate::storage::{TypeDescriptor, SCHEMA_VERSION};
+  let schema = BTree::<u32, u64, 3>::export_schema();
+  assert_eq!("u32", schema.key_type.name);
+  assert_eq!(4, schema.key_type.size);
+  assert_eq!(TypeDescriptor::of::<u64>(), schema.value_type);
+  assert_eq!(3, schema.order);
+  assert_eq!(SCHEMA_VERSION, schema.version);
+
+  assert!(BTree::<u32, u64, 3>::check_schema_compatibility(&schema));
+  assert!(!BTree::<u32, u64, 4>::check_schema_compatibility(&schema));
+  assert!(!BTree::<u32, i64, 3>::check_schema_compatibility(&schema));
+  let decoded = bincode::deserialize(&bincode::serialize(&schema).unwrap()).unwrap();
+  assert!(BTree::<u32, u64, 3>::check_schema_compatibility(&decoded));
+}
+
+#[test]
+fn shared_btree() {
+  use crate::concurrent::SharedBTree;
+  let shared = SharedBTree::new(BTree::<u32, u32, 2>::new());
+  std::thread::scope(|scope| {
+    for t in 0..4u32 {
+      let shared = shared.clone();
+      scope.spawn(move || {
+        for i in (0..1000u32).filter(|i| i % 4 == t) {
+          assert_eq!(None, shared.put(i, i));
+          assert_eq!(Some(i), shared.get(&i));
+          if i % 2 == 0 {
+            assert_eq!(Some(i), shared.delete(&i));
+          }
+        }
+      });
+    }
+  });
+  assert_eq!(500, shared.size());
+  let snapshot = shared.snapshot_iter();
+  assert_eq!(
+    (1..1000).step_by(2).map(|i| (i, i)).collect::<Vec<_>>(),
+    snapshot
+  );
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn async_btree() {
+  use crate::async_::AsyncBTree;
+  let tree = AsyncBTree::new(BTree::<u32, u32, 2>::new());
+  let mut tasks = Vec::new();
+  for t in 0..4u32 {
+    let tree = tree.clone();
+    tasks.push(tokio::spawn(async move {
+      for i in (0..1000u32).filter(|i| i % 4 == t) {
+        assert_eq!(None, tree.put(i, i).await);
+        assert_eq!(Some(i), tree.get(&i).await);
+        if i % 2 == 0 {
+          assert_eq!(Some(i), tree.delete(&i).await);
+        }
+      }
+    }));
+  }
+  for task in tasks {
+    task.await.unwrap();
+  }
+  assert_eq!(500, tree.size().await);
+
+  // a slow consumer must not block writers while the scan is being delivered
+  let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+  let scanner = {
+    let tree = tree.clone();
+    tokio::spawn(async move { tree.scan(100..200, tx).await })
+  };
+  let first = rx.recv().await.unwrap();
+  assert_eq!((101, 101), first);
+  assert_eq!(None, tree.put(150, 0).await);
+  let mut received = vec![first];
+  while let Some(entry) = rx.recv().await {
+    received.push(entry);
+  }
+  assert_eq!(50, scanner.await.unwrap());
+  assert_eq!(
+    (101..200).step_by(2).map(|i| (i, i)).collect::<Vec<_>>(),
+    received
+  );
+}
+
+#[test]
+fn profile_operations() {
+  use crate::Op;
+  let mut btree = BTree::<u32, u32, 2>::new();
+  let puts = (0..100).map(|i| Op::Put(i, i)).collect::<Vec<_>>();
+  let profile = btree.profile_operations(&puts);
+  assert_eq!(100, btree.size());
+  assert!(profile.node_splits > 0);
+  assert_eq!(0, profile.node_merges);
+  assert_eq!(btree.level(), profile.levels_reached);
+  assert!(profile.average_put_time.is_some());
+  assert_eq!(None, profile.average_get_time);
+  assert_eq!(None, profile.average_delete_time);
+
+  let mixed = (0..100)
+    .flat_map(|i| [Op::Get(i), Op::Delete(i)])
+    .collect::<Vec<_>>();
+  let level = btree.level();
+  let profile = btree.profile_operations(&mixed);
+  assert_eq!(0, btree.size());
+  assert_eq!(0, profile.node_splits);
+  assert!(profile.node_merges > 0);
+  assert_eq!(level, profile.max_traversal_depth);
+  assert_eq!(1, profile.levels_reached);
+  assert!(profile.total_time >= profile.average_get_time.unwrap() * 100);
+}
+
+#[cfg(feature = "audit")]
+#[test]
+fn audit_log() {
+  use crate::Op;
+  let mut btree = BTree::<u32, u32, 2>::new();
+  btree.put(1, 10);
+  btree.put(2, 20);
+  btree.put(1, 11);
+  btree.delete(&2);
+  btree.delete(&3);
+  let log = btree.audit_log();
+  assert_eq!(4, log.len());
+  assert_eq!(Op::Put(1, 11), log[2].op);
+  assert_eq!(Some(10), log[2].old_value);
+  assert_eq!(Op::Delete(2), log[3].op);
+  assert_eq!(Some(20), log[3].old_value);
+  assert!(log.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+
+  let mut target = BTree::<u32, u32, 2>::new();
+  assert_eq!(4, btree.replay_audit_to(&mut target));
+  assert_eq!(btree, target);
+
+  btree.clear_audit_log();
+  assert!(btree.audit_log().is_empty());
+}
+
+#[test]
+fn validate_errors() {
+  use crate::ValidationError;
+  let btree = (0..100u32).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  assert_eq!(Ok(()), btree.validate());
+  assert_eq!(Ok(()), BTree::<u32, u32, 2>::new().validate());
+
+  let broken = btree.clone();
+  broken.root.borrow().pivots[0].borrow_mut().keys.pop();
+  broken.root.borrow().pivots[0].borrow_mut().keys.pop();
+  broken.root.borrow().pivots[0].borrow_mut().keys.pop();
+  assert!(matches!(
+    broken.validate(),
+    Err(ValidationError::FillFactorViolation { depth: 1, .. })
+  ));
+
+  let broken = btree.clone();
+  broken.root.borrow_mut().keys.swap(0, 1);
+  assert_eq!(
+    Err(ValidationError::KeyOutOfOrder { depth: 0, index: 1 }),
+    broken.validate()
+  );
+
+  let broken = btree.clone();
+  let pivot = broken.root.borrow_mut().pivots.pop().unwrap();
+  assert!(matches!(
+    broken.validate(),
+    Err(ValidationError::PivotCountMismatch { depth: 0, .. })
+  ));
+  drop(pivot);
+
+  let broken = btree.clone();
+  let child = broken.root.borrow().pivots[0].clone();
+  let leaf = child.borrow().pivots[0].clone();
+  leaf
+    .borrow_mut()
+    .pivots
+    .push(Rc::new(RefCell::new(Node::new(true))));
+  assert_eq!(
+    Err(ValidationError::LeafHasPivots { depth: 2 }),
+    broken.validate()
+  );
+
+  let broken = btree.clone();
+  broken.root.borrow().pivots[1].borrow_mut().subtree_size += 1;
+  assert!(matches!(
+    broken.validate(),
+    Err(ValidationError::SubtreeSizeMismatch { depth: 1, .. })
+  ));
+}
+
+#[test]
+fn to_dot() {
+  let btree = (1..=5).map(|i| (i, i * 10)).collect::<BTree<_, _, 2>>();
+  assert_eq!("L2: [3]\n  [1, 2]\n  [4, 5]\n", btree.display_tree());
+  assert_eq!(
+    concat!(
+      "digraph BTree {\n",
+      "  node [fontname=\"monospace\"];\n",
+      "  n0 [shape=record, label=\"<p0>|3|<p1>\"];\n",
+      "  n1 [shape=Mrecord, label=\"1: 10|2: 20\"];\n",
+      "  n0:p0 -> n1 [label=\"< 3\"];\n",
+      "  n2 [shape=Mrecord, label=\"4: 40|5: 50\"];\n",
+      "  n0:p1 -> n2 [label=\"> 3\"];\n",
+      "}\n",
+    ),
+    btree.to_dot()
+  );
+
+  // characters with a meaning in record labels are escaped
+  let quoted = [("a\"b|{c}".to_string(), 1)]
+    .into_iter()
+    .collect::<BTree<_, _, 2>>();
+  assert!(quoted.to_dot().contains(r#"label="a\"b\|\{c\}: 1""#));
+}
+
+#[test]
+fn compare_exchange_value() {
+  let mut btree = (0..100u32).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  assert_eq!(Ok(50), btree.compare_exchange_value(&50, 50, 500));
+  assert_eq!(Some(&500), btree.get(&50));
+  assert_eq!(Err(500), btree.compare_exchange_value(&50, 50, 5000));
+  assert_eq!(Some(&500), btree.get(&50));
+  assert_eq!(100, btree.size());
+}
+
+#[test]
+#[should_panic(expected = "key not found in BTree")]
+fn compare_exchange_value_missing_key() {
+  let mut btree = (0..100u32).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  let _ = btree.compare_exchange_value(&100, 100, 0);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn write_to_ndjson() {
+  let btree = [(2u32, -1i32), (1, 10)]
+    .into_iter()
+    .collect::<BTree<_, _, 2>>();
+  let mut buffer = Vec::new();
+  let written = btree.write_to_ndjson(&mut buffer).unwrap();
+  assert_eq!(buffer.len(), written);
+  assert_eq!(
+    "{\"k\":1,\"v\":10}\n{\"k\":2,\"v\":-1}\n",
+    String::from_utf8(buffer).unwrap()
+  );
+  assert_eq!(
+    0,
+    BTree::<u32, u32, 2>::new()
+      .write_to_ndjson(std::io::sink())
+      .unwrap()
+  );
+}
+
+#[test]
+fn tree_stats() {
+  let stats = BTree::<u32, u32, 2>::new().stats();
+  assert_eq!(
+    (0, 1, 0, 1),
+    (
+      stats.size,
+      stats.height,
+      stats.internal_node_count,
+      stats.leaf_count
+    )
+  );
+  assert_eq!((0, 0), (stats.min_keys_per_leaf, stats.max_keys_per_leaf));
+  assert_eq!(0.0, stats.avg_fill_factor);
+
+  let btree = (1..=5).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  let stats = btree.stats();
+  assert_eq!(5, stats.size);
+  assert_eq!(2, stats.height);
+  assert_eq!(1, stats.internal_node_count);
+  assert_eq!(2, stats.leaf_count);
+  assert_eq!(3, stats.total_node_count);
+  assert_eq!((2, 2), (stats.min_keys_per_leaf, stats.max_keys_per_leaf));
+  assert_eq!(5.0 / 12.0, stats.avg_fill_factor);
+
+  let btree = (0..1000).map(|i| (i, i)).collect::<BTree<_, _, 3>>();
+  let stats = btree.stats();
+  assert_eq!(btree.size(), stats.size);
+  assert_eq!(btree.level(), stats.height);
+  assert!(stats.min_keys_per_leaf >= 3 && stats.max_keys_per_leaf <= 6);
+}
+
+#[test]
+fn build_prefix_tree() {
+  let words = [
+    "apple",
+    "app",
+    "application",
+    "banana",
+    "band",
+    "bandana",
+    "",
+    "日本",
+    "日本語",
+  ];
+  let btree = words
+    .iter()
+    .enumerate()
+    .map(|(i, w)| (w.to_string(), i))
+    .collect::<BTree<_, _, 2>>();
+  let trie = btree.build_prefix_tree();
+  assert_eq!(words.len(), trie.len());
+  assert_eq!(Some(&1), trie.get("app"));
+  assert_eq!(None, trie.get("ap"));
+
+  let collect = |prefix: &str| {
+    trie
+      .prefix_iter(prefix)
+      .map(|(k, _)| k.to_string())
+      .collect::<Vec<_>>()
+  };
+  assert_eq!(vec!["app", "apple", "application"], collect("app"));
+  assert_eq!(vec!["band", "bandana"], collect("band"));
+  assert_eq!(vec!["日本", "日本語"], collect("日"));
+  assert!(collect("c").is_empty());
+  let expected = btree.iter().map(|(k, _)| k).collect::<Vec<_>>();
+  assert_eq!(expected, collect(""));
+}
+
+#[test]
+fn measure_iter_overhead() {
+  let empty = BTree::<u32, u32, 2>::new().measure_iter_overhead();
+  assert_eq!(std::time::Duration::ZERO, empty.per_entry_time);
+
+  let btree = (0..10_000u32).map(|k| (k, k)).collect::<BTree<_, _, 2>>();
+  let overhead = btree.measure_iter_overhead();
+  assert!(overhead.traversal_time > std::time::Duration::ZERO);
+  assert!(overhead.per_entry_time <= overhead.traversal_time);
+  assert_eq!(overhead.traversal_time / 10_000, overhead.per_entry_time);
+}
+
+#[test]
+fn lookup_trace() {
+  let btree = (1..=5).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  let trace = btree.lookup_trace(&3);
+  assert_eq!(
+    crate::LookupTrace {
+      depth: 0,
+      nodes_visited: 1,
+      found: true,
+      path: vec![(0, 1)],
+    },
+    trace
+  );
+  let trace = btree.lookup_trace(&5);
+  assert!(trace.found);
+  assert_eq!(vec![(1, 1), (1, 2)], trace.path);
+  let trace = btree.lookup_trace(&6);
+  assert!(!trace.found);
+  assert_eq!((1, 2), (trace.depth, trace.nodes_visited));
+  assert_eq!(vec![(1, 1), (2, 2)], trace.path);
+  assert_eq!(Some(0), btree.access_count(&5));
+
+  let btree = (0..1000).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  for key in [0, 500, 999, 1000] {
+    let trace = btree.lookup_trace(&key);
+    assert!(trace.nodes_visited <= btree.level());
+    assert_eq!(key < 1000, trace.found);
+  }
+}
+
+#[test]
+fn iter_key_ranges() {
+  let empty = BTree::<u32, u32, 2>::new();
+  assert_eq!(
+    vec![(None, None)],
+    empty.iter_key_ranges().collect::<Vec<_>>()
+  );
+
+  let btree = (1..=5).map(|i| (i, i)).collect::<BTree<_, _, 2>>();
+  assert_eq!(
+    vec![(None, Some(&2)), (Some(&4), None)],
+    btree.iter_key_ranges().collect::<Vec<_>>()
+  );
+
+  let btree = (0..1000).map(|i| (i, i)).collect::<BTree<_, _, 3>>();
+  let ranges = btree.iter_key_ranges().collect::<Vec<_>>();
+  assert_eq!(btree.stats().leaf_count, ranges.len());
+  assert!(ranges.windows(2).all(|w| w[0].1 < w[1].0));
+  assert!(ranges[1..ranges.len() - 1]
+    .iter()
+    .all(|(min, max)| min <= max));
+}
+
+#[test]
+fn btree_set() {
+  use crate::BTreeSet;
+  let mut set = BTreeSet::<u32, 2>::new();
+  assert!(set.is_empty());
+  for i in (0..100).rev() {
+    assert!(set.insert(i * 2));
+  }
+  assert!(!set.insert(10));
+  assert_eq!(100, set.len());
+  assert!(set.contains(&10));
+  assert!(!set.contains(&11));
+  assert!(set.remove(&10));
+  assert!(!set.remove(&10));
+  assert_eq!(99, set.len());
+  let expected = (0..100)
+    .map(|i| i * 2)
+    .filter(|i| *i != 10)
+    .collect::<Vec<_>>();
+  assert_eq!(expected, set.iter().copied().collect::<Vec<_>>());
+
+  assert_eq!(
+    vec![8, 12, 14],
+    set.range(7..16).copied().collect::<Vec<_>>()
+  );
+  assert_eq!(vec![8, 12], set.range(8..=12).copied().collect::<Vec<_>>());
+  assert_eq!(
+    vec![196, 198],
+    set.range(195..).copied().collect::<Vec<_>>()
+  );
+  assert_eq!(
+    vec![0, 2],
+    set
+      .range((Bound::Unbounded, Bound::Excluded(4)))
+      .copied()
+      .collect::<Vec<_>>()
+  );
+  assert_eq!(0, set.range(199..).count());
+  for start in 0..200 {
+    let expected = expected.iter().filter(|k| **k >= start).collect::<Vec<_>>();
+    assert_eq!(expected, set.range(start..).collect::<Vec<_>>());
+  }
+
+  let a = (0..10).collect::<BTreeSet<u32, 2>>();
+  let b = (5..15).collect::<BTreeSet<u32, 2>>();
+  assert_eq!(
+    (0..15).collect::<Vec<_>>(),
+    a.union(&b).iter().copied().collect::<Vec<_>>()
+  );
+  assert_eq!(
+    (5..10).collect::<Vec<_>>(),
+    a.intersection(&b).iter().copied().collect::<Vec<_>>()
+  );
+  assert_eq!(
+    (0..5).collect::<Vec<_>>(),
+    a.difference(&b).iter().copied().collect::<Vec<_>>()
+  );
+  assert!(a.difference(&a).is_empty());
+}
+
+#[test]
+fn bplus_tree() {
+  use crate::BPlusTree;
+  let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+  let mut tree = BPlusTree::<u32, u32, 2>::new();
+  let mut expected = std::collections::BTreeMap::new();
+  for _ in 0..5000 {
+    let key = rng.next_u32() % 500;
+    if rng.next_u32() % 3 == 0 {
+      assert_eq!(expected.remove(&key), tree.delete(&key));
+    } else {
+      assert_eq!(expected.insert(key, key * 2), tree.put(key, key * 2));
     }
-    let mut depths = Vec::with_capacity(node.pivots.len());
-    for child in node.pivots.iter() {
-      let depth = _validate(child.borrow(), false, _depth + 1)?;
-      depths.push(depth);
+    assert_eq!(expected.len(), tree.len());
+  }
+  assert!(tree.level() > 2);
+  for key in 0..500 {
+    assert_eq!(expected.get(&key).copied(), tree.get(&key));
+  }
+  let entries = expected.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+  assert_eq!(entries, tree.iter().collect::<Vec<_>>());
+  let reversed = entries.iter().rev().copied().collect::<Vec<_>>();
+  assert_eq!(reversed, tree.iter().rev().collect::<Vec<_>>());
+  let mut iter = tree.iter();
+  assert_eq!(entries.first().copied(), iter.next());
+  assert_eq!(entries.last().copied(), iter.next_back());
+  assert_eq!(entries.len() - 2, iter.count());
+
+  for (start, end) in [(0, 500), (100, 200), (250, 251), (499, 1000), (300, 300)] {
+    let expected = expected
+      .range(start..end)
+      .map(|(k, v)| (*k, *v))
+      .collect::<Vec<_>>();
+    assert_eq!(expected, tree.range(start..end).collect::<Vec<_>>());
+  }
+  assert_eq!(
+    expected.range(..=50).count(),
+    tree.range((Bound::Unbounded, Bound::Included(50))).count()
+  );
+
+  for key in 0..500 {
+    tree.delete(&key);
+  }
+  assert!(tree.is_empty());
+  assert_eq!(1, tree.level());
+  assert_eq!(0, tree.iter().count());
+}
+
+#[test]
+fn mirror() {
+  let btree = (-20i32..30)
+    .map(|k| (k, k * 10))
+    .collect::<BTree<_, _, 2>>();
+  let mirror = btree.mirror();
+  validate(&mirror);
+  assert_eq!(
+    (-29..=20).map(|k| (k, -k * 10)).collect::<Vec<_>>(),
+    mirror.iter().collect::<Vec<_>>()
+  );
+
+  let btree = ["a", "b", "c"]
+    .iter()
+    .map(|k| (k.to_string(), k.len()))
+    .collect::<BTree<_, _, 2>>();
+  let mirror = btree.mirror_reverse();
+  assert_eq!(
+    vec!["c", "b", "a"],
+    mirror.iter().map(|(k, _)| k.0).collect::<Vec<_>>()
+  );
+  assert_eq!(Some(&1), mirror.get(&std::cmp::Reverse("b".to_string())));
+}
+
+#[test]
+fn throttled_iter() {
+  let btree = (0..5u32).map(|k| (k, k)).collect::<BTree<_, _, 2>>();
+  let start = std::time::Instant::now();
+  assert_eq!(
+    btree.iter().collect::<Vec<_>>(),
+    btree
+      .throttled_iter(100.0)
+      .map(|(k, v)| (*k, *v))
+      .collect::<Vec<_>>()
+  );
+  assert!(start.elapsed() >= std::time::Duration::from_millis(40));
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn throttled_iter_async() {
+  let btree = (0..5u32).map(|k| (k, k)).collect::<BTree<_, _, 2>>();
+  let start = std::time::Instant::now();
+  let mut iter = btree.throttled_iter(100.0);
+  let mut keys = Vec::new();
+  while let Some((key, _)) = iter.next_async().await {
+    keys.push(*key);
+  }
+  assert_eq!(vec![0, 1, 2, 3, 4], keys);
+  assert!(start.elapsed() >= std::time::Duration::from_millis(40));
+}
+
+#[test]
+fn btree_range() {
+  let btree = (0..200u32).map(|k| (k * 2, k)).collect::<BTree<_, _, 2>>();
+  for start in 0..402 {
+    let expected = btree
+      .iter()
+      .filter(|(k, _)| (start..start + 20).contains(k))
+      .collect::<Vec<_>>();
+    assert_eq!(expected, btree.range(start..start + 20).collect::<Vec<_>>());
+  }
+  assert_eq!(
+    vec![(2, 1), (4, 2)],
+    btree
+      .range((Bound::Excluded(0), Bound::Included(4)))
+      .collect::<Vec<_>>()
+  );
+  assert_eq!(200, btree.range(..).count());
+}
+
+#[test]
+fn btree_multimap() {
+  use crate::BTreeMultiMap;
+  let mut map = BTreeMultiMap::<String, u32, 2>::new();
+  for (i, word) in ["a", "b", "a", "c", "a", "b"].iter().enumerate() {
+    map.insert(word.to_string(), i as u32);
+  }
+  map.insert("a".to_string(), 0);
+  assert_eq!(7, map.len());
+  assert_eq!(&[0, 2, 4, 0], map.get_all("a"));
+  assert_eq!(&[1, 5], map.get_all("b"));
+  assert!(map.get_all("d").is_empty());
+  assert!(!map.contains_key("d"));
+
+  assert!(map.delete_one("a", &0));
+  assert_eq!(&[2, 4, 0], map.get_all("a"));
+  assert!(!map.delete_one("a", &9));
+  assert!(!map.delete_one("d", &0));
+  assert!(map.delete_one("c", &3));
+  assert!(!map.contains_key("c"));
+  assert_eq!(2, map.delete_all("b"));
+  assert_eq!(0, map.delete_all("b"));
+  assert!(!map.contains_key("b"));
+  assert_eq!(3, map.len());
+  map.insert("c".to_string(), 3);
+  assert_eq!(
+    vec![
+      ("a".to_string(), 2),
+      ("a".to_string(), 4),
+      ("a".to_string(), 0),
+      ("c".to_string(), 3)
+    ],
+    map.iter().collect::<Vec<_>>()
+  );
+}
+
+#[test]
+fn ttl_eviction() {
+  use std::time::Duration;
+  let btree = (0..10u32).map(|k| (k, k)).collect::<BTree<_, _, 2>>();
+  let mut cache = btree.with_ttl_eviction(Duration::from_millis(50));
+  assert_eq!(Some(3), cache.get(&3));
+  std::thread::sleep(Duration::from_millis(60));
+  assert_eq!(None, cache.put(5, 50));
+  cache.put(20, 200);
+  assert_eq!(None, cache.get(&3));
+  assert_eq!(9 + 1, cache.len());
+  assert_eq!(vec![(5, 50), (20, 200)], cache.iter().collect::<Vec<_>>());
+  assert_eq!(8, cache.evict_expired());
+  assert_eq!(2, cache.len());
+  assert_eq!(Some(50), cache.get(&5));
+  assert_eq!(Some(200), cache.delete(&20));
+  assert_eq!(vec![(5, 50)], cache.into_btree().iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn ttl_expiry() {
+  use std::time::{Duration, Instant};
+  let mut cache = BTree::<u32, u32, 2>::new().with_ttl_eviction(Duration::from_secs(3600));
+  assert_eq!(None, cache.next_expiry());
+  let now = Instant::now();
+  assert_eq!(None, cache.put_with_expiry(1, 10, now));
+  assert_eq!(
+    None,
+    cache.put_with_expiry(2, 20, now + Duration::from_secs(60))
+  );
+  assert_eq!(None, cache.put_without_expiry(3, 30));
+  assert_eq!(None, cache.put(4, 40));
+  assert_eq!(Some(now), cache.next_expiry());
+
+  assert_eq!(None, cache.get(&1));
+  assert_eq!(Some(20), cache.get(&2));
+  assert_eq!(Some(30), cache.get(&3));
+  assert_eq!(3, cache.len());
+  assert_eq!(Some(now + Duration::from_secs(60)), cache.next_expiry());
+
+  assert_eq!(Some(20), cache.put_with_expiry(2, 21, now));
+  assert_eq!(Some(30), cache.put_with_expiry(3, 31, now));
+  assert_eq!(2, cache.purge_expired());
+  assert_eq!(vec![(4, 40)], cache.iter().collect::<Vec<_>>());
+  assert!(cache.next_expiry().unwrap() > now + Duration::from_secs(3000));
+}
+
+#[test]
+fn clone_values() {
+  let mut btree = BTree::<u32, String, 2>::new();
+  for i in 0..50 {
+    assert_eq!(None, btree.put(i, i.to_string()));
+  }
+  assert_eq!(Some("7".to_string()), btree.put(7, "seven".to_string()));
+  assert_eq!(Some(&"seven".to_string()), btree.get(&7));
+  assert_eq!(Some("8".to_string()), btree.get_cloned(&8));
+  assert_eq!(None, btree.get(&50));
+  assert_eq!(Some("9".to_string()), btree.delete(&9));
+  validate(&btree);
+  assert_eq!(49, btree.iter().count());
+}
+
+#[test]
+fn gini_coefficient() {
+  assert_eq!(0.0, BTree::<u32, f64, 2>::new().compute_gini_coefficient());
+  let equal = (0..10u32).map(|k| (k, 5u32)).collect::<BTree<_, _, 2>>();
+  assert!(equal.compute_gini_coefficient().abs() < 1e-12);
+  let skewed = (0..10u32)
+    .map(|k| (k, if k == 3 { 100u32 } else { 0 }))
+    .collect::<BTree<_, _, 2>>();
+  assert!((skewed.compute_gini_coefficient() - 0.9).abs() < 1e-12);
+  let linear = (1..=4u32).map(|k| (k, k)).collect::<BTree<_, _, 2>>();
+  assert!((linear.compute_gini_coefficient() - 0.25).abs() < 1e-12);
+}
+
+#[test]
+fn borrowed_key_lookup() {
+  let mut btree = ["apple", "banana", "cherry", "durian", "elderberry"]
+    .iter()
+    .map(|k| (k.to_string(), k.len()))
+    .collect::<BTree<String, usize, 2>>();
+  assert_eq!(Some(&6), btree.get("banana"));
+  assert_eq!(Some(6), btree.get_cloned("cherry"));
+  assert_eq!(6, btree["durian"]);
+  assert!(btree.contains_key("apple"));
+  assert!(!btree.contains_key("fig"));
+  assert_eq!(Some(1), btree.access_count("banana"));
+  assert_eq!(Ok(6), btree.compare_exchange_value("cherry", 6, 60));
+  assert_eq!(Some(5), btree.delete("apple"));
+  assert_eq!(None, btree.delete("apple"));
+  let right = btree.split_off("d");
+  assert_eq!(
+    vec!["banana", "cherry"],
+    btree.iter().map(|(k, _)| k).collect::<Vec<_>>()
+  );
+  assert_eq!(2, right.size());
+
+  let bytes = (0..20u8)
+    .map(|i| (vec![i, i], i))
+    .collect::<BTree<Vec<u8>, u8, 2>>();
+  assert_eq!(Some(&7), bytes.get(&[7u8, 7][..]));
+  assert_eq!(None, bytes.get(&[7u8][..]));
+
+  #[cfg(feature = "serde")]
+  {
+    let path = std::env::temp_dir().join(format!("btree-{}.borrowed.wal", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+    let mut wal = crate::storage::WalBTree::open(path, BTree::<String, usize, 2>::new()).unwrap();
+    wal.put("fig".to_string(), 3).unwrap();
+    assert_eq!(Some(3), wal.delete("fig").unwrap());
+    drop(wal);
+    assert_eq!(
+      0,
+      BTree::<String, usize, 2>::rebuild_from_wal(None, path)
+        .unwrap()
+        .size()
+    );
+    std::fs::remove_file(path).unwrap();
+  }
+}
+
+#[test]
+fn rolling_percentile() {
+  let values = [5u32, 1, 4, 2, 8, 3, 3, 9];
+  let btree = values
+    .iter()
+    .enumerate()
+    .map(|(k, v)| (k as u32, *v))
+    .collect::<BTree<_, _, 2>>();
+  assert_eq!(
+    vec![(2, 4.0), (3, 2.0), (4, 4.0), (5, 3.0), (6, 3.0), (7, 3.0)],
+    btree.rolling_percentile(50.0, 3)
+  );
+  assert_eq!(
+    vec![(2, 5.0), (3, 4.0), (4, 8.0), (5, 8.0), (6, 8.0), (7, 9.0)],
+    btree.rolling_percentile(100.0, 3)
+  );
+  assert_eq!(
+    vec![(7, 3.0 + 0.5 * 1.0)],
+    btree.rolling_percentile(50.0, 8)
+  );
+  assert_eq!(values.len(), btree.rolling_percentile(25.0, 1).len());
+  assert!(btree.rolling_percentile(50.0, 9).is_empty());
+  let p = btree.rolling_percentile(25.0, 4);
+  // window [5, 1, 4, 2] -> sorted [1, 2, 4, 5], position 0.75
+  assert_eq!((3, 1.75), p[0]);
+}
+
+#[test]
+fn key_entropy() {
+  assert_eq!(0.0, BTree::<u32, u32, 2>::new().key_entropy());
+  let even = (0..100u32).map(|k| (k * 3, k)).collect::<BTree<_, _, 2>>();
+  assert!((even.key_entropy() - 100f64.log2()).abs() < 1e-9);
+  let clustered = [0i64, 1, 2, 3, 1000]
+    .iter()
+    .map(|k| (*k, 0))
+    .collect::<BTree<_, _, 2>>();
+  let expected = -(0.8f64 * 0.8f64.log2() + 0.2 * 0.2f64.log2());
+  assert!((clustered.key_entropy() - expected).abs() < 1e-9);
+
+  // keys spanning the whole i128 range must not overflow
+  let full = [i128::MIN, 0, i128::MAX]
+    .iter()
+    .map(|k| (*k, 0))
+    .collect::<BTree<_, _, 2>>();
+  assert!((full.key_entropy() - 3f64.log2()).abs() < 1e-9);
+  let edges = [i128::MIN, i128::MIN + 1, i128::MAX - 1, i128::MAX]
+    .iter()
+    .map(|k| (*k, 0))
+    .collect::<BTree<_, _, 2>>();
+  assert!((edges.key_entropy() - 1.0).abs() < 1e-9);
+  let narrow = [i128::MAX - 3, i128::MAX - 2, i128::MAX - 1, i128::MAX]
+    .iter()
+    .map(|k| (*k, 0))
+    .collect::<BTree<_, _, 2>>();
+  assert!((narrow.key_entropy() - 2.0).abs() < 1e-9);
+
+  let tens = (1..=9u32).map(|k| (k * 10, k)).collect::<BTree<_, _, 2>>();
+  let digits = tens.digit_entropy();
+  assert_eq!(2, digits.len());
+  assert_eq!(0.0, digits[0]);
+  assert!((digits[1] - 9f64.log2()).abs() < 1e-9);
+  let signed = (-9i32..=9).map(|k| (k, k)).collect::<BTree<_, _, 2>>();
+  let (zero, other) = (1.0 / 19.0, 2.0 / 19.0);
+  let expected = -(zero * f64::log2(zero) + 9.0 * other * f64::log2(other));
+  assert!((signed.digit_entropy()[0] - expected).abs() < 1e-9);
+}
+
+#[test]
+fn btree_macro() {
+  let sorted = crate::btree! { S = 3; "a" => 1, "b" => 2, "c" => 3 };
+  assert_eq!(
+    vec![("a", 1), ("b", 2), ("c", 3)],
+    sorted.iter().collect::<Vec<_>>()
+  );
+  let unsorted: BTree<u32, u32, 2> = crate::btree! { S = 2; 5 => 50, 1 => 10, 3 => 30, };
+  validate(&unsorted);
+  assert_eq!(
+    vec![1, 3, 5],
+    unsorted.iter().map(|(k, _)| k).collect::<Vec<_>>()
+  );
+  let empty: BTree<u32, u32, 2> = crate::btree! { S = 2; };
+  assert_eq!(0, empty.size());
+  let (one, two) = (1, 2);
+  let result = std::panic::catch_unwind(|| crate::btree! { S = 2; one => 1, two => 2, 1 => 3 });
+  assert!(result.is_err());
+}
+
+#[test]
+fn batch_upsert() {
+  let mut btree = (0..10u32).map(|k| (k, 1u64)).collect::<BTree<_, _, 2>>();
+  let pairs = [(3, 5), (20, 7), (4, 0), (3, 1), (21, 0)];
+  assert_eq!(4, btree.batch_upsert(&pairs, |a, b| a + b));
+  validate(&btree);
+  assert_eq!(Some(&7), btree.get(&3));
+  assert_eq!(Some(&1), btree.get(&4));
+  assert_eq!(Some(&7), btree.get(&20));
+  assert_eq!(Some(&0), btree.get(&21));
+  assert_eq!(12, btree.size());
+}
+
+#[test]
+fn invariant_violation() {
+  use crate::Error;
+
+  let mut btree = (0..5).map(|k| (k, k)).collect::<BTree<_, _, 2>>();
+  btree.root.borrow_mut().pivots[1].borrow_mut().is_leaf = false;
+  assert!(matches!(
+    btree.try_delete(&0),
+    Err(Error::InvariantViolation(_))
+  ));
+
+  let mut btree = (0..5).map(|k| (k, k)).collect::<BTree<_, _, 2>>();
+  btree.root.borrow_mut().pivots.pop();
+  assert!(matches!(
+    btree.try_put(10, 10),
+    Err(Error::InvariantViolation(_))
+  ));
+
+  let mut btree = (0..5).map(|k| (k, k)).collect::<BTree<_, _, 2>>();
+  assert_eq!(Ok(None), btree.try_put(5, 5).map_err(|e| e.to_string()));
+  assert_eq!(Ok(Some(0)), btree.try_delete(&0).map_err(|e| e.to_string()));
+  validate(&btree);
+}
+
+#[test]
+fn sliding_window_sum() {
+  let btree = (1..=10u32)
+    .map(|k| (k, k as u64))
+    .collect::<BTree<_, _, 2>>();
+  for window in 1..=11 {
+    let expected = (window..=10)
+      .map(|last| {
+        (
+          last as u32,
+          ((last + 1 - window)..=last).sum::<usize>() as u64,
+        )
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(expected, btree.sliding_window_sum(window));
+  }
+  assert!(BTree::<u32, u64, 2>::new().sliding_window_sum(3).is_empty());
+}
+
+#[test]
+fn get_mut() {
+  let mut btree = (0..100)
+    .map(|k| (k, format!("v{k}")))
+    .collect::<BTree<_, _, 2>>();
+  for k in (0..100).step_by(7) {
+    btree.get_mut(&k).unwrap().push('!');
+  }
+  *btree.get_mut(&50).unwrap() = String::from("replaced");
+  assert!(btree.get_mut(&100).is_none());
+  for k in 0..100 {
+    let expected = match k {
+      50 => String::from("replaced"),
+      k if k % 7 == 0 => format!("v{k}!"),
+      k => format!("v{k}"),
+    };
+    assert_eq!(Some(&expected), btree.get(&k));
+  }
+  assert_eq!(Some(2), btree.access_count(&7));
+  validate(&btree);
+}
+
+#[test]
+fn select_by_value_rank() {
+  let values = [5u32, 3, 9, 3, 7, 1, 9, 0, 4, 8];
+  let btree = values
+    .iter()
+    .enumerate()
+    .map(|(k, v)| (k, *v))
+    .collect::<BTree<_, _, 2>>();
+  let mut expected = values
+    .iter()
+    .enumerate()
+    .map(|(k, v)| (*v, k))
+    .collect::<Vec<_>>();
+  expected.sort();
+  for (rank, (value, key)) in expected.iter().enumerate() {
+    assert_eq!(Some((key, value)), btree.select_by_value_rank(rank));
+  }
+  assert_eq!(None, btree.select_by_value_rank(values.len()));
+  assert_eq!(None, BTree::<u32, u32, 2>::new().select_by_value_rank(0));
+}
+
+#[test]
+fn transaction() {
+  let mut btree = (0..10).map(|k| (k, k)).collect::<BTree<_, _, 2>>();
+
+  let mut tx = btree.transaction();
+  assert_eq!(Some(3), tx.put(3, 30));
+  assert_eq!(Some(4), tx.delete(&4));
+  assert_eq!(None, tx.put(10, 100));
+  assert_eq!(Some(&30), tx.get(&3));
+  assert_eq!(None, tx.get(&4));
+  tx.commit();
+  assert_eq!(Some(&30), btree.get(&3));
+  assert_eq!(None, btree.get(&4));
+  assert_eq!(Some(&100), btree.get(&10));
+  validate(&btree);
+
+  let mut tx = btree.transaction();
+  tx.put(3, 0);
+  tx.delete(&0);
+  tx.rollback();
+  assert_eq!(Some(&30), btree.get(&3));
+  assert_eq!(Some(&0), btree.get(&0));
+
+  {
+    let mut tx = btree.transaction();
+    tx.put(20, 20);
+  }
+  assert_eq!(None, btree.get(&20));
+  assert_eq!(10, btree.size());
+}
+
+#[test]
+fn persistent_btree() {
+  use crate::PersistentBTree;
+  use std::collections::BTreeMap;
+
+  let mut rand = rand::rngs::StdRng::seed_from_u64(74);
+  let mut versions = vec![(PersistentBTree::<u32, u32, 2>::new(), BTreeMap::new())];
+  for _ in 0..500 {
+    let (tree, expected) = versions.last().unwrap();
+    let key = rand.next_u32() % 100;
+    let (tree, mut expected) = (tree.clone(), expected.clone());
+    let tree = if rand.next_u32() % 3 == 0 {
+      expected.remove(&key);
+      tree.delete(&key)
+    } else {
+      expected.insert(key, rand.next_u32());
+      tree.put(key, expected[&key])
+    };
+    versions.push((tree, expected));
+  }
+
+  // すべての過去のバージョンが変更されずに残っている
+  for (tree, expected) in versions.iter() {
+    assert_eq!(expected.len(), tree.len());
+    assert!(tree.iter().eq(expected.iter()));
+    for key in 0..100 {
+      assert_eq!(expected.get(&key), tree.get(&key));
     }
-    let depth = depths.first().unwrap();
-    if !depths.iter().all(|d| d == depth) {
-      return Err(format!(
-        "[{}] The tree level is not match: {:?}",
-        _depth, depths
-      ));
+  }
+
+  let count = versions[0].0.version_count();
+  assert!(count > 1 && count <= versions.len());
+  let latest = versions.pop().unwrap().0;
+  drop(versions);
+  assert_eq!(1, latest.version_count());
+  assert_eq!(1, latest.delete(&1000).version_count());
+  let next = latest.put(1000, 0);
+  assert_eq!(2, latest.version_count());
+  assert_eq!(2, next.version_count());
+}
+
+#[test]
+fn change_data_capture() {
+  use crate::{MutationEvent, MutationKind};
+
+  let mut tree = (0..3)
+    .map(|k| (k, k))
+    .collect::<BTree<_, _, 2>>()
+    .with_cdc();
+  assert_eq!(0, tree.pending_events());
+  assert_eq!(None, tree.put(5, 50));
+  assert_eq!(Some(1), tree.put(1, 10));
+  assert_eq!(Some(2), tree.delete(&2));
+  assert_eq!(None, tree.delete(&2));
+  assert_eq!(3, tree.pending_events());
+
+  let event = |kind, key, old_value, new_value| MutationEvent {
+    kind,
+    key,
+    old_value,
+    new_value,
+  };
+  assert_eq!(
+    vec![
+      event(MutationKind::Inserted, 5, None, Some(50)),
+      event(MutationKind::Updated, 1, Some(1), Some(10)),
+      event(MutationKind::Deleted, 2, Some(2), None),
+    ],
+    tree.drain_events().collect::<Vec<_>>()
+  );
+  assert_eq!(0, tree.pending_events());
+  assert_eq!(0, tree.drain_events().count());
+  assert_eq!(
+    vec![(0, 0), (1, 10), (5, 50)],
+    tree.into_btree().iter().collect::<Vec<_>>()
+  );
+}
+
+#[test]
+fn sibling_key() {
+  use crate::SiblingDirection::{Left, Right};
+
+  let btree = (0..200).map(|k| (k * 2, k)).collect::<BTree<_, _, 2>>();
+  for k in 0..200 {
+    let left = (k > 0).then(|| ((k - 1) * 2, k - 1));
+    let right = (k < 199).then(|| ((k + 1) * 2, k + 1));
+    let sibling = |d| btree.sibling_key(&(k * 2), d).map(|(k, v)| (*k, *v));
+    assert_eq!(left, sibling(Left));
+    assert_eq!(right, sibling(Right));
+    assert_eq!(None, btree.sibling_key(&(k * 2 + 1), Left));
+    assert_eq!(None, btree.sibling_key(&(k * 2 + 1), Right));
+  }
+  assert_eq!(None, BTree::<u32, u32, 2>::new().sibling_key(&0, Left));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn byte_stream_insert() {
+  let mut stream = Vec::new();
+  for (key, value) in [(3u32, "three"), (1, "one"), (3, "THREE")] {
+    for bytes in [
+      bincode::serialize(&key).unwrap(),
+      bincode::serialize(value).unwrap(),
+    ] {
+      stream.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+      stream.extend_from_slice(&bytes);
     }
-    return Ok(*depth);
-  } else if !node.pivots.is_empty() {
-    return Err(format!(
-      "[{}] Pivots are not empty: {:?}",
-      _depth,
-      node.pivots.len()
-    ));
   }
+  let mut btree = BTree::<u32, String, 2>::new();
+  assert_eq!(3, btree.byte_stream_insert(stream.as_slice()).unwrap());
+  assert_eq!(
+    vec![(1, String::from("one")), (3, String::from("THREE"))],
+    btree.iter().collect::<Vec<_>>()
+  );
+  assert_eq!(0, btree.byte_stream_insert(&[][..]).unwrap());
+
+  // レコードの途中で終了したストリーム
+  let mut btree = BTree::<u32, String, 2>::new();
+  assert!(matches!(
+    btree.byte_stream_insert(&stream[..stream.len() - 1]),
+    Err(crate::Error::IO(_))
+  ));
+  // a huge length prefix fails on the short stream instead of allocating it up front
+  assert!(matches!(
+    btree.byte_stream_insert(&[0xFF, 0xFF, 0xFF, 0xFF, 1, 2, 3, 4, 5][..]),
+    Err(crate::Error::IO(_))
+  ));
+  assert_eq!(2, btree.size());
+}
+
+#[cfg(feature = "bloom")]
+#[test]
+fn bloom_filter() {
+  let mut btree = (0..1000u32).map(|k| (k * 2, k)).collect::<BTree<_, _, 2>>();
+  assert_eq!(0.0, btree.bloom_stats().fill_rate);
+  assert_eq!(Some(&1), btree.get_filtered(&2));
+
+  btree.rebuild_bloom_filter();
+  let stats = btree.bloom_stats();
+  assert!(stats.fill_rate > 0.0 && stats.fill_rate < 0.5);
+  assert!(stats.estimated_false_positive_rate < 0.1);
+  for k in 0..1000u32 {
+    assert_eq!(Some(&k), btree.get_filtered(&(k * 2)));
+  }
+  let false_positives = (0..1000u32)
+    .filter(|k| btree.bloom.as_ref().unwrap().may_contain(&(k * 2 + 1)))
+    .count();
+  assert!(false_positives < 150, "{false_positives} false positives");
+
+  // put や extend、merge で追加したキーも検出される
+  btree.put(5001, 1);
+  btree.extend([(6001, 2), (6003, 3)]);
+  btree.merge((7001..7100).map(|k| (k, k)).collect());
+  assert_eq!(Some(&1), btree.get_filtered(&5001));
+  assert_eq!(Some(&3), btree.get_filtered(&6003));
+  assert_eq!(Some(&7050), btree.get_filtered(&7050));
+
+  // 削除したキーはフィルタに残るがツリーの探索で None となる
+  btree.delete(&4);
+  assert_eq!(None, btree.get_filtered(&4));
+  let before = btree.bloom_stats().fill_rate;
+  for k in 0..500u32 {
+    btree.delete(&(k * 2));
+  }
+  btree.rebuild_bloom_filter();
+  assert!(btree.bloom_stats().fill_rate <= before);
 
-  // all keys are sorted
-  if !node.keys.is_empty() {
-    for i in 0..node.keys.len() - 1 {
-      if node.keys[i].key >= node.keys[i + 1].key {
-        return Err(format!(
-          "[{}] Keys are not sorted: key[{}] ≧ key[{}",
-          _depth,
-          i,
-          i + 1
-        ));
+  // borrowed keys are checked against the filter as well
+  let mut names = ["alice", "bob"]
+    .map(|k| (k.to_string(), k.len()))
+    .into_iter()
+    .collect::<BTree<_, _, 2>>();
+  names.rebuild_bloom_filter();
+  assert_eq!(Some(&3), names.get_filtered("bob"));
+  assert!(names.bloom.as_ref().unwrap().may_contain("alice"));
+  assert_eq!(None, names.get_filtered("carol"));
+
+  // get keeps accepting keys that do not implement Hash
+  #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+  struct Plain(u32);
+  let plain = (0..10u32)
+    .map(|k| (Plain(k), k))
+    .collect::<BTree<_, _, 2>>();
+  assert_eq!(Some(&3), plain.get(&Plain(3)));
+}
+
+#[test]
+fn fuzzy_get() {
+  fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in 0..=a.len() {
+      for j in 0..=b.len() {
+        d[i][j] = match (i, j) {
+          (0, j) => j,
+          (i, 0) => i,
+          (i, j) => (d[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]))
+            .min(d[i - 1][j] + 1)
+            .min(d[i][j - 1] + 1),
+        };
       }
     }
+    d[a.len()][b.len()]
   }
 
-  // all branches are smaller or larger
-  if !node.is_leaf {
-    for i in 0..node.keys.len() {
-      if node.pivots[i].borrow().keys.last().unwrap().key >= node.keys[i].key {
-        return Err(format!(
-          "[{}] The left branch of key[{}] is out of order",
-          _depth, i
-        ));
-      }
-      if node.pivots[i + 1].borrow().keys.first().unwrap().key <= node.keys[i].key {
-        return Err(format!(
-          "[{}] The left branch of key[{}] is out of order",
-          _depth, i
-        ));
-      }
+  let words = [
+    "apple",
+    "apply",
+    "ample",
+    "maple",
+    "applet",
+    "app",
+    "banana",
+    "bandana",
+    "cat",
+    "cart",
+    "hat",
+    "",
+    "日本語",
+    "日本",
+  ];
+  let btree = words
+    .iter()
+    .enumerate()
+    .map(|(i, w)| (w.to_string(), i))
+    .collect::<BTree<_, _, 2>>();
+  for query in ["apple", "bat", "", "日本", "xyz"] {
+    for distance in 0..4 {
+      let mut expected = words
+        .iter()
+        .enumerate()
+        .filter(|(_, w)| edit_distance(w, query) <= distance)
+        .map(|(i, w)| (w.to_string(), i))
+        .collect::<Vec<_>>();
+      expected.sort();
+      let actual = btree
+        .fuzzy_get(query, distance)
+        .into_iter()
+        .map(|(k, v)| (k.clone(), *v))
+        .collect::<Vec<_>>();
+      assert_eq!(expected, actual, "{query:?} within {distance}");
+    }
+  }
+}
+
+#[test]
+fn iter_by_value_chunk() {
+  let values = [1u32, 2, 9, 3, 9, 9, 4, 5, 6, 10];
+  let btree = values
+    .iter()
+    .enumerate()
+    .map(|(k, v)| (k, *v))
+    .collect::<BTree<_, _, 2>>();
+  let runs = btree
+    .iter_by_value_chunk(9)
+    .map(|run| run.into_iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>())
+    .collect::<Vec<_>>();
+  assert_eq!(
+    vec![
+      vec![(0, 1), (1, 2)],
+      vec![(3, 3)],
+      vec![(6, 4), (7, 5), (8, 6)]
+    ],
+    runs
+  );
+  assert_eq!(1, btree.iter_by_value_chunk(100).count());
+  assert_eq!(0, btree.iter_by_value_chunk(0).count());
+}
+
+#[test]
+fn prefix_range() {
+  let keys: [&[u8]; 9] = [
+    b"",
+    b"a",
+    b"ab",
+    b"abc",
+    b"abd",
+    b"ac",
+    b"\xff",
+    b"\xff\xff",
+    b"\xff\xff\x00",
+  ];
+  let btree = keys
+    .iter()
+    .enumerate()
+    .map(|(i, k)| (k.to_vec(), i))
+    .collect::<BTree<_, _, 2>>();
+  for prefix in [&b""[..], b"a", b"ab", b"abc", b"b", b"\xff", b"\xff\xff"] {
+    let expected = btree
+      .iter()
+      .filter(|(k, _)| k.starts_with(prefix))
+      .collect::<Vec<_>>();
+    let actual = btree
+      .prefix_range(prefix)
+      .map(|(k, v)| (k.clone(), *v))
+      .collect::<Vec<_>>();
+    assert_eq!(expected, actual, "{prefix:?}");
+  }
+
+  let btree = ["app", "apple", "applet", "apply", "apt", "b", "é", "éa"]
+    .iter()
+    .enumerate()
+    .map(|(i, k)| (k.to_string(), i))
+    .collect::<BTree<_, _, 2>>();
+  let keys = |prefix| {
+    btree
+      .prefix_range(prefix)
+      .map(|(k, _)| k.as_str())
+      .collect::<Vec<_>>()
+  };
+  assert_eq!(vec!["apple", "applet"], keys("apple"));
+  assert_eq!(vec!["app", "apple", "applet", "apply"], keys("app"));
+  assert_eq!(vec!["é", "éa"], keys("é"));
+  assert!(keys("c").is_empty());
+  assert_eq!(8, keys("").len());
+}
+
+#[test]
+fn fold_range() {
+  let btree = (0..100u32)
+    .map(|k| (k, k as u64 * 10))
+    .collect::<BTree<_, _, 2>>();
+  assert_eq!(
+    (10..20).map(|k| k * 10).sum::<u64>(),
+    btree.fold_range(10..20, 0, |sum, (_, v)| sum + v)
+  );
+  assert_eq!(
+    Some((99, 990)),
+    btree.fold_range(90.., None, |max, (k, v)| max.max(Some((*k, *v))))
+  );
+  assert_eq!(0, btree.fold_range(200.., 0, |n, _| n + 1));
+  assert_eq!(100, btree.fold_range(.., 0, |n, _| n + 1));
+
+  let mut keys = Vec::new();
+  btree.for_each_range(5..=8, |k, v| keys.push((*k, *v)));
+  assert_eq!(vec![(5, 50), (6, 60), (7, 70), (8, 80)], keys);
+}
+
+#[cfg(feature = "repair")]
+#[test]
+fn repair_node() {
+  // 5 つのキーを挿入したツリーはルート [2] と葉 [0, 1], [3, 4] からなる
+  let mut btree = (0..5).map(|k| (k, k)).collect::<BTree<_, _, 2>>();
+  btree.root.borrow().pivots[1].borrow_mut().keys.swap(0, 1);
+  assert!(btree.validate().is_err());
+  assert!(btree.repair_node(&[1]));
+  validate(&btree);
+  assert_eq!(
+    (0..5).map(|k| (k, k)).collect::<Vec<_>>(),
+    btree.iter().collect::<Vec<_>>()
+  );
+
+  // 区切りのキーを越えたキーは親ノードのキーと交換される
+  let mut btree = (0..5).map(|k| (k, k)).collect::<BTree<_, _, 2>>();
+  {
+    let root = btree.root.borrow();
+    let mut left = root.pivots[0].borrow_mut();
+    let mut right = root.pivots[1].borrow_mut();
+    std::mem::swap(&mut left.keys[1], &mut right.keys[0]);
+  }
+  assert!(btree.repair_node(&[0]));
+  assert!(btree.repair_node(&[1]));
+  // 右の葉の修復で親に移動したキーによって左の葉が再び区切りを越える
+  assert!(btree.validate().is_err());
+  assert!(btree.repair_node(&[0]));
+  validate(&btree);
+  assert_eq!(
+    (0..5).map(|k| (k, k)).collect::<Vec<_>>(),
+    btree.iter().collect::<Vec<_>>()
+  );
+
+  assert!(btree.repair_node(&[]));
+  assert!(!btree.repair_node(&[2]));
+  assert!(!btree.repair_node(&[0, 0]));
+}
+
+#[test]
+fn iter_with_depth() {
+  let btree = (0..200).map(|k| (k, k * 2)).collect::<BTree<_, _, 2>>();
+  let entries = btree.iter_with_depth().collect::<Vec<_>>();
+  assert_eq!(200, entries.len());
+  assert!(entries
+    .iter()
+    .all(|(depth, _, _)| *depth == btree.level() - 1));
+  assert!(entries
+    .iter()
+    .enumerate()
+    .all(|(i, (_, k, v))| **k == i && **v == i * 2));
+  assert_eq!(
+    vec![(0, &1, &1)],
+    BTree::<_, _, 2>::from_iter([(1, 1)])
+      .iter_with_depth()
+      .collect::<Vec<_>>()
+  );
+
+  // 葉の深さが揃っていないツリー: ルート [2] の右の子を 1 段深くする
+  let btree = (0..5).map(|k| (k, k)).collect::<BTree<_, _, 2>>();
+  {
+    let mut root = btree.root.borrow_mut();
+    let leaf = root.pivots[1].clone();
+    let mut wrapper = Node::new(false);
+    wrapper.pivots.push(leaf);
+    root.pivots[1] = Rc::new(RefCell::new(wrapper));
+  }
+  assert_eq!(
+    vec![1, 1, 1, 2, 2],
+    btree
+      .iter_with_depth()
+      .map(|(d, _, _)| d)
+      .collect::<Vec<_>>()
+  );
+}
+
+#[test]
+fn set_ops() {
+  use crate::set_ops::{difference, intersection, union};
+
+  let a = (0..20u32)
+    .filter(|k| k % 2 == 0)
+    .map(|k| (k, k))
+    .collect::<BTree<_, _, 2>>();
+  let b = (0..20u32)
+    .filter(|k| k % 3 == 0)
+    .map(|k| (k, k * 100))
+    .collect::<BTree<_, _, 2>>();
+  let sum = |_: &u32, x: &u32, y: &u32| x + y;
+
+  let both = intersection(&a, &b, sum);
+  validate(&both);
+  assert_eq!(
+    vec![(0, 0), (6, 606), (12, 1212), (18, 1818)],
+    both.iter().collect::<Vec<_>>()
+  );
+
+  let all = union(&a, &b, sum);
+  validate(&all);
+  let expected = (0..20u32)
+    .filter(|k| k % 2 == 0 || k % 3 == 0)
+    .map(|k| match (k % 2 == 0, k % 3 == 0) {
+      (true, true) => (k, k + k * 100),
+      (true, false) => (k, k),
+      _ => (k, k * 100),
+    })
+    .collect::<Vec<_>>();
+  assert_eq!(expected, all.iter().collect::<Vec<_>>());
+
+  let only_a = difference(&a, &b);
+  validate(&only_a);
+  assert_eq!(
+    vec![2, 4, 8, 10, 14, 16],
+    only_a.iter().map(|(k, _)| k).collect::<Vec<_>>()
+  );
+  assert_eq!(0, difference(&a, &a).size());
+  assert_eq!(a, union(&a, &BTree::new(), sum));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_for_each() {
+  use std::sync::atomic::{AtomicU64, Ordering};
+
+  let btree = (0..2000u64).map(|k| (k, k * 2)).collect::<BTree<_, _, 2>>();
+  for (start, end) in [(0, 2000), (13, 1500), (700, 701), (1999, 3000)] {
+    let (count, sum) = (AtomicU64::new(0), AtomicU64::new(0));
+    btree.par_for_each(start..end, |k, v| {
+      assert_eq!(k * 2, *v);
+      count.fetch_add(1, Ordering::Relaxed);
+      sum.fetch_add(*k, Ordering::Relaxed);
+    });
+    let expected = (start..end.min(2000)).collect::<Vec<_>>();
+    assert_eq!(expected.len() as u64, count.into_inner());
+    assert_eq!(expected.iter().sum::<u64>(), sum.into_inner());
+  }
+  let visited = AtomicU64::new(0);
+  btree.par_for_each(..=5, |_, _| {
+    visited.fetch_add(1, Ordering::Relaxed);
+  });
+  assert_eq!(6, visited.into_inner());
+}
+
+#[test]
+fn range_by_first_field() {
+  use crate::CompositeKey;
+
+  let btree = (0..10u32)
+    .flat_map(|a| (0..5i32).map(move |b| (CompositeKey(a, b - 2), a * 10 + b as u32)))
+    .collect::<BTree<_, _, 2>>();
+  let keys =
+    |r: Vec<(&CompositeKey<u32, i32>, &u32)>| r.into_iter().map(|(k, _)| *k).collect::<Vec<_>>();
+
+  let single = keys(btree.range_by_first_field(3..=3).collect());
+  assert_eq!(
+    (-2..3).map(|b| CompositeKey(3, b)).collect::<Vec<_>>(),
+    single
+  );
+  assert_eq!(10, btree.range_by_first_field(4..6).count());
+  assert_eq!(15, btree.range_by_first_field(7..).count());
+  assert_eq!(5, btree.range_by_first_field(..1).count());
+  assert_eq!(
+    Some((&CompositeKey(9, -2), &90)),
+    btree
+      .range_by_first_field((Bound::Excluded(8), Bound::Unbounded))
+      .next()
+  );
+  assert_eq!(0, btree.range_by_first_field(20..).count());
+}
+
+#[test]
+fn auto_incr_btree() {
+  use crate::AutoIncrBTree;
+
+  let mut btree = AutoIncrBTree::<&str, 2>::new();
+  assert!(btree.is_empty());
+  assert_eq!(0, btree.insert("a"));
+  assert_eq!(1, btree.insert("b"));
+  assert_eq!(2, btree.peek_next_key());
+  assert_eq!(Some("b"), btree.delete(1));
+  assert_eq!(2, btree.insert("c"));
+
+  assert_eq!(None, btree.insert_at(1, "d"));
+  assert_eq!(3, btree.peek_next_key());
+  assert_eq!(None, btree.insert_at(10, "e"));
+  assert_eq!(11, btree.peek_next_key());
+  assert_eq!(Some("e"), btree.insert_at(10, "f"));
+  assert_eq!(11, btree.insert("g"));
+
+  assert_eq!(Some(&"d"), btree.get(1));
+  assert_eq!(None, btree.get(5));
+  assert_eq!(
+    vec![(0, "a"), (1, "d"), (2, "c"), (10, "f"), (11, "g")],
+    btree.iter().collect::<Vec<_>>()
+  );
+  assert_eq!(5, btree.len());
+}
+
+#[test]
+fn force_rebalance() {
+  let mut btree = (0..1000u32).map(|k| (k, k)).collect::<BTree<_, _, 3>>();
+  for k in (0..1000).filter(|k| k % 3 != 0) {
+    btree.delete(&k);
+  }
+  btree.get(&300);
+  btree.get(&300);
+  let nodes_before = btree.stats().total_node_count;
+  btree.force_rebalance();
+  validate(&btree);
+  assert!(btree.stats().total_node_count <= nodes_before);
+  assert_eq!(
+    (0..1000)
+      .filter(|k| k % 3 == 0)
+      .map(|k| (k, k))
+      .collect::<Vec<_>>(),
+    btree.iter().collect::<Vec<_>>()
+  );
+  assert_eq!(
+    BTree::<_, _, 3>::from_sorted_iter(btree.iter()).stats(),
+    btree.stats()
+  );
+  assert_eq!(Some(2), btree.access_count(&300));
+
+  let mut empty = BTree::<u32, u32, 2>::new();
+  empty.force_rebalance();
+  assert_eq!(0, empty.size());
+}
+
+#[test]
+fn deep_clone_with_transform() {
+  let btree = (0..100u32).map(|k| (k, k * 2)).collect::<BTree<_, _, 2>>();
+  let doubled = btree.deep_clone_with_transform(|k| u64::from(k) * 2, |v| v.to_string());
+  validate(&doubled);
+  assert_eq!(
+    (0..100u64)
+      .map(|k| (k * 2, (k * 2).to_string()))
+      .collect::<Vec<_>>(),
+    doubled.iter().collect::<Vec<_>>()
+  );
+
+  let folded = btree.deep_clone_with_transform(|k| k % 10, |v| v);
+  validate(&folded);
+  assert_eq!(
+    (0..10u32).map(|k| (k, (90 + k) * 2)).collect::<Vec<_>>(),
+    folded.iter().collect::<Vec<_>>()
+  );
+  assert_eq!(100, btree.size());
+}
+
+#[test]
+fn indexed_btree() {
+  use crate::IndexedBTree;
+
+  let mut btree = IndexedBTree::<u32, String, String, 2>::new(|_, v| v.to_lowercase());
+  for (k, v) in [(1, "Alice"), (2, "Bob"), (3, "Carol")] {
+    assert_eq!(None, btree.put(k, v.to_string()).unwrap());
+  }
+  assert_eq!(
+    Some((&2, &"Bob".to_string())),
+    btree.get_by_index(&"bob".to_string())
+  );
+
+  assert!(matches!(
+    btree.put(4, "ALICE".to_string()),
+    Err(crate::Error::DuplicateIndexKey)
+  ));
+  assert_eq!(None, btree.get(&4));
+  assert!(matches!(
+    btree.put(3, "alice".to_string()),
+    Err(crate::Error::DuplicateIndexKey)
+  ));
+  assert_eq!(Some(&"Carol".to_string()), btree.get(&3));
+
+  assert_eq!(
+    Some("Bob".to_string()),
+    btree.put(2, "Dave".to_string()).unwrap()
+  );
+  assert_eq!(None, btree.get_by_index(&"bob".to_string()));
+  assert_eq!(
+    Some((&2, &"Dave".to_string())),
+    btree.get_by_index(&"dave".to_string())
+  );
+  assert_eq!(
+    Some("Dave".to_string()),
+    btree.put(2, "DAVE".to_string()).unwrap()
+  );
+  assert_eq!(
+    Some((&2, &"DAVE".to_string())),
+    btree.get_by_index(&"dave".to_string())
+  );
+
+  assert_eq!(Some("Alice".to_string()), btree.delete(&1));
+  assert_eq!(None, btree.get_by_index(&"alice".to_string()));
+  assert_eq!(2, btree.len());
+  assert_eq!(btree.len(), btree.secondary().size());
+}
+
+#[test]
+fn consistent_hash_shard() {
+  type Tree = BTree<u64, (), 2>;
+
+  let mut counts = [0usize; 8];
+  for key in 0..8000u64 {
+    let shard = Tree::consistent_hash_shard(&key, 8);
+    assert_eq!(shard, Tree::consistent_hash_shard(&key, 8));
+    counts[shard] += 1;
+  }
+  assert!(
+    counts.iter().all(|&c| (700..1300).contains(&c)),
+    "{counts:?}"
+  );
+
+  let mut moved = 0;
+  for key in 0..8000u64 {
+    let (before, after) = (
+      Tree::consistent_hash_shard(&key, 8),
+      Tree::consistent_hash_shard(&key, 9),
+    );
+    if before != after {
+      assert_eq!(8, after);
+      moved += 1;
+    }
+  }
+  assert!((500..1300).contains(&moved), "{moved}");
+  assert_eq!(0, Tree::consistent_hash_shard(&42, 1));
+}
+
+#[test]
+fn inverted_index() {
+  use crate::InvertedIndex;
+
+  let mut index = InvertedIndex::<&str, u32, 2>::new();
+  let docs = [
+    (3, "the quick brown fox"),
+    (1, "the lazy dog"),
+    (2, "quick brown dog"),
+    (4, "the brown fox jumps"),
+  ];
+  for (id, text) in docs {
+    index.index_document(id, text.split(' '));
+  }
+  index.index_document(3, ["fox"].into_iter());
+  assert_eq!(2, index.term_frequency(&"fox"));
+  assert_eq!(0, index.term_frequency(&"cat"));
+  assert_eq!(vec![1, 3, 4], index.search(&["the"]));
+  assert_eq!(vec![3, 4], index.search(&["brown", "the", "fox"]));
+  assert_eq!(vec![2], index.search(&["dog", "quick"]));
+  assert!(index.search(&["dog", "cat"]).is_empty());
+  assert!(index.search(&[]).is_empty());
+
+  index.remove_document(4, "the brown fox jumps".split(' '));
+  assert_eq!(vec![3], index.search(&["brown", "the", "fox"]));
+  assert_eq!(0, index.term_frequency(&"jumps"));
+  assert_eq!(6, index.term_count());
+}
+
+#[test]
+fn keys_in_common() {
+  let a = (0..100u32)
+    .filter(|k| k % 2 == 0)
+    .map(|k| (k, k))
+    .collect::<BTree<_, _, 2>>();
+  let b = (0..100u32)
+    .filter(|k| k % 3 == 0)
+    .map(|k| (k, k.to_string()))
+    .collect::<BTree<_, _, 2>>();
+  assert_eq!(
+    (0..100).filter(|k| k % 6 == 0).collect::<Vec<_>>(),
+    a.keys_in_common(&b)
+  );
+  assert!(a.keys_in_common(&BTree::<u32, (), 2>::new()).is_empty());
+}
+
+#[test]
+fn rank_and_select() {
+  let mut rng = rand::rngs::StdRng::seed_from_u64(324);
+  let mut btree = BTree::<u32, u32, 2>::new();
+  let mut reference = std::collections::BTreeMap::new();
+  for _ in 0..2000 {
+    let key = rng.next_u32() % 500;
+    if rng.next_u32() % 3 == 0 {
+      assert_eq!(reference.remove(&key), btree.delete(&key));
+    } else {
+      assert_eq!(reference.insert(key, key * 2), btree.put(key, key * 2));
     }
   }
-  Ok(_depth)
+  let right = btree.split_off(&250);
+  validate(&btree);
+  validate(&right);
+  btree.merge(right);
+  validate(&btree);
+
+  assert_eq!(reference.len(), btree.size());
+  for (rank, (k, v)) in reference.iter().enumerate() {
+    assert_eq!(Some(rank), btree.rank(k));
+    assert_eq!(Some((k, v)), btree.select(rank));
+  }
+  assert_eq!(None, btree.select(reference.len()));
+  let missing = (0..500).find(|k| !reference.contains_key(k)).unwrap();
+  assert_eq!(None, btree.rank(&missing));
+  assert_eq!(None, BTree::<u32, u32, 2>::new().select(0));
+}
+
+#[test]
+fn btree_priority_queue() {
+  use crate::BTreePriorityQueue;
+
+  let mut queue = BTreePriorityQueue::<u32, char, 2>::new();
+  assert!(queue.is_empty());
+  assert_eq!(None, queue.pop());
+  let ids = [(3, 'a'), (1, 'b'), (3, 'c'), (2, 'd'), (1, 'e')].map(|(p, i)| queue.push(p, i));
+  assert_eq!([0, 1, 2, 3, 4], ids);
+  assert_eq!(5, queue.len());
+  assert_eq!(Some((&1, &'b')), queue.peek());
+
+  assert!(queue.change_priority(2, 0));
+  assert!(!queue.change_priority(99, 0));
+  assert_eq!(Some((0, 'c')), queue.pop());
+  assert_eq!(Some((1, 'b')), queue.pop());
+  assert!(!queue.change_priority(1, 5));
+  assert_eq!(Some((1, 'e')), queue.pop());
+  assert_eq!(Some((2, 'd')), queue.pop());
+  assert_eq!(Some((3, 'a')), queue.pop());
+  assert_eq!(None, queue.peek());
+  assert!(queue.is_empty());
+}
+
+#[test]
+fn summarize() {
+  use crate::RangeSummary;
+
+  let btree = (0..100i64)
+    .map(|k| (k, (k - 50) * (k % 7)))
+    .collect::<BTree<_, _, 2>>();
+  for (start, end) in [(0, 100), (10, 20), (42, 43), (95, 200)] {
+    let values = (start..end.min(100))
+      .map(|k| (k - 50) * (k % 7))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      Some(RangeSummary {
+        count: values.len(),
+        min: *values.iter().min().unwrap(),
+        max: *values.iter().max().unwrap(),
+        sum: values.iter().sum(),
+      }),
+      btree.summarize(start..end)
+    );
+  }
+  assert_eq!(None, btree.summarize(200..));
+  assert_eq!(Some(1), btree.summarize(..=0).map(|s| s.count));
 }
@@ -0,0 +1,89 @@
+use crate::{BTree, Iter};
+use std::time::{Duration, Instant};
+
+/// 内部のイテレータが列挙する要素を毎秒最大 `rate` 個に制限するイテレータです。`BTree::throttled_iter` で
+/// 構築します。
+///
+/// `n` 番目の要素は列挙を開始した時刻から `n / rate` 秒が経過するまで返されません。各要素の間隔ではなく開始時刻
+/// からの経過時間で待機するため、呼び出し側の処理に時間がかかった場合でも全体の速度が `rate` を下回ることは
+/// ありません。
+///
+#[derive(Debug)]
+pub struct ThrottledIter<I: Iterator> {
+  inner: I,
+  rate: f64,
+  started: Option<Instant>,
+  yielded: u64,
+}
+
+impl<I: Iterator> ThrottledIter<I> {
+  fn new(inner: I, rate: f64) -> Self {
+    assert!(
+      rate > 0.0,
+      "the rate must be positive, but {rate} was specified"
+    );
+    ThrottledIter {
+      inner,
+      rate,
+      started: None,
+      yielded: 0,
+    }
+  }
+
+  /// 次の要素を返してよい時刻を返します。
+  ///
+  fn deadline(&mut self) -> Instant {
+    let started = *self.started.get_or_insert_with(Instant::now);
+    let deadline = started
+      .checked_add(Duration::from_secs_f64(self.yielded as f64 / self.rate))
+      .unwrap_or(started);
+    self.yielded += 1;
+    deadline
+  }
+
+  /// `next` と同様に次の要素を返しますが、スレッドを停止せずに `tokio::time::sleep_until` で待機します。
+  ///
+  #[cfg(feature = "async")]
+  pub async fn next_async(&mut self) -> Option<I::Item> {
+    let item = self.inner.next()?;
+    tokio::time::sleep_until(self.deadline().into()).await;
+    Some(item)
+  }
+}
+
+impl<I: Iterator> Iterator for ThrottledIter<I> {
+  type Item = I::Item;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let item = self.inner.next()?;
+    let wait = self.deadline().saturating_duration_since(Instant::now());
+    if !wait.is_zero() {
+      std::thread::sleep(wait);
+    }
+    Some(item)
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// すべての Key-Value ペアをキーの昇順で、毎秒最大 `rate` 個の速度で列挙します。受け入れ可能な速度に上限の
+  /// ある下流のシステムへエクスポートするために使用します。`iter_while` と同じく要素はツリーに格納されている
+  /// キーと値への参照として返されます。
+  ///
+  /// 返されるイテレータの `next` は `std::thread::sleep` でスレッドを停止して待機します。`async` 機能が有効な
+  /// 場合、非同期のコンテキストでは代わりに `ThrottledIter::next_async` を使用します。
+  ///
+  /// `rate` が正の値でない場合は panic します。
+  ///
+  pub fn throttled_iter(
+    &self,
+    rate: f64,
+  ) -> ThrottledIter<impl Iterator<Item = (&KEY, &VALUE)> + '_> {
+    let mut iter = Iter::new(self);
+    let entries = core::iter::from_fn(move || iter.next_entry().map(|kv| (&kv.key, &kv.value)));
+    ThrottledIter::new(entries, rate)
+  }
+}
@@ -0,0 +1,94 @@
+use crate::BTree;
+use core::borrow::Borrow;
+
+/// `BTree::transaction` で開始したトランザクションです。開始時点のツリーを複製したシャドウに対して操作を行い、
+/// `commit` するまで元のツリーは変更されません。
+///
+/// `commit` でシャドウを元のツリーに置き換え、`rollback` でシャドウを破棄します。いずれも呼び出さずに破棄
+/// されたトランザクションはロールバックされます。
+///
+#[derive(Debug)]
+pub struct Transaction<'a, KEY, VALUE, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  tree: &'a mut BTree<KEY, VALUE, S>,
+  /// 操作の対象となるツリーの複製です。`commit` で取り出されるまで常に `Some` です。
+  shadow: Option<BTree<KEY, VALUE, S>>,
+}
+
+impl<KEY, VALUE, const S: usize> Transaction<'_, KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// トランザクション内の状態で指定されたキーに関連付けられた値を返します。
+  ///
+  pub fn get<Q>(&self, key: &Q) -> Option<&VALUE>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.shadow().get(key)
+  }
+
+  pub fn put(&mut self, key: KEY, value: VALUE) -> Option<VALUE> {
+    self.shadow_mut().put(key, value)
+  }
+
+  pub fn delete<Q>(&mut self, key: &Q) -> Option<VALUE>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.shadow_mut().delete(key)
+  }
+
+  /// トランザクション内で行った変更を元のツリーに反映します。
+  ///
+  pub fn commit(mut self) {
+    *self.tree = self.shadow.take().unwrap();
+  }
+
+  /// トランザクション内で行った変更を破棄します。
+  ///
+  pub fn rollback(self) {}
+
+  fn shadow(&self) -> &BTree<KEY, VALUE, S> {
+    self.shadow.as_ref().unwrap()
+  }
+
+  fn shadow_mut(&mut self) -> &mut BTree<KEY, VALUE, S> {
+    self.shadow.as_mut().unwrap()
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Drop for Transaction<'_, KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// `commit` されていないトランザクションのシャドウを破棄し、元のツリーを変更せずに残します。
+  ///
+  fn drop(&mut self) {
+    self.shadow = None;
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// このツリーに対するトランザクションを開始します。トランザクションはツリーの複製に対して操作を行うため、
+  /// 開始時にすべてのノードを複製する O(n) の処理となります。
+  ///
+  pub fn transaction(&mut self) -> Transaction<'_, KEY, VALUE, S> {
+    let shadow = self.snapshot();
+    Transaction {
+      tree: self,
+      shadow: Some(shadow),
+    }
+  }
+}
@@ -0,0 +1,165 @@
+use crate::BTree;
+use std::borrow::Borrow;
+use std::time::{Duration, Instant};
+
+/// 各エントリに有効期間を持つ B-Tree です。`BTree::with_ttl_eviction` で構築します。
+///
+/// 各エントリには値と共に有効期限を保持し、有効期限の時刻に達したエントリは期限切れとして扱います。`put` で
+/// 挿入 (または更新) したエントリの有効期限はその時刻から `ttl` 後となり、`put_with_expiry` では任意の時刻を
+/// 指定できます。`put_without_expiry` で挿入したエントリは期限切れになりません。期限切れのエントリは `get` で
+/// 参照されたとき、または `purge_expired` を呼び出したときに削除されるため、削除されるまでは `len` に数え
+/// られます。
+///
+#[derive(Debug, Clone)]
+pub struct TtlBTree<KEY, VALUE, const S: usize>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  tree: BTree<KEY, (VALUE, Option<Instant>), S>,
+  ttl: Duration,
+}
+
+impl<KEY, VALUE, const S: usize> TtlBTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  pub fn ttl(&self) -> Duration {
+    self.ttl
+  }
+
+  /// 削除されていない期限切れのエントリを含むエントリの数を参照します。
+  ///
+  pub fn len(&self) -> usize {
+    self.tree.size()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// 指定されたキーに関連付けられた値を返します。値が存在しないか、期限切れの場合は None を返します。期限切れ
+  /// のエントリはこの時点でツリーから削除されます。
+  ///
+  pub fn get<Q>(&mut self, key: &Q) -> Option<VALUE>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let (value, expires_at) = self.tree.get_cloned(key)?;
+    if is_expired(expires_at, Instant::now()) {
+      self.tree.delete(key);
+      return None;
+    }
+    Some(value)
+  }
+
+  /// Key-Value ペアを現在の時刻から `ttl` 後を有効期限として挿入します。既に同じキーが存在する場合は値と有効
+  /// 期限を置き換え、期限切れでなければ古い値を返します。
+  ///
+  pub fn put(&mut self, key: KEY, value: VALUE) -> Option<VALUE> {
+    self.insert(key, value, Some(Instant::now() + self.ttl))
+  }
+
+  /// Key-Value ペアを `expires_at` を有効期限として挿入します。既に同じキーが存在する場合は値と有効期限を置き
+  /// 換え、期限切れでなければ古い値を返します。
+  ///
+  pub fn put_with_expiry(&mut self, key: KEY, value: VALUE, expires_at: Instant) -> Option<VALUE> {
+    self.insert(key, value, Some(expires_at))
+  }
+
+  /// 期限切れにならない Key-Value ペアを挿入します。既に同じキーが存在する場合は値を置き換え、期限切れでなけ
+  /// れば古い値を返します。
+  ///
+  pub fn put_without_expiry(&mut self, key: KEY, value: VALUE) -> Option<VALUE> {
+    self.insert(key, value, None)
+  }
+
+  /// 指定されたキーのエントリを削除します。期限切れでなければ削除した値を返します。
+  ///
+  pub fn delete<Q>(&mut self, key: &Q) -> Option<VALUE>
+  where
+    KEY: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let old = self.tree.delete(key)?;
+    self.live(old)
+  }
+
+  /// 有効期限が現在の時刻以前であるすべてのエントリを走査して削除し、削除したエントリの数を返します。
+  ///
+  pub fn purge_expired(&mut self) -> usize {
+    let now = Instant::now();
+    self
+      .tree
+      .retain(|_, (_, expires_at)| !is_expired(*expires_at, now))
+  }
+
+  /// `purge_expired` と同じく期限切れのすべてのエントリを削除します。
+  ///
+  pub fn evict_expired(&mut self) -> usize {
+    self.purge_expired()
+  }
+
+  /// 削除されていない期限切れのエントリを含む、すべてのエントリの中で最も早い有効期限を返します。有効期限を
+  /// 持つエントリが存在しない場合は None を返します。すべてのエントリを走査する O(n) の操作です。
+  ///
+  pub fn next_expiry(&self) -> Option<Instant> {
+    self
+      .tree
+      .iter()
+      .filter_map(|(_, (_, expires_at))| expires_at)
+      .min()
+  }
+
+  /// 期限切れでないエントリの Key-Value をキーの昇順で列挙します。
+  ///
+  pub fn iter(&self) -> impl Iterator<Item = (KEY, VALUE)> + '_ {
+    let now = Instant::now();
+    self
+      .tree
+      .iter()
+      .filter(move |(_, (_, expires_at))| !is_expired(*expires_at, now))
+      .map(|(key, (value, _))| (key, value))
+  }
+
+  /// 期限切れのエントリを削除し、有効期間の情報を取り除いたツリーを返します。
+  ///
+  pub fn into_btree(mut self) -> BTree<KEY, VALUE, S> {
+    self.purge_expired();
+    BTree::from_sorted_iter(self.tree.iter().map(|(key, (value, _))| (key, value)))
+  }
+
+  fn insert(&mut self, key: KEY, value: VALUE, expires_at: Option<Instant>) -> Option<VALUE> {
+    let old = self.tree.put(key, (value, expires_at))?;
+    self.live(old)
+  }
+
+  fn live(&self, (value, expires_at): (VALUE, Option<Instant>)) -> Option<VALUE> {
+    (!is_expired(expires_at, Instant::now())).then_some(value)
+  }
+}
+
+/// 有効期限 `expires_at` のエントリが時刻 `now` において期限切れであれば true を返します。
+///
+fn is_expired(expires_at: Option<Instant>, now: Instant) -> bool {
+  expires_at.is_some_and(|expires_at| expires_at <= now)
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// このツリーを、各エントリが `ttl` の有効期間を持つプロセス内キャッシュとして使用できる `TtlBTree` に変換
+  /// します。既存のエントリの有効期間はこの時点から始まります。
+  ///
+  pub fn with_ttl_eviction(self, ttl: Duration) -> TtlBTree<KEY, VALUE, S> {
+    let expires_at = Some(Instant::now() + ttl);
+    TtlBTree {
+      tree: BTree::from_sorted_iter(self.iter().map(|(key, value)| (key, (value, expires_at)))),
+      ttl,
+    }
+  }
+}
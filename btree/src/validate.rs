@@ -0,0 +1,280 @@
+use crate::{BTree, Node};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::hash::Hash;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+/// `BTree::validate_against_reference` で比較の基準として使用するマップの操作です。
+///
+pub trait Map<KEY, VALUE> {
+  fn get(&self, key: &KEY) -> Option<VALUE>;
+
+  fn len(&self) -> usize;
+
+  fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// すべての Key-Value を列挙します。列挙の順序は実装に依存します。
+  ///
+  fn iter(&self) -> Box<dyn Iterator<Item = (KEY, VALUE)> + '_>;
+}
+
+impl<KEY, VALUE> Map<KEY, VALUE> for BTreeMap<KEY, VALUE>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn get(&self, key: &KEY) -> Option<VALUE> {
+    BTreeMap::get(self, key).cloned()
+  }
+
+  fn len(&self) -> usize {
+    BTreeMap::len(self)
+  }
+
+  fn iter(&self) -> Box<dyn Iterator<Item = (KEY, VALUE)> + '_> {
+    Box::new(BTreeMap::iter(self).map(|(k, v)| (k.clone(), v.clone())))
+  }
+}
+
+#[cfg(feature = "std")]
+impl<KEY, VALUE> Map<KEY, VALUE> for HashMap<KEY, VALUE>
+where
+  KEY: Eq + Hash + Clone,
+  VALUE: Clone,
+{
+  fn get(&self, key: &KEY) -> Option<VALUE> {
+    HashMap::get(self, key).cloned()
+  }
+
+  fn len(&self) -> usize {
+    HashMap::len(self)
+  }
+
+  fn iter(&self) -> Box<dyn Iterator<Item = (KEY, VALUE)> + '_> {
+    Box::new(HashMap::iter(self).map(|(k, v)| (k.clone(), v.clone())))
+  }
+}
+
+impl<KEY, VALUE, const S: usize> Map<KEY, VALUE> for BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  fn get(&self, key: &KEY) -> Option<VALUE> {
+    BTree::get_cloned(self, key)
+  }
+
+  fn len(&self) -> usize {
+    self.size()
+  }
+
+  fn iter(&self) -> Box<dyn Iterator<Item = (KEY, VALUE)> + '_> {
+    Box::new(BTree::iter(self))
+  }
+}
+
+/// `BTree::validate_against_reference` による比較の結果です。各リストはキーの昇順に並びます。
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport<KEY, VALUE> {
+  /// ツリーにのみ存在するキーです。
+  pub extra_keys: Vec<KEY>,
+  /// 基準のマップにのみ存在するキーです。
+  pub missing_keys: Vec<KEY>,
+  /// 値が一致しないキーと、ツリーの値、基準のマップの値の組です。
+  pub value_mismatches: Vec<(KEY, VALUE, VALUE)>,
+}
+
+impl<KEY, VALUE> ValidationReport<KEY, VALUE> {
+  /// ツリーと基準のマップの内容が完全に一致した場合に true を返します。
+  ///
+  pub fn is_valid(&self) -> bool {
+    self.extra_keys.is_empty() && self.missing_keys.is_empty() && self.value_mismatches.is_empty()
+  }
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone + PartialEq,
+{
+  /// このツリーの内容を基準となるマップの実装と比較し、その差異を返します。開発時の差分テストで B-Tree の
+  /// 操作の正しさを確認するために使用します。
+  ///
+  pub fn validate_against_reference<M: Map<KEY, VALUE>>(
+    &self,
+    reference: &M,
+  ) -> ValidationReport<KEY, VALUE> {
+    let mut extra_keys = Vec::new();
+    let mut value_mismatches = Vec::new();
+    for (key, value) in self.iter() {
+      match reference.get(&key) {
+        None => extra_keys.push(key),
+        Some(expected) if expected != value => value_mismatches.push((key, value, expected)),
+        Some(_) => (),
+      }
+    }
+    let mut missing_keys = Vec::new();
+    if self.size() - extra_keys.len() != reference.len() {
+      missing_keys = reference
+        .iter()
+        .filter(|(key, _)| self.root.borrow().lookup_entry(key, |_| ()).is_none())
+        .map(|(key, _)| key)
+        .collect::<Vec<_>>();
+      missing_keys.sort();
+    }
+    ValidationReport {
+      extra_keys,
+      missing_keys,
+      value_mismatches,
+    }
+  }
+}
+
+/// `BTree::validate` が検出したツリーの構造の不整合です。`depth` はルートを 0 とするノードの深さです。
+///
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ValidationError {
+  /// ノード内のキー、または子ノードとその親のキーが昇順に並んでいません。`index` は親ノード内のキーの位置
+  /// です。
+  #[error("[{depth}] key[{index}] is out of order")]
+  KeyOutOfOrder { depth: usize, index: usize },
+
+  #[error("[{depth}] the number of keys {found} is not in the range {min} to {max}")]
+  FillFactorViolation {
+    depth: usize,
+    found: usize,
+    min: usize,
+    max: usize,
+  },
+
+  #[error("[{depth}] {pivots} pivots for {keys} keys")]
+  PivotCountMismatch {
+    depth: usize,
+    keys: usize,
+    pivots: usize,
+  },
+
+  /// 葉の深さが揃っていません。
+  #[error("leaf depth mismatch: expected {expected}, but found {found}")]
+  DepthInconsistency { expected: usize, found: usize },
+
+  #[error("[{depth}] the leaf has pivots")]
+  LeafHasPivots { depth: usize },
+
+  /// ノードが保持する部分木のエントリ数が実際のエントリ数と一致しません。
+  #[error("[{depth}] subtree size {stored} does not match the actual size {actual}")]
+  SubtreeSizeMismatch {
+    depth: usize,
+    stored: usize,
+    actual: usize,
+  },
+}
+
+impl<KEY, VALUE, const S: usize> BTree<KEY, VALUE, S>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  /// ツリーが B-Tree の構造の規則を満たしているかを検証します。ファイルからの読み込みや WAL による復旧の後に
+  /// ツリーが壊れていないことを確認するために使用します。最初に見つかった不整合を返します。
+  ///
+  pub fn validate(&self) -> core::result::Result<(), ValidationError> {
+    validate_node(&self.root.borrow(), true, 0)?;
+    validate_sizes(&self.root.borrow(), 0).map(|_| ())
+  }
+}
+
+/// `node` をルートとする部分木のすべてのノードについて、保持している部分木のエントリ数が実際のエントリ数と
+/// 一致することを検証し、実際のエントリ数を返します。
+///
+fn validate_sizes<KEY, VALUE, const S: usize>(
+  node: &Node<KEY, VALUE, S>,
+  depth: usize,
+) -> core::result::Result<usize, ValidationError>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  let mut actual = node.keys.len();
+  for pivot in node.pivots.iter() {
+    actual += validate_sizes(&pivot.borrow(), depth + 1)?;
+  }
+  if node.subtree_size != actual {
+    return Err(ValidationError::SubtreeSizeMismatch {
+      depth,
+      stored: node.subtree_size,
+      actual,
+    });
+  }
+  Ok(actual)
+}
+
+/// `node` をルートとする部分木を検証し、その葉の深さを返します。
+///
+fn validate_node<KEY, VALUE, const S: usize>(
+  node: &Node<KEY, VALUE, S>,
+  root: bool,
+  depth: usize,
+) -> core::result::Result<usize, ValidationError>
+where
+  KEY: Ord + Clone,
+  VALUE: Clone,
+{
+  let min = match (root, node.is_leaf) {
+    (true, true) => 0,
+    (true, false) => 1,
+    (false, _) => S,
+  };
+  let (found, max) = (node.keys.len(), 2 * S);
+  if found < min || found > max {
+    return Err(ValidationError::FillFactorViolation {
+      depth,
+      found,
+      min,
+      max,
+    });
+  }
+  if let Some(index) = (1..found).find(|i| node.keys[i - 1].key >= node.keys[*i].key) {
+    return Err(ValidationError::KeyOutOfOrder { depth, index });
+  }
+  if node.is_leaf {
+    if !node.pivots.is_empty() {
+      return Err(ValidationError::LeafHasPivots { depth });
+    }
+    return Ok(depth);
+  }
+
+  if node.pivots.len() != found + 1 {
+    return Err(ValidationError::PivotCountMismatch {
+      depth,
+      keys: found,
+      pivots: node.pivots.len(),
+    });
+  }
+  for (index, kv) in node.keys.iter().enumerate() {
+    let left = node.pivots[index].borrow();
+    let right = node.pivots[index + 1].borrow();
+    let left_ok = left.keys.last().is_none_or(|last| last.key < kv.key);
+    let right_ok = right.keys.first().is_none_or(|first| first.key > kv.key);
+    if !left_ok || !right_ok {
+      return Err(ValidationError::KeyOutOfOrder { depth, index });
+    }
+  }
+  let mut leaf_depth = None;
+  for pivot in node.pivots.iter() {
+    let found = validate_node(&pivot.borrow(), false, depth + 1)?;
+    match leaf_depth {
+      Some(expected) if expected != found => {
+        return Err(ValidationError::DepthInconsistency { expected, found });
+      }
+      _ => leaf_depth = Some(found),
+    }
+  }
+  Ok(leaf_depth.unwrap_or(depth))
+}